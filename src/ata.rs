@@ -1,105 +1,357 @@
+use std::cell::Cell;
 use std::ffi::CString;
 
-const SG_IO: u64 = 0x2285;
-const SG_DXFER_NONE: i32 = -1;
-const SG_DXFER_TO_DEV: i32 = -2;
-const SG_DXFER_FROM_DEV: i32 = -3;
+use crate::errno::errno_to_str;
+use crate::hexdump::hexdump;
+use crate::scsi::{self, SgIoHdr, SENSE_BUF_LEN, SG_DXFER_FROM_DEV, SG_DXFER_NONE, SG_DXFER_TO_DEV};
+
 const ATA_PT16_OPCODE: u8 = 0x85;
-const ATA_PT16_CDB_LEN: u8 = 16;
-const SENSE_BUF_LEN: u8 = 32;
-const TIMEOUT_MS: u32 = 10_000;
+const ATA_PT12_OPCODE: u8 = 0xA1;
+const JMICRON_OPCODE: u8 = 0xDF;
+const CYPRESS_OPCODE: u8 = 0x24;
+const USB_VENDOR_JMICRON: u16 = 0x152d;
+const USB_VENDOR_CYPRESS: u16 = 0x04b4;
+pub const DEFAULT_TIMEOUT_MS: u32 = 10_000;
 
 const PROTO_NON_DATA: u8 = 3;
 const PROTO_PIO_DATA_IN: u8 = 4;
 const PROTO_PIO_DATA_OUT: u8 = 5;
 const PROTO_DMA: u8 = 6;
 
+const ATA_CMD_IDENTIFY: u8 = 0xEC;
+const SCSI_INQUIRY: u8 = 0x12;
+
 // CDB byte 2 values: chk_cond=1(bit5), t_dir(bit3), byte_block=1(bit2), t_length=2(bits1:0)
 const CDB2_READ: u8 = 0x2E;
 const CDB2_WRITE: u8 = 0x26;
 const CDB2_NON_DATA: u8 = 0x20;
 
-#[repr(C)]
-struct SgIoHdr {
-    interface_id: i32,
-    dxfer_direction: i32,
-    cmd_len: u8,
-    mx_sb_len: u8,
-    iovec_count: u16,
-    dxfer_len: u32,
-    dxferp: *mut u8,
-    cmdp: *const u8,
-    sbp: *mut u8,
-    timeout: u32,
-    flags: u32,
-    pack_id: i32,
-    usr_ptr: *mut u8,
-    status: u8,
-    masked_status: u8,
-    msg_status: u8,
-    sb_len_wr: u8,
-    host_status: u16,
-    driver_status: u16,
-    resid: i32,
-    duration: u32,
-    info: u32,
+/// How ATA commands reach the drive. Commands are built as ATA PASS-THROUGH(16) CDBs
+/// and translated for the other methods when sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtaMethod {
+    /// SAT ATA PASS-THROUGH(16), 0x85: libata, SAS HBAs, current USB bridges
+    Pt16,
+    /// SAT ATA PASS-THROUGH(12), 0xA1: older USB bridges and HBAs; no 48-bit commands
+    Pt12,
+    /// JMicron JM20329/JM20336 vendor tunnel, 0xDF
+    JMicron,
+    /// Cypress CY7C68300 ATACB, 0x24
+    Cypress,
+}
+
+pub const METHOD_NAMES: &str = "pt16, pt12, jmicron, cypress";
+
+impl AtaMethod {
+    pub fn name(self) -> &'static str {
+        match self {
+            AtaMethod::Pt16 => "pt16",
+            AtaMethod::Pt12 => "pt12",
+            AtaMethod::JMicron => "jmicron",
+            AtaMethod::Cypress => "cypress",
+        }
+    }
+
+    pub fn display(self) -> &'static str {
+        match self {
+            AtaMethod::Pt16 => "ATA PASS-THROUGH(16)",
+            AtaMethod::Pt12 => "ATA PASS-THROUGH(12)",
+            AtaMethod::JMicron => "JMicron 0xDF tunnel",
+            AtaMethod::Cypress => "Cypress ATACB 0x24 tunnel",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [AtaMethod::Pt16, AtaMethod::Pt12, AtaMethod::JMicron, AtaMethod::Cypress]
+            .into_iter()
+            .find(|m| m.name() == name)
+    }
+
+    /// Methods to try, in order. The vendor tunnels reuse opcodes that mean something
+    /// else to other devices, so they are only tried on their vendor's USB bridges.
+    fn candidates(usb_vendor: Option<u16>) -> Vec<AtaMethod> {
+        let mut methods = vec![AtaMethod::Pt16, AtaMethod::Pt12];
+        match usb_vendor {
+            Some(USB_VENDOR_JMICRON) => methods.push(AtaMethod::JMicron),
+            Some(USB_VENDOR_CYPRESS) => methods.push(AtaMethod::Cypress),
+            _ => {}
+        }
+        methods
+    }
+}
+
+/// ATA registers returned by the drive, from the sense data of an ATA PASS-THROUGH
+/// command with CK_COND set. Some vendor commands return data here instead of (or as
+/// well as) in a data phase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AtaRegisters {
+    pub error: u8,
+    pub status: u8,
+    pub device: u8,
+    /// Upper byte only valid with `extend`
+    pub count: u16,
+    /// 48-bit LBA; bits 47:24 only valid with `extend`
+    pub lba: u64,
+    pub extend: bool,
+}
+
+const ATA_STATUS_ERR: u8 = 0x01;
+const ATA_STATUS_DF: u8 = 0x20;
+const ATA_ERROR_ABRT: u8 = 0x04;
+
+impl AtaRegisters {
+    /// From the ATA Status Return descriptor (type 0x09) of descriptor-format sense, or
+    /// the INFORMATION and COMMAND-SPECIFIC INFORMATION fields of fixed-format sense. The
+    /// fixed-format fields only carry registers with ASC/ASCQ 00/1D ("ATA pass through
+    /// information available"); for any other sense they mean something else.
+    fn from_sense(sense: &scsi::Sense) -> Option<Self> {
+        if let Some(d) = sense.descriptor(0x09) {
+            let d: &[u8; 14] = d.try_into().ok()?;
+            return Some(AtaRegisters {
+                extend: d[2] & 1 != 0,
+                error: d[3],
+                count: u16::from_be_bytes([d[4], d[5]]),
+                lba: u64::from_be_bytes([0, 0, d[10], d[8], d[6], d[11], d[9], d[7]]),
+                device: d[12],
+                status: d[13],
+            });
+        }
+        if (sense.asc, sense.ascq) != (0x00, 0x1D) {
+            return None;
+        }
+        let [error, status, device, count] = sense.information()?;
+        let [flags, lba_low, lba_mid, lba_high] = sense.command_specific()?;
+        Some(AtaRegisters {
+            extend: flags & 0x80 != 0,
+            error,
+            count: count as u16,
+            lba: u64::from_le_bytes([lba_low, lba_mid, lba_high, 0, 0, 0, 0, 0]),
+            device,
+            status,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "status=0x{:02x} error=0x{:02x} count=0x{:04x} lba=0x{:012x} dev=0x{:02x}",
+            self.status, self.error, self.count, self.lba, self.device
+        )
+    }
 }
 
-impl SgIoHdr {
-    fn zeroed() -> Self {
-        SgIoHdr {
-            interface_id: 0,
-            dxfer_direction: 0,
-            cmd_len: 0,
-            mx_sb_len: 0,
-            iovec_count: 0,
-            dxfer_len: 0,
-            dxferp: std::ptr::null_mut(),
-            cmdp: std::ptr::null(),
-            sbp: std::ptr::null_mut(),
-            timeout: 0,
-            flags: 0,
-            pack_id: 0,
-            usr_ptr: std::ptr::null_mut(),
-            status: 0,
-            masked_status: 0,
-            msg_status: 0,
-            sb_len_wr: 0,
-            host_status: 0,
-            driver_status: 0,
-            resid: 0,
-            duration: 0,
-            info: 0,
+/// Why an ATA command failed. Converts into the String errors the readers use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AtaError {
+    /// Not sent: interrupted, no working pass-through method, or a command the method
+    /// can't express
+    NotSent(String),
+    TimedOut { command: u8, ms: u32 },
+    Ioctl { command: u8, errno: i32 },
+    Transport { command: u8, host_status: u16, driver_status: u16 },
+    /// ILLEGAL REQUEST (INVALID FIELD IN CDB, INVALID COMMAND OPERATION CODE): the SCSI
+    /// layer or a bridge refused the CDB, so the drive never saw the command
+    IllegalRequest { command: u8, asc: u8, ascq: u8 },
+    /// The drive completed the command with ERR and ABRT: the usual answer to a vendor
+    /// command it doesn't implement
+    Aborted { command: u8, regs: AtaRegisters },
+    /// ERR without ABRT, or device fault
+    DeviceError { command: u8, regs: AtaRegisters },
+    /// Other sense data without returned registers (NOT READY, UNIT ATTENTION, ...)
+    Sense { command: u8, key: u8, asc: u8, ascq: u8 },
+}
+
+impl std::fmt::Display for AtaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AtaError::NotSent(e) => write!(f, "{}", e),
+            AtaError::TimedOut { command, ms } => {
+                write!(f, "ata command 0x{:02x} timed out after {} ms", command, ms)
+            }
+            AtaError::Ioctl { command, errno } => write!(
+                f,
+                "sg_io ioctl failed: {} (errno {}, command 0x{:02x})",
+                errno_to_str(*errno),
+                errno,
+                command
+            ),
+            AtaError::Transport { command, host_status, driver_status } => write!(
+                f,
+                "sg_io transport error: host_status=0x{:04x}, driver_status=0x{:04x}, command 0x{:02x}",
+                host_status, driver_status, command
+            ),
+            AtaError::IllegalRequest { command, asc, ascq } => write!(
+                f,
+                "ata command 0x{:02x} rejected before reaching the drive: {}",
+                command,
+                scsi::describe_sense(scsi::SENSE_ILLEGAL_REQUEST, *asc, *ascq)
+            ),
+            AtaError::Aborted { command, regs } => {
+                write!(f, "ata command 0x{:02x} aborted by device: {}", command, regs.describe())
+            }
+            AtaError::DeviceError { command, regs } => {
+                write!(f, "ata command 0x{:02x} failed: {}", command, regs.describe())
+            }
+            AtaError::Sense { command, key, asc, ascq } => write!(
+                f,
+                "ata command 0x{:02x} failed: {}",
+                command,
+                scsi::describe_sense(*key, *asc, *ascq)
+            ),
         }
     }
 }
 
+impl From<AtaError> for String {
+    fn from(e: AtaError) -> String {
+        e.to_string()
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub struct AtaDevice {
     fd: i32,
+    /// The node actually opened: the disk, or its /dev/sgN or /dev/bsg/* node
+    path: String,
+    /// bsg nodes take the sg_io_v4 header
+    bsg: bool,
+    /// None until the first command picks one (see `detect_method`)
+    method: Cell<Option<AtaMethod>>,
+    registers: Cell<Option<AtaRegisters>>,
+    timeout_ms: Cell<u32>,
+    timeouts: Cell<u32>,
+    dry_run: Cell<bool>,
+    planned: Cell<u32>,
 }
 
 #[allow(clippy::too_many_arguments)]
 impl AtaDevice {
+    /// Opens a disk (/dev/sdX), a SCSI generic node (/dev/sgN) or a bsg node
+    /// (/dev/bsg/H:C:T:L). A disk whose block node can't be opened (no medium, locked
+    /// drive) is opened through its sg or bsg node instead.
     pub fn open(path: &str) -> Result<Self, String> {
-        let c_path =
-            CString::new(path).map_err(|e| format!("invalid device path '{}': {}", path, e))?;
-        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_RDWR) };
-        if fd < 0 {
-            let errno = unsafe { *libc::__errno_location() };
-            return Err(format!(
-                "failed to open '{}': {} (errno {})",
-                path,
-                errno_to_str(errno),
-                errno
-            ));
+        let (fd, node) = match open_node(path) {
+            Ok(fd) => (fd, path.to_string()),
+            Err(e) => [crate::sysfs::sg_node(path), crate::sysfs::bsg_node(path)]
+                .into_iter()
+                .flatten()
+                .find_map(|node| open_node(&node).ok().map(|fd| (fd, node)))
+                .ok_or(e)?,
+        };
+        Ok(AtaDevice {
+            fd,
+            bsg: node.starts_with("/dev/bsg/"),
+            path: node,
+            method: Cell::new(None),
+            registers: Cell::new(None),
+            timeout_ms: Cell::new(DEFAULT_TIMEOUT_MS),
+            timeouts: Cell::new(0),
+            dry_run: Cell::new(false),
+            planned: Cell::new(0),
+        })
+    }
+
+    pub fn set_timeout(&self, timeout_ms: u32) {
+        self.timeout_ms.set(timeout_ms);
+    }
+
+    /// The node commands go to, which differs from the opened path after a fallback.
+    pub fn node(&self) -> &str {
+        &self.path
+    }
+
+    /// Skips method detection, e.g. for a bridge that accepts a CDB but ignores it.
+    pub fn set_method(&self, method: AtaMethod) {
+        self.method.set(Some(method));
+    }
+
+    /// The method in use, detecting it first if no command has been sent yet.
+    pub fn method(&self) -> Result<AtaMethod, AtaError> {
+        match self.method.get() {
+            Some(m) => Ok(m),
+            None => self.detect_method(),
+        }
+    }
+
+    /// Sends IDENTIFY DEVICE with each candidate method and keeps the first one that
+    /// returns data. Bridges that don't know a CDB either reject it or complete it
+    /// without transferring anything, so an all-zero response counts as a failure.
+    fn detect_method(&self) -> Result<AtaMethod, AtaError> {
+        let usb_vendor = crate::sysfs::usb_id(&self.path).map(|(vid, _)| vid);
+        let mut failures = Vec::new();
+        for method in AtaMethod::candidates(usb_vendor) {
+            self.method.set(Some(method));
+            let mut buf = [0u8; 512];
+            match self.ata_read(ATA_CMD_IDENTIFY, 0, 1, 0, 0, 0, 0xE0, &mut buf) {
+                Ok(()) if buf.iter().any(|&b| b != 0) => return Ok(method),
+                Ok(()) => failures.push(format!("{}: empty response", method.name())),
+                Err(e) => failures.push(format!("{}: {}", method.name(), e)),
+            }
+            if crate::interrupt::check().is_err() {
+                break;
+            }
         }
-        Ok(AtaDevice { fd })
+        self.method.set(None);
+        Err(AtaError::NotSent(format!(
+            "no ATA pass-through method works ({})",
+            failures.join("; ")
+        )))
+    }
+
+    /// In dry-run mode every command except IDENTIFY DEVICE is printed instead of sent,
+    /// and data-in commands "return" a zeroed buffer.
+    pub fn set_dry_run(&self, dry_run: bool) {
+        self.dry_run.set(dry_run);
+    }
+
+    /// Number of commands printed (not sent) in dry-run mode.
+    pub fn planned(&self) -> u32 {
+        self.planned.get()
+    }
+
+    /// Number of commands that timed out since the device was opened.
+    pub fn timeouts(&self) -> u32 {
+        self.timeouts.get()
+    }
+
+    /// Registers returned by the last command, when its sense data carried them (ATA
+    /// PASS-THROUGH methods only).
+    pub fn registers(&self) -> Option<AtaRegisters> {
+        self.registers.get()
+    }
+
+    /// SCSI device reset (SG_SCSI_RESET), which makes libata reset the link and the drive.
+    pub fn reset_device(&self) -> Result<(), String> {
+        scsi::reset_device(self.fd)
     }
 
-    pub fn ata_identify(&self) -> Result<[u8; 512], String> {
+    pub fn ata_identify(&self) -> Result<[u8; 512], AtaError> {
         let mut buf = [0u8; 512];
-        self.ata_read(0xEC, 0, 1, 0, 0, 0, 0xE0, &mut buf)?;
+        self.ata_read(ATA_CMD_IDENTIFY, 0, 1, 0, 0, 0, 0xE0, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Standard SCSI INQUIRY, answered by the SCSI layer itself (libata's SAT, a USB
+    /// bridge, a SAS disk). Not a vendor command, so it is also sent in dry-run mode.
+    pub fn inquiry(&self) -> Result<[u8; 96], String> {
+        let mut buf = [0u8; 96];
+        let cdb = [SCSI_INQUIRY, 0, 0, 0, buf.len() as u8, 0];
+        let mut sense = [0u8; SENSE_BUF_LEN as usize];
+        let (hdr, errno) = self.send(
+            &cdb,
+            SG_DXFER_FROM_DEV,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut sense,
+        );
+        if errno != 0 {
+            return Err(format!("inquiry failed: {} (errno {})", errno_to_str(errno), errno));
+        }
+        if hdr.status != 0 || hdr.host_status != 0 || hdr.driver_status & 0x0F != 0 {
+            return Err(format!(
+                "inquiry failed: scsi status 0x{:02x}, host 0x{:04x}, driver 0x{:04x}",
+                hdr.status, hdr.host_status, hdr.driver_status
+            ));
+        }
         Ok(buf)
     }
 
@@ -113,7 +365,7 @@ impl AtaDevice {
         lba_high: u8,
         device: u8,
         buf: &mut [u8],
-    ) -> Result<(), String> {
+    ) -> Result<(), AtaError> {
         let cdb = build_cdb(
             PROTO_PIO_DATA_IN,
             CDB2_READ,
@@ -138,7 +390,7 @@ impl AtaDevice {
         lba_high: u8,
         device: u8,
         buf: &mut [u8],
-    ) -> Result<(), String> {
+    ) -> Result<(), AtaError> {
         let cdb = build_cdb(
             PROTO_DMA,
             CDB2_READ,
@@ -163,7 +415,7 @@ impl AtaDevice {
         lba_high: u8,
         device: u8,
         buf: &[u8],
-    ) -> Result<(), String> {
+    ) -> Result<(), AtaError> {
         let cdb = build_cdb(
             PROTO_PIO_DATA_OUT,
             CDB2_WRITE,
@@ -187,7 +439,7 @@ impl AtaDevice {
         lba_mid: u8,
         lba_high: u8,
         device: u8,
-    ) -> Result<(), String> {
+    ) -> Result<(), AtaError> {
         let cdb = build_cdb(
             PROTO_NON_DATA,
             CDB2_NON_DATA,
@@ -217,7 +469,7 @@ impl AtaDevice {
         prev_lba_mid: u8,
         prev_lba_high: u8,
         buf: &mut [u8],
-    ) -> Result<(), String> {
+    ) -> Result<(), AtaError> {
         let cdb = build_cdb_ext(
             PROTO_PIO_DATA_IN,
             CDB2_READ,
@@ -237,6 +489,41 @@ impl AtaDevice {
         self.sg_io(&cdb, SG_DXFER_FROM_DEV, buf.as_mut_ptr(), buf.len(), command)
     }
 
+    pub fn ata_write_ext(
+        &self,
+        command: u8,
+        features: u8,
+        count: u8,
+        lba_low: u8,
+        lba_mid: u8,
+        lba_high: u8,
+        device: u8,
+        prev_features: u8,
+        prev_count: u8,
+        prev_lba_low: u8,
+        prev_lba_mid: u8,
+        prev_lba_high: u8,
+        buf: &[u8],
+    ) -> Result<(), AtaError> {
+        let cdb = build_cdb_ext(
+            PROTO_PIO_DATA_OUT,
+            CDB2_WRITE,
+            command,
+            features,
+            count,
+            lba_low,
+            lba_mid,
+            lba_high,
+            device,
+            prev_features,
+            prev_count,
+            prev_lba_low,
+            prev_lba_mid,
+            prev_lba_high,
+        );
+        self.sg_io(&cdb, SG_DXFER_TO_DEV, buf.as_ptr() as *mut u8, buf.len(), command)
+    }
+
     pub fn ata_no_data_ext(
         &self,
         command: u8,
@@ -251,7 +538,7 @@ impl AtaDevice {
         prev_lba_low: u8,
         prev_lba_mid: u8,
         prev_lba_high: u8,
-    ) -> Result<(), String> {
+    ) -> Result<(), AtaError> {
         let cdb = build_cdb_ext(
             PROTO_NON_DATA,
             CDB2_NON_DATA,
@@ -278,53 +565,162 @@ impl AtaDevice {
         dxferp: *mut u8,
         dxfer_len: usize,
         command: u8,
-    ) -> Result<(), String> {
+    ) -> Result<(), AtaError> {
+        crate::interrupt::check().map_err(AtaError::NotSent)?;
+        self.registers.set(None);
+        let method = self.method()?;
+        let wire = translate_cdb(method, cdb, direction, dxfer_len)?;
+        let desc = match method {
+            AtaMethod::Pt16 => describe_taskfile(cdb, direction, dxfer_len),
+            _ => format!("{} via {}", describe_taskfile(cdb, direction, dxfer_len), method.name()),
+        };
+        if self.dry_run.get() && command != ATA_CMD_IDENTIFY {
+            self.planned.set(self.planned.get() + 1);
+            println!("[dry-run] {}", desc);
+            if dxfer_len > 0 {
+                let data = unsafe { std::slice::from_raw_parts_mut(dxferp, dxfer_len) };
+                if direction == SG_DXFER_TO_DEV {
+                    print!("{}", hexdump(data, "    "));
+                } else {
+                    data.fill(0);
+                }
+            }
+            return Ok(());
+        }
         let mut sense = [0u8; SENSE_BUF_LEN as usize];
-        let mut hdr = SgIoHdr::zeroed();
-        hdr.interface_id = b'S' as i32;
-        hdr.dxfer_direction = direction;
-        hdr.cmd_len = ATA_PT16_CDB_LEN;
-        hdr.mx_sb_len = SENSE_BUF_LEN;
-        hdr.dxfer_len = dxfer_len as u32;
-        hdr.dxferp = dxferp;
-        hdr.cmdp = cdb.as_ptr();
-        hdr.sbp = sense.as_mut_ptr();
-        hdr.timeout = TIMEOUT_MS;
-
-        let ret = unsafe { libc::ioctl(self.fd, SG_IO, &mut hdr as *mut SgIoHdr) };
+        let start = std::time::Instant::now();
+        let (hdr, errno) =
+            self.send(&wire, direction, dxferp, dxfer_len, &mut sense);
+        let elapsed = start.elapsed();
+        let ret = if errno != 0 { -1 } else { 0 };
+
+        let outcome = self.check_completion(&hdr, &sense, ret, errno, command, method);
+        let error = outcome.as_ref().err().map(|e| e.to_string());
+
+        let data = if dxfer_len > 0 {
+            Some(unsafe { std::slice::from_raw_parts(dxferp as *const u8, dxfer_len) })
+        } else {
+            None
+        };
+        crate::trace::record(&crate::trace::CommandRecord {
+            tag: format!("ata-{:02x}", command),
+            desc,
+            vendor: command != ATA_CMD_IDENTIFY,
+            duration: elapsed,
+            status: match (ret, self.registers.get()) {
+                (r, _) if r < 0 => format!("errno {}", errno),
+                (_, Some(regs)) => format!("{}, {}", hdr.describe_status(), regs.describe()),
+                (_, None) => hdr.describe_status(),
+            },
+            error: error.as_deref(),
+            data_out: data.filter(|_| direction == SG_DXFER_TO_DEV),
+            data_in: data.filter(|_| direction == SG_DXFER_FROM_DEV && outcome.is_ok()),
+            sense: Some(&sense[..(hdr.sb_len_wr as usize).min(sense.len())]),
+        });
+        outcome
+    }
+
+    fn send(
+        &self,
+        cdb: &[u8],
+        direction: i32,
+        dxferp: *mut u8,
+        dxfer_len: usize,
+        sense: &mut [u8; SENSE_BUF_LEN as usize],
+    ) -> (SgIoHdr, i32) {
+        let send = if self.bsg { scsi::send_bsg } else { scsi::send };
+        send(self.fd, cdb, direction, dxferp, dxfer_len, self.timeout_ms.get(), sense)
+    }
+
+    fn check_completion(
+        &self,
+        hdr: &SgIoHdr,
+        sense: &[u8; SENSE_BUF_LEN as usize],
+        ret: i32,
+        errno: i32,
+        command: u8,
+        method: AtaMethod,
+    ) -> Result<(), AtaError> {
+        if errno == libc::ETIMEDOUT || (ret == 0 && hdr.timed_out()) {
+            self.timeouts.set(self.timeouts.get() + 1);
+            return Err(AtaError::TimedOut {
+                command,
+                ms: hdr.timeout,
+            });
+        }
         if ret < 0 {
-            let errno = unsafe { *libc::__errno_location() };
-            return Err(format!(
-                "sg_io ioctl failed: {} (errno {}, command 0x{:02x})",
-                errno_to_str(errno),
-                errno,
-                command
-            ));
+            return Err(AtaError::Ioctl { command, errno });
         }
 
         // DRIVER_SENSE (0x08) is expected when CK_COND=1 is set in CDB byte 2
         if hdr.host_status != 0 || (hdr.driver_status & !0x08) != 0 {
-            return Err(format!(
-                "sg_io transport error: host_status=0x{:04x}, driver_status=0x{:04x}, command 0x{:02x}",
-                hdr.host_status, hdr.driver_status, command
-            ));
+            return Err(AtaError::Transport {
+                command,
+                host_status: hdr.host_status,
+                driver_status: hdr.driver_status,
+            });
         }
 
-        // Parse ATA Status Return descriptor (type 0x09) from descriptor format sense data.
-        // Descriptor layout at sense[8]: [type=0x09][len=0x0C][extend][error]...[device][status]
-        if hdr.sb_len_wr >= 22 && sense[0] == 0x72 && sense[8] == 0x09 && sense[9] == 0x0C {
-            let ata_status = sense[21];
-            let ata_error = sense[11];
-            if ata_status & 0x01 != 0 {
-                return Err(format!(
-                    "ata command 0x{:02x} failed: status=0x{:02x}, error=0x{:02x}",
-                    command, ata_status, ata_error
-                ));
-            }
+        let Some(sense) = scsi::decode_sense(&sense[..(hdr.sb_len_wr as usize).min(sense.len())])
+        else {
+            return Ok(());
+        };
+        // The vendor tunnels' sense data is not SAT-formatted
+        let regs = match method {
+            AtaMethod::Pt16 | AtaMethod::Pt12 => AtaRegisters::from_sense(&sense),
+            AtaMethod::JMicron | AtaMethod::Cypress => None,
+        };
+        self.registers.set(regs);
+        sense_result(command, &sense, regs)
+    }
+}
+
+/// Outcome of a pass-through command that returned sense data, with the registers
+/// decoded from it (None when the sense carries none).
+fn sense_result(command: u8, sense: &scsi::Sense, regs: Option<AtaRegisters>) -> Result<(), AtaError> {
+    if let Some(regs) = regs
+        && regs.status & (ATA_STATUS_ERR | ATA_STATUS_DF) != 0
+    {
+        if regs.status & ATA_STATUS_DF == 0 && regs.error & ATA_ERROR_ABRT != 0 {
+            return Err(AtaError::Aborted { command, regs });
         }
+        return Err(AtaError::DeviceError { command, regs });
+    }
+    match sense.key {
+        scsi::SENSE_ILLEGAL_REQUEST => Err(AtaError::IllegalRequest {
+            command,
+            asc: sense.asc,
+            ascq: sense.ascq,
+        }),
+        // CK_COND=1 makes every completion a CHECK CONDITION: NO SENSE or RECOVERED
+        // ERROR with "ATA pass through information available" is a success
+        0x0 | 0x1 => Ok(()),
+        // Any other key failed the command whatever the registers say (ABORTED COMMAND
+        // from a bridge, NOT READY, MEDIUM ERROR, UNIT ATTENTION after a reset)
+        _ => Err(AtaError::Sense {
+            command,
+            key: sense.key,
+            asc: sense.asc,
+            ascq: sense.ascq,
+        }),
+    }
+}
 
-        Ok(())
+/// O_RDWR: SG_IO on a read-only fd is limited to the commands the kernel deems safe.
+fn open_node(path: &str) -> Result<i32, String> {
+    let c_path =
+        CString::new(path).map_err(|e| format!("invalid device path '{}': {}", path, e))?;
+    let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_RDWR) };
+    if fd < 0 {
+        let errno = unsafe { *libc::__errno_location() };
+        return Err(format!(
+            "failed to open '{}': {} (errno {})",
+            path,
+            errno_to_str(errno),
+            errno
+        ));
     }
+    Ok(fd)
 }
 
 impl Drop for AtaDevice {
@@ -335,6 +731,103 @@ impl Drop for AtaDevice {
     }
 }
 
+/// Decodes an ATA PASS-THROUGH(16) CDB back into taskfile registers for display.
+pub fn describe_taskfile(cdb: &[u8; 16], direction: i32, len: usize) -> String {
+    let protocol = match (cdb[1] >> 1) & 0x0F {
+        PROTO_NON_DATA => "non-data",
+        PROTO_PIO_DATA_IN => "pio-in",
+        PROTO_PIO_DATA_OUT => "pio-out",
+        PROTO_DMA => "dma",
+        _ => "other",
+    };
+    let dir = match direction {
+        SG_DXFER_TO_DEV => "to-device",
+        SG_DXFER_FROM_DEV => "from-device",
+        _ => "none",
+    };
+    if cdb[1] & 1 != 0 {
+        format!(
+            "ata pt16 cmd=0x{:02x} feat=0x{:02x}{:02x} count=0x{:02x}{:02x} \
+             lba=0x{:02x}{:02x}{:02x}{:02x}{:02x}{:02x} dev=0x{:02x} proto={} ext=1 dir={} len={}",
+            cdb[14], cdb[3], cdb[4], cdb[5], cdb[6], cdb[11], cdb[9], cdb[7], cdb[12], cdb[10],
+            cdb[8], cdb[13], protocol, dir, len
+        )
+    } else {
+        format!(
+            "ata pt16 cmd=0x{:02x} feat=0x{:02x} count=0x{:02x} lba=0x{:02x}{:02x}{:02x} \
+             dev=0x{:02x} proto={} dir={} len={}",
+            cdb[14], cdb[4], cdb[6], cdb[12], cdb[10], cdb[8], cdb[13], protocol, dir, len
+        )
+    }
+}
+
+/// Rewrites an ATA PASS-THROUGH(16) CDB for `method`. The 12-byte and vendor CDBs
+/// have no room for the previous-register bytes, so 48-bit commands can't be sent.
+fn translate_cdb(method: AtaMethod, cdb: &[u8; 16], direction: i32, len: usize) -> Result<Vec<u8>, AtaError> {
+    let (features, count, lba_low, lba_mid, lba_high) = (cdb[4], cdb[6], cdb[8], cdb[10], cdb[12]);
+    let (device, command) = (cdb[13], cdb[14]);
+    if method != AtaMethod::Pt16 && cdb[1] & 1 != 0 {
+        return Err(AtaError::NotSent(format!(
+            "ata command 0x{:02x} is a 48-bit command, which {} cannot carry",
+            command,
+            method.display()
+        )));
+    }
+    Ok(match method {
+        AtaMethod::Pt16 => cdb.to_vec(),
+        AtaMethod::Pt12 => vec![
+            ATA_PT12_OPCODE,
+            cdb[1],
+            cdb[2],
+            features,
+            count,
+            lba_low,
+            lba_mid,
+            lba_high,
+            device,
+            command,
+            0,
+            0,
+        ],
+        AtaMethod::JMicron => {
+            let [len_hi, len_lo] = (len as u16).to_be_bytes();
+            vec![
+                JMICRON_OPCODE,
+                // 0x10: data-in or no data, 0x00: data-out
+                if direction == SG_DXFER_TO_DEV { 0x00 } else { 0x10 },
+                0,
+                len_hi,
+                len_lo,
+                features,
+                count,
+                lba_low,
+                lba_mid,
+                lba_high,
+                device | 0xA0,
+                command,
+            ]
+        }
+        AtaMethod::Cypress => {
+            let mut c = vec![0u8; 16];
+            c[0] = CYPRESS_OPCODE;
+            c[1] = CYPRESS_OPCODE;
+            // IdentifyPacketDevice flag: the bridge handles the IDENTIFY data phase itself
+            c[2] = if command == ATA_CMD_IDENTIFY { 0x80 } else { 0 };
+            // Register select: features, count, LBA low/mid/high and command
+            c[3] = 0xBE;
+            c[4] = 1;
+            c[6] = features;
+            c[7] = count;
+            c[8] = lba_low;
+            c[9] = lba_mid;
+            c[10] = lba_high;
+            c[11] = device;
+            c[12] = command;
+            c
+        }
+    })
+}
+
 #[allow(clippy::too_many_arguments)]
 fn build_cdb(
     protocol: u8,
@@ -408,16 +901,35 @@ pub struct AtaIdentify {
     pub model: String,
     pub serial: String,
     pub firmware: String,
+    pub wwn: Option<String>,
+    /// Word 217: 1 = non-rotating media, 0x0401-0xFFFE = rpm, 0 = not reported
+    pub rotation_rate: u16,
 }
 
 pub fn parse_ata_identify(data: &[u8; 512]) -> AtaIdentify {
     let serial = ata_string_trim(&data[20..40]);
     let firmware = ata_string_trim(&data[46..54]);
     let model = ata_string_trim(&data[54..94]);
+    let wwn = parse_wwn(data);
+    let rotation_rate = u16::from_le_bytes([data[434], data[435]]);
     AtaIdentify {
         model,
         serial,
         firmware,
+        wwn,
+        rotation_rate,
+    }
+}
+
+/// World wide name from words 108-111 (word 108 holds the most significant bits).
+fn parse_wwn(data: &[u8; 512]) -> Option<String> {
+    let wwn = data[216..224]
+        .chunks_exact(2)
+        .fold(0u64, |acc, w| (acc << 16) | u16::from_le_bytes([w[0], w[1]]) as u64);
+    if wwn == 0 {
+        None
+    } else {
+        Some(format!("{:016x}", wwn))
     }
 }
 
@@ -436,17 +948,74 @@ fn ata_string_trim(raw: &[u8]) -> String {
     s.trim().to_string()
 }
 
-fn errno_to_str(errno: i32) -> &'static str {
-    match errno {
-        libc::EACCES => "permission denied",
-        libc::ENOENT => "no such file or directory",
-        libc::EBUSY => "device busy",
-        libc::EIO => "i/o error",
-        libc::EINVAL => "invalid argument",
-        libc::ENOTTY => "inappropriate ioctl for device",
-        libc::ENOMEM => "out of memory",
-        libc::EPERM => "operation not permitted",
-        libc::ENODEV => "no such device",
-        _ => "unknown error",
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate_cdb() {
+        let identify = build_cdb(PROTO_PIO_DATA_IN, CDB2_READ, ATA_CMD_IDENTIFY, 0, 1, 0, 0, 0, 0xE0);
+        let pt12 = translate_cdb(AtaMethod::Pt12, &identify, SG_DXFER_FROM_DEV, 512).unwrap();
+        assert_eq!(pt12, [0xA1, 0x08, 0x2E, 0, 1, 0, 0, 0, 0xE0, 0xEC, 0, 0]);
+        let jm = translate_cdb(AtaMethod::JMicron, &identify, SG_DXFER_FROM_DEV, 512).unwrap();
+        assert_eq!(jm, [0xDF, 0x10, 0, 0x02, 0x00, 0, 1, 0, 0, 0, 0xE0, 0xEC]);
+        let cy = translate_cdb(AtaMethod::Cypress, &identify, SG_DXFER_FROM_DEV, 512).unwrap();
+        assert_eq!((cy[0], cy[2], cy[7], cy[12]), (0x24, 0x80, 1, 0xEC));
+
+        let ext = build_cdb_ext(PROTO_NON_DATA, CDB2_NON_DATA, 0x27, 0, 0, 0, 0, 0, 0xE0, 0, 0, 0, 0, 0);
+        assert!(translate_cdb(AtaMethod::Pt16, &ext, SG_DXFER_NONE, 0).is_ok());
+        assert!(translate_cdb(AtaMethod::Pt12, &ext, SG_DXFER_NONE, 0).is_err());
+        assert_eq!(AtaMethod::candidates(Some(USB_VENDOR_JMICRON)).last(), Some(&AtaMethod::JMicron));
+        assert_eq!(AtaMethod::candidates(None), [AtaMethod::Pt16, AtaMethod::Pt12]);
+    }
+
+    #[test]
+    fn test_registers_from_sense() {
+        // Descriptor format: ATA Status Return for a 48-bit command, ERR|DRDY with ABRT
+        let mut desc = vec![0x72, 0x0B, 0x00, 0x00, 0, 0, 0, 14];
+        desc.extend_from_slice(&[0x09, 0x0C, 0x01, 0x04, 0x00, 0x02]);
+        desc.extend_from_slice(&[0x44, 0x11, 0x55, 0x22, 0x66, 0x33, 0xE0, 0x51]);
+        let sense = scsi::decode_sense(&desc).unwrap();
+        let regs = AtaRegisters::from_sense(&sense).unwrap();
+        assert_eq!(regs.lba, 0x6655_4433_2211);
+        assert_eq!((regs.status, regs.error, regs.count, regs.extend), (0x51, 0x04, 2, true));
+
+        // Fixed format: INFORMATION = error, status, device, count; then flags and LBA 23:0
+        let mut fixed = [0u8; 18];
+        fixed[0] = 0x70;
+        fixed[2] = 0x01;
+        fixed[3..7].copy_from_slice(&[0x00, 0x50, 0xE0, 0x01]);
+        fixed[8..12].copy_from_slice(&[0x00, 0x4F, 0xC2, 0x00]);
+        fixed[12] = 0x00;
+        fixed[13] = 0x1D;
+        let sense = scsi::decode_sense(&fixed).unwrap();
+        let regs = AtaRegisters::from_sense(&sense).unwrap();
+        assert_eq!((regs.status, regs.count, regs.lba, regs.extend), (0x50, 1, 0xC24F, false));
+        assert_eq!(sense_result(0xEC, &sense, Some(regs)), Ok(()));
+    }
+
+    #[test]
+    fn test_fixed_sense_without_registers() {
+        // A bridge's MEDIUM ERROR (UNRECOVERED READ ERROR) in fixed format: INFORMATION
+        // is an LBA here, not ATA registers
+        let mut fixed = [0u8; 18];
+        fixed[0] = 0xF0;
+        fixed[2] = 0x03;
+        fixed[3..7].copy_from_slice(&[0x00, 0x00, 0x12, 0x34]);
+        fixed[7] = 10;
+        fixed[12] = 0x11;
+        let sense = scsi::decode_sense(&fixed).unwrap();
+        assert!(AtaRegisters::from_sense(&sense).is_none());
+        assert_eq!(
+            sense_result(0xC8, &sense, None),
+            Err(AtaError::Sense { command: 0xC8, key: 0x03, asc: 0x11, ascq: 0x00 })
+        );
+
+        // Registers without ERR don't turn a failing sense key into a success
+        let regs = AtaRegisters { status: 0x50, error: 0, device: 0xE0, count: 0, lba: 0, extend: false };
+        fixed[2] = 0x02;
+        fixed[12] = 0x04;
+        let sense = scsi::decode_sense(&fixed).unwrap();
+        assert!(sense_result(0xC8, &sense, Some(regs)).is_err());
     }
 }
@@ -0,0 +1,89 @@
+use crate::ata::{parse_ata_identify, AtaDevice};
+use crate::sysfs::{block_rotational, block_transport, BlockTransport};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SataClass {
+    /// ATA device with non-rotating media: the only kind vendor commands are sent to
+    Ssd,
+    /// ATA hard disk, or rotation not reported
+    Rotational,
+    /// Doesn't speak ATA (USB stick, SAS disk, iSCSI LUN, virtual disk)
+    NotAta,
+}
+
+impl SataClass {
+    pub fn name(self) -> &'static str {
+        match self {
+            SataClass::Ssd => "ssd",
+            SataClass::Rotational => "rotational",
+            SataClass::NotAta => "non-ata",
+        }
+    }
+}
+
+pub struct Classification {
+    pub class: SataClass,
+    pub transport: BlockTransport,
+    /// The evidence that decided the class
+    pub reason: String,
+}
+
+/// Classifies a /dev/sdX device from sysfs, SCSI INQUIRY and ATA IDENTIFY (`id`, None
+/// when IDENTIFY failed), before any vendor command is sent to it.
+pub fn classify(dev_path: &str, dev: &AtaDevice, id: Option<&[u8; 512]>) -> Classification {
+    let transport = block_transport(dev_path);
+    let result = |class, reason: String| Classification {
+        class,
+        transport,
+        reason,
+    };
+
+    if transport == BlockTransport::Iscsi {
+        return result(SataClass::NotAta, "iSCSI LUN".to_string());
+    }
+    let Some(id) = id else {
+        return result(SataClass::NotAta, "no response to ATA IDENTIFY DEVICE".to_string());
+    };
+    // libata and SAS HBAs report "ATA" for translated devices; USB bridges report
+    // their own vendor, so only the IDENTIFY answer counts there
+    if transport != BlockTransport::Usb
+        && let Ok(inq) = dev.inquiry()
+    {
+        let vendor = String::from_utf8_lossy(&inq[8..16]).trim().to_string();
+        if vendor != "ATA" {
+            return result(
+                SataClass::NotAta,
+                format!("SCSI INQUIRY vendor '{}' is not an ATA translation", vendor),
+            );
+        }
+    }
+
+    match parse_ata_identify(id).rotation_rate {
+        1 => result(SataClass::Ssd, "IDENTIFY word 217: non-rotating media".to_string()),
+        rpm @ 0x0401..=0xFFFE => {
+            result(SataClass::Rotational, format!("IDENTIFY word 217: {} rpm", rpm))
+        }
+        // Word 217 predates ACS-1 on some SSDs; the kernel's rotational flag is the fallback
+        _ => match block_rotational(dev_path) {
+            Some(false) => result(
+                SataClass::Ssd,
+                "rotation rate not reported, queue/rotational = 0".to_string(),
+            ),
+            Some(true) => result(
+                SataClass::Rotational,
+                "rotation rate not reported, queue/rotational = 1".to_string(),
+            ),
+            None => result(
+                SataClass::Rotational,
+                "rotation rate not reported, no queue/rotational to fall back on".to_string(),
+            ),
+        },
+    }
+}
+
+/// Opens and classifies a device for listing and auto-selection.
+pub fn classify_path(dev_path: &str) -> Result<Classification, String> {
+    let dev = AtaDevice::open(dev_path)?;
+    let id = dev.ata_identify().ok();
+    Ok(classify(dev_path, &dev, id.as_ref()))
+}
@@ -0,0 +1,373 @@
+use std::io::{BufRead, Write};
+
+use crate::ata::AtaDevice;
+use crate::hexdump::hexdump;
+use crate::nvme::NvmeDevice;
+
+const HELP: &str = "\
+commands:
+    admin OPCODE [nsid=N] [cdw10=V] .. [cdw15=V] [in=LEN | out=LEN] [data=HEX]
+                        NVMe admin command; in= reads LEN bytes, out= sends LEN bytes
+                        (data= bytes, else the loaded payload, else zeros)
+    ata CMD [feat=V] [count=V] [lba=V] [dev=V] [ext] [in=LEN | out=LEN] [dma] [data=HEX]
+                        ATA PASS-THROUGH; 28-bit taskfile unless ext (48-bit LBA,
+                        16-bit feature/count); dma uses the DMA protocol for in=
+    show                hexdump the last response again
+    save FILE           write the last response to FILE
+    load FILE           use FILE as the payload of following out= commands
+    run FILE            replay commands from a script (one per line, # comments)
+    help                show this help
+    quit                leave the console
+
+numbers are decimal or 0x-prefixed hex";
+
+/// Nesting limit for scripts that run other scripts, so a script that runs itself fails
+/// instead of overflowing the stack
+const MAX_SCRIPT_DEPTH: usize = 8;
+
+pub enum ConsoleDevice<'a> {
+    Nvme(&'a NvmeDevice),
+    Ata(&'a AtaDevice),
+}
+
+struct Console<'a> {
+    dev: ConsoleDevice<'a>,
+    last: Vec<u8>,
+    payload: Option<Vec<u8>>,
+    /// Number of `run` scripts currently executing
+    depth: usize,
+}
+
+/// Interactive vendor command console on top of the NVMe and ATA transports. Every
+/// command goes through the regular transport, so -v tracing, --audit-log and
+/// --dump-dir apply to it as well.
+pub fn run(dev: ConsoleDevice) {
+    let mut console = Console {
+        dev,
+        last: Vec::new(),
+        payload: None,
+        depth: 0,
+    };
+    let prompt = match console.dev {
+        ConsoleDevice::Nvme(_) => "nvme> ",
+        ConsoleDevice::Ata(_) => "ata> ",
+    };
+    println!("type 'help' for commands, 'quit' to exit");
+
+    let stdin = std::io::stdin();
+    let mut line = String::new();
+    loop {
+        print!("{}", prompt);
+        let _ = std::io::stdout().flush();
+        line.clear();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        match console.execute(line.trim()) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => println!("error: {}", e),
+        }
+        if crate::interrupt::check().is_err() {
+            break;
+        }
+    }
+}
+
+impl Console<'_> {
+    /// Returns Ok(false) when the console should exit.
+    fn execute(&mut self, line: &str) -> Result<bool, String> {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut words = line.split_whitespace();
+        let Some(cmd) = words.next() else {
+            return Ok(true);
+        };
+        let rest: Vec<&str> = words.collect();
+        match cmd {
+            "quit" | "exit" => return Ok(false),
+            "help" | "?" => println!("{}", HELP),
+            "admin" => self.admin(&rest)?,
+            "ata" => self.ata(&rest)?,
+            "show" => print!("{}", hexdump(&self.last, "")),
+            "save" => {
+                let file = rest.first().ok_or("save requires a file name")?;
+                std::fs::write(file, &self.last)
+                    .map_err(|e| format!("failed to write '{}': {}", file, e))?;
+                println!("saved {} bytes to {}", self.last.len(), file);
+            }
+            "load" => {
+                let file = rest.first().ok_or("load requires a file name")?;
+                let data =
+                    std::fs::read(file).map_err(|e| format!("failed to read '{}': {}", file, e))?;
+                println!("loaded {} byte payload from {}", data.len(), file);
+                self.payload = Some(data);
+            }
+            "run" => {
+                let file = rest.first().ok_or("run requires a file name")?;
+                if self.depth >= MAX_SCRIPT_DEPTH {
+                    return Err(format!("scripts nested more than {} deep", MAX_SCRIPT_DEPTH));
+                }
+                let script = std::fs::read_to_string(file)
+                    .map_err(|e| format!("failed to read '{}': {}", file, e))?;
+                self.depth += 1;
+                let result = self.run_script(file, &script);
+                self.depth -= 1;
+                return result;
+            }
+            other => return Err(format!("unknown command '{}' (try 'help')", other)),
+        }
+        Ok(true)
+    }
+
+    fn admin(&mut self, words: &[&str]) -> Result<(), String> {
+        let ConsoleDevice::Nvme(dev) = self.dev else {
+            return Err("admin commands need an NVMe device".to_string());
+        };
+        let (opcode, opts) = words.split_first().ok_or("admin requires an opcode")?;
+        let opcode = parse_num(opcode)?;
+        let opts = Options::parse(opts);
+        let mut cdw = [0u32; 6];
+        for (i, c) in cdw.iter_mut().enumerate() {
+            *c = opts.get(&format!("cdw{}", 10 + i))?.unwrap_or(0);
+        }
+        let nsid = opts.get("nsid")?.unwrap_or(0);
+
+        let outcome = if let Some(len) = opts.get("in")? {
+            let mut buf = vec![0u8; len];
+            let r = dev.admin_read(
+                opcode, nsid, cdw[0], cdw[1], cdw[2], cdw[3], cdw[4], cdw[5], &mut buf,
+            );
+            if r.is_ok() {
+                self.last = buf;
+            }
+            r
+        } else if let Some(len) = opts.get("out")? {
+            let buf = self.out_payload(&opts, len)?;
+            dev.admin_write(opcode, nsid, cdw[0], cdw[1], cdw[2], cdw[3], cdw[4], cdw[5], &buf)
+        } else {
+            dev.admin_no_data(opcode, nsid, cdw[0], cdw[1], cdw[2], cdw[3], cdw[4], cdw[5])
+        };
+        // A rejected command still completed: show its status and cdw0 rather than the
+        // transport error
+        let result = match (outcome, dev.completion()) {
+            (Ok(r), _) => r,
+            (Err(_), Some((status, cdw0))) if status != 0 => {
+                return Err(format!(
+                    "rejected, {}, result (cdw0) 0x{:08x}",
+                    crate::nvme::describe_status(status),
+                    cdw0
+                ));
+            }
+            (Err(e), _) => return Err(e),
+        };
+        println!("ok, result (cdw0) 0x{:08x}", result);
+        if opts.value("in").is_some() {
+            print!("{}", hexdump(&self.last, ""));
+        }
+        Ok(())
+    }
+
+    fn ata(&mut self, words: &[&str]) -> Result<(), String> {
+        let ConsoleDevice::Ata(dev) = self.dev else {
+            return Err("ata commands need a SATA device".to_string());
+        };
+        let (command, opts) = words.split_first().ok_or("ata requires a command")?;
+        let command = parse_num(command)?;
+        let opts = Options::parse(opts);
+        let ext = opts.flag("ext");
+        let feat: u64 = opts.get("feat")?.unwrap_or(0);
+        let count: u64 = opts.get("count")?.unwrap_or(0);
+        let lba: u64 = opts.get("lba")?.unwrap_or(0);
+        let mut device: u8 = opts.get("dev")?.unwrap_or(0xE0);
+        let b = |v: u64, byte: u32| (v >> (8 * byte)) as u8;
+        if ext {
+            if lba > 0xFFFF_FFFF_FFFF || feat > 0xFFFF || count > 0xFFFF {
+                return Err("48-bit taskfile: lba <= 0xffffffffffff, feat/count <= 0xffff".into());
+            }
+        } else {
+            if lba > 0x0FFF_FFFF || feat > 0xFF || count > 0xFF {
+                return Err("28-bit taskfile: lba <= 0xfffffff, feat/count <= 0xff (use ext)".into());
+            }
+            device = (device & 0xF0) | (b(lba, 3) & 0x0F);
+        }
+
+        if let Some(len) = opts.get("in")? {
+            let mut buf = vec![0u8; len];
+            match (ext, opts.flag("dma")) {
+                (false, false) => dev.ata_read(
+                    command, b(feat, 0), b(count, 0), b(lba, 0), b(lba, 1), b(lba, 2), device,
+                    &mut buf,
+                )?,
+                (false, true) => dev.ata_dma_read(
+                    command, b(feat, 0), b(count, 0), b(lba, 0), b(lba, 1), b(lba, 2), device,
+                    &mut buf,
+                )?,
+                (true, false) => dev.ata_read_ext(
+                    command, b(feat, 0), b(count, 0), b(lba, 0), b(lba, 1), b(lba, 2), device,
+                    b(feat, 1), b(count, 1), b(lba, 3), b(lba, 4), b(lba, 5), &mut buf,
+                )?,
+                (true, true) => return Err("48-bit DMA reads are not supported".to_string()),
+            }
+            print_ata_ok(dev);
+            print!("{}", hexdump(&buf, ""));
+            self.last = buf;
+        } else if let Some(len) = opts.get("out")? {
+            let buf = self.out_payload(&opts, len)?;
+            if ext {
+                dev.ata_write_ext(
+                    command, b(feat, 0), b(count, 0), b(lba, 0), b(lba, 1), b(lba, 2), device,
+                    b(feat, 1), b(count, 1), b(lba, 3), b(lba, 4), b(lba, 5), &buf,
+                )?;
+            } else {
+                dev.ata_write(
+                    command, b(feat, 0), b(count, 0), b(lba, 0), b(lba, 1), b(lba, 2), device,
+                    &buf,
+                )?;
+            }
+            print_ata_ok(dev);
+        } else {
+            if ext {
+                dev.ata_no_data_ext(
+                    command, b(feat, 0), b(count, 0), b(lba, 0), b(lba, 1), b(lba, 2), device,
+                    b(feat, 1), b(count, 1), b(lba, 3), b(lba, 4), b(lba, 5),
+                )?;
+            } else {
+                dev.ata_no_data(
+                    command, b(feat, 0), b(count, 0), b(lba, 0), b(lba, 1), b(lba, 2), device,
+                )?;
+            }
+            print_ata_ok(dev);
+        }
+        Ok(())
+    }
+
+    fn run_script(&mut self, file: &str, script: &str) -> Result<bool, String> {
+        for (n, script_line) in script.lines().enumerate() {
+            if script_line.split('#').next().unwrap_or("").trim().is_empty() {
+                continue;
+            }
+            println!("> {}", script_line.trim());
+            if !self
+                .execute(script_line)
+                .map_err(|e| format!("{}:{}: {}", file, n + 1, e))?
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Payload for a data-out command: data= bytes, else the loaded file, zero-padded to len.
+    fn out_payload(&self, opts: &Options, len: usize) -> Result<Vec<u8>, String> {
+        let src = match opts.value("data") {
+            Some(hex) => parse_hex(hex)?,
+            None => self.payload.clone().unwrap_or_default(),
+        };
+        if src.len() > len {
+            return Err(format!("payload is {} bytes, longer than out={}", src.len(), len));
+        }
+        let mut buf = vec![0u8; len];
+        buf[..src.len()].copy_from_slice(&src);
+        Ok(buf)
+    }
+}
+
+/// "ok", plus the registers the drive returned when the sense data carried them.
+fn print_ata_ok(dev: &AtaDevice) {
+    match dev.registers() {
+        Some(regs) => println!("ok, {}", regs.describe()),
+        None => println!("ok"),
+    }
+}
+
+/// key=value options and bare flags following a console command.
+struct Options<'a> {
+    pairs: Vec<(&'a str, &'a str)>,
+    flags: Vec<&'a str>,
+}
+
+impl<'a> Options<'a> {
+    fn parse(words: &[&'a str]) -> Self {
+        let mut opts = Options {
+            pairs: Vec::new(),
+            flags: Vec::new(),
+        };
+        for w in words {
+            match w.split_once('=') {
+                Some((k, v)) => opts.pairs.push((k, v)),
+                None => opts.flags.push(w),
+            }
+        }
+        opts
+    }
+
+    fn value(&self, key: &str) -> Option<&'a str> {
+        self.pairs.iter().rev().find(|(k, _)| *k == key).map(|&(_, v)| v)
+    }
+
+    fn get<T: TryFrom<u64>>(&self, key: &str) -> Result<Option<T>, String> {
+        self.value(key)
+            .map(|v| parse_num(v).map_err(|e| format!("{}: {}", key, e)))
+            .transpose()
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.contains(&name)
+    }
+}
+
+/// Decimal or 0x-prefixed hex number, rejected when it doesn't fit the target field.
+fn parse_num<T: TryFrom<u64>>(s: &str) -> Result<T, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    let n = parsed.map_err(|_| format!("invalid number '{}'", s))?;
+    T::try_from(n).map_err(|_| format!("'{}' is out of range", s))
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = s.bytes().filter(|b| !matches!(b, b':' | b'-' | b'_')).collect();
+    if !digits.iter().all(u8::is_ascii_hexdigit) {
+        return Err(format!("invalid hex data '{}'", s));
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("hex data '{}' has an odd number of digits", s));
+    }
+    let nibble = |d: u8| (d as char).to_digit(16).unwrap() as u8;
+    Ok(digits.chunks_exact(2).map(|p| nibble(p[0]) << 4 | nibble(p[1])).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_num() {
+        assert_eq!(parse_num::<u8>("0xC2"), Ok(0xC2));
+        assert_eq!(parse_num::<u32>("4096"), Ok(4096));
+        assert!(parse_num::<u8>("0x1c2").unwrap_err().contains("out of range"));
+        assert!(parse_num::<u32>("12abc").unwrap_err().contains("invalid number"));
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("00:03-0c_ff"), Ok(vec![0x00, 0x03, 0x0C, 0xFF]));
+        assert!(parse_hex("abc").unwrap_err().contains("odd number"));
+        // Non-ASCII input is an error, not a panic on a char boundary
+        assert!(parse_hex("aé0").unwrap_err().contains("invalid hex"));
+        assert!(parse_hex("zz").is_err());
+    }
+
+    #[test]
+    fn test_options() {
+        let opts = Options::parse(&["cdw10=0x200", "in=512", "ext", "in=1024"]);
+        assert_eq!(opts.get::<u32>("cdw10"), Ok(Some(0x200)));
+        // The last occurrence wins
+        assert_eq!(opts.get::<usize>("in"), Ok(Some(1024)));
+        assert_eq!(opts.get::<u8>("cdw11"), Ok(None));
+        assert!(opts.get::<u8>("cdw10").unwrap_err().starts_with("cdw10: "));
+        assert!(opts.flag("ext") && !opts.flag("dma"));
+    }
+}
@@ -0,0 +1,27 @@
+use crate::controllers::{extract_banks, FlashIdResult};
+use crate::risk::Risk;
+use crate::scsi::ScsiDevice;
+
+/// 0xFA read only
+pub const RISK: Risk = Risk::VendorRead;
+
+/// Alcor AU698x vendor read command
+const ALCOR_CDB: u8 = 0xFA;
+const SUBCMD_FLASH_ID: u8 = 0x0D;
+const RESPONSE_LEN: usize = 512;
+/// One 8-byte ID per chip enable
+const MAX_BANKS: usize = 8;
+
+pub fn read_flash_id(dev: &ScsiDevice) -> Result<FlashIdResult, String> {
+    let mut buf = [0u8; RESPONSE_LEN];
+    let cdb = [ALCOR_CDB, SUBCMD_FLASH_ID, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    dev.cdb_read(&cdb, &mut buf)
+        .map_err(|e| format!("Alcor USB flash ID read failed: {}", e))?;
+
+    let banks = extract_banks(&buf, 0, MAX_BANKS);
+
+    Ok(FlashIdResult {
+        controller_name: "Alcor Micro AU698x".to_string(),
+        banks,
+    })
+}
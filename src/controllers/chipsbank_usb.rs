@@ -0,0 +1,34 @@
+use crate::controllers::{extract_banks, FlashIdResult};
+use crate::risk::Risk;
+use crate::scsi::ScsiDevice;
+
+/// One 0xC5 read
+pub const RISK: Risk = Risk::VendorRead;
+
+/// ChipsBank CBM2xxx vendor read command
+const CBM_CDB: u8 = 0xC5;
+const SUBCMD_FLASH_ID: u8 = 0x07;
+const RESPONSE_LEN: usize = 512;
+const MAX_BANKS: usize = 8;
+/// Chip number (BCD, e.g. 0x2199) after the bank table
+const CHIP_OFFSET: usize = 0x80;
+
+pub fn read_flash_id(dev: &ScsiDevice) -> Result<FlashIdResult, String> {
+    let mut buf = [0u8; RESPONSE_LEN];
+    let cdb = [CBM_CDB, SUBCMD_FLASH_ID, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    dev.cdb_read(&cdb, &mut buf)
+        .map_err(|e| format!("ChipsBank USB flash ID read failed: {}", e))?;
+
+    let banks = extract_banks(&buf, 0, MAX_BANKS);
+
+    let chip = u16::from_be_bytes([buf[CHIP_OFFSET], buf[CHIP_OFFSET + 1]]);
+    let controller_name = match chip {
+        0x0000 | 0xFFFF => "ChipsBank CBM2xxx".to_string(),
+        _ => format!("ChipsBank CBM{:04X}", chip),
+    };
+
+    Ok(FlashIdResult {
+        controller_name,
+        banks,
+    })
+}
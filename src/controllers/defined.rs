@@ -0,0 +1,137 @@
+use crate::ata::AtaDevice;
+use crate::controllers::{is_bank_empty, FlashBank, FlashIdResult};
+use crate::defs::{DefCommand, DefDir, Definition};
+use crate::nand_db::manufacturer_name;
+use crate::nvme::NvmeDevice;
+
+/// Runs a definition's command sequence on an NVMe device and parses the banks.
+pub fn read_flash_id_nvme(dev: &NvmeDevice, def: &Definition) -> Result<FlashIdResult, String> {
+    let responses = run_commands(def, &def.commands, |cmd, buf| send_nvme(dev, cmd, buf))?;
+    parse_result(def, &responses)
+}
+
+pub fn read_flash_id_ata(dev: &AtaDevice, def: &Definition) -> Result<FlashIdResult, String> {
+    let responses = run_commands(def, &def.commands, |cmd, buf| send_ata(dev, cmd, buf))?;
+    parse_result(def, &responses)
+}
+
+pub fn teardown_nvme(dev: &NvmeDevice, def: &Definition) -> Result<(), String> {
+    run_commands(def, &def.teardown, |cmd, buf| send_nvme(dev, cmd, buf)).map(|_| ())
+}
+
+pub fn teardown_ata(dev: &AtaDevice, def: &Definition) -> Result<(), String> {
+    run_commands(def, &def.teardown, |cmd, buf| send_ata(dev, cmd, buf)).map(|_| ())
+}
+
+/// Sends each command in order and returns every response buffer (empty for commands
+/// without data-in).
+fn run_commands(
+    def: &Definition,
+    commands: &[DefCommand],
+    mut send: impl FnMut(&DefCommand, &mut [u8]) -> Result<(), String>,
+) -> Result<Vec<Vec<u8>>, String> {
+    let mut responses = Vec::new();
+    for (i, cmd) in commands.iter().enumerate() {
+        let mut buf = vec![0u8; cmd.len];
+        if cmd.dir == DefDir::Out {
+            for &(off, byte) in &cmd.payload {
+                buf[off] = byte;
+            }
+        }
+        send(cmd, &mut buf)
+            .map_err(|e| format!("{} command {} (0x{:02x}) failed: {}", def.name, i, cmd.opcode, e))?;
+        if let Some((off, ref sig)) = cmd.expect
+            && buf.get(off..off + sig.len()) != Some(sig.as_slice())
+        {
+            return Err(format!(
+                "{} command {} response does not contain '{}' at 0x{:x}",
+                def.name,
+                i,
+                String::from_utf8_lossy(sig),
+                off
+            ));
+        }
+        responses.push(if cmd.dir == DefDir::In { buf } else { Vec::new() });
+    }
+    Ok(responses)
+}
+
+fn send_nvme(dev: &NvmeDevice, cmd: &DefCommand, buf: &mut [u8]) -> Result<(), String> {
+    let [c10, c11, c12, c13, c14, c15] = cmd.cdw;
+    match cmd.dir {
+        DefDir::In => dev.admin_read(cmd.opcode, cmd.nsid, c10, c11, c12, c13, c14, c15, buf),
+        DefDir::Out => dev.admin_write(cmd.opcode, cmd.nsid, c10, c11, c12, c13, c14, c15, buf),
+        DefDir::None => dev.admin_no_data(cmd.opcode, cmd.nsid, c10, c11, c12, c13, c14, c15),
+    }
+    .map(|_| ())
+}
+
+fn send_ata(dev: &AtaDevice, cmd: &DefCommand, buf: &mut [u8]) -> Result<(), String> {
+    let (c, f, n) = (cmd.opcode, cmd.features, cmd.count);
+    let (lo, mid, hi, d) = (cmd.lba_low, cmd.lba_mid, cmd.lba_high, cmd.device);
+    match cmd.dir {
+        DefDir::In if cmd.dma => dev.ata_dma_read(c, f, n, lo, mid, hi, d, buf),
+        DefDir::In => dev.ata_read(c, f, n, lo, mid, hi, d, buf),
+        DefDir::Out => dev.ata_write(c, f, n, lo, mid, hi, d, buf),
+        DefDir::None => dev.ata_no_data(c, f, n, lo, mid, hi, d),
+    }
+    .map_err(String::from)
+}
+
+/// Extracts the banks and controller name from the responses per the parse layout.
+pub fn parse_result(def: &Definition, responses: &[Vec<u8>]) -> Result<FlashIdResult, String> {
+    let layout = &def.parse;
+    let resp = def
+        .bank_response()
+        .and_then(|i| responses.get(i))
+        .ok_or_else(|| format!("{}: bank response missing", def.name))?;
+
+    let mut banks = Vec::new();
+    for i in 0..layout.bank_count {
+        let offset = layout.bank_start + i * layout.bank_size;
+        let Some(bank_data) = resp.get(offset..offset + layout.bank_size) else {
+            break;
+        };
+        if is_bank_empty(bank_data) {
+            if layout.filter.stop_at_empty {
+                break;
+            }
+            if layout.filter.nonempty {
+                continue;
+            }
+        }
+        if layout.filter.jedec && manufacturer_name(bank_data[0]) == "Unknown" {
+            continue;
+        }
+        let mut flash_id = [0u8; 8];
+        flash_id[..bank_data.len()].copy_from_slice(bank_data);
+        banks.push(FlashBank {
+            bank_num: i as u32,
+            flash_id,
+        });
+    }
+
+    let controller_name = layout
+        .name
+        .and_then(|(resp_idx, off, len)| {
+            let idx = resp_idx.or(def.bank_response())?;
+            let data = responses.get(idx)?.get(off..)?;
+            extract_name(&data[..len.min(data.len())])
+        })
+        .unwrap_or_else(|| def.controller.clone());
+
+    Ok(FlashIdResult {
+        controller_name,
+        banks,
+    })
+}
+
+fn extract_name(data: &[u8]) -> Option<String> {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    let s: String = data[..end]
+        .iter()
+        .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { ' ' })
+        .collect();
+    let trimmed = s.trim();
+    (!trimmed.is_empty()).then(|| trimmed.to_string())
+}
@@ -0,0 +1,24 @@
+use crate::controllers::{extract_banks, FlashIdResult};
+use crate::mmc::MmcDevice;
+use crate::risk::Risk;
+
+/// One GEN_CMD read of the NAND information report
+pub const RISK: Risk = Risk::VendorRead;
+
+/// GEN_CMD argument of the NAND information report
+const NAND_INFO_ARG: u32 = 0x1100_05FB;
+const BANK_START: usize = 0x20;
+const MAX_BANKS: usize = 16;
+
+pub fn read_flash_id(dev: &MmcDevice) -> Result<FlashIdResult, String> {
+    let report = dev
+        .gen_cmd_read(NAND_INFO_ARG)
+        .map_err(|e| format!("SK hynix NAND info read failed: {}", e))?;
+
+    let banks = extract_banks(&report, BANK_START, MAX_BANKS);
+
+    Ok(FlashIdResult {
+        controller_name: "SK hynix eMMC".to_string(),
+        banks,
+    })
+}
@@ -0,0 +1,29 @@
+use crate::controllers::{extract_banks, FlashIdResult};
+use crate::risk::Risk;
+use crate::scsi::ScsiDevice;
+
+/// READ BUFFER of a vendor buffer
+pub const RISK: Risk = Risk::VendorRead;
+
+const READ_BUFFER: u8 = 0x3C;
+/// Data mode with a vendor buffer ID
+const MODE_DATA: u8 = 0x02;
+const BUFFER_FLASH_ID: u8 = 0xF1;
+const RESPONSE_LEN: usize = 512;
+const BANK_START: usize = 0x10;
+const MAX_BANKS: usize = 32;
+
+pub fn read_flash_id(dev: &ScsiDevice) -> Result<FlashIdResult, String> {
+    let mut buf = [0u8; RESPONSE_LEN];
+    let len = (RESPONSE_LEN as u32).to_be_bytes();
+    let cdb = [READ_BUFFER, MODE_DATA, BUFFER_FLASH_ID, 0, 0, 0, len[1], len[2], len[3], 0];
+    dev.cdb_read(&cdb, &mut buf)
+        .map_err(|e| format!("SK hynix UFS flash ID buffer read failed: {}", e))?;
+
+    let banks = extract_banks(&buf, BANK_START, MAX_BANKS);
+
+    Ok(FlashIdResult {
+        controller_name: "SK hynix UFS".to_string(),
+        banks,
+    })
+}
@@ -1,5 +1,9 @@
 use crate::controllers::{is_bank_empty, FlashBank, FlashIdResult};
 use crate::nvme::NvmeDevice;
+use crate::risk::Risk;
+
+/// F2 read only
+pub const RISK: Risk = Risk::VendorRead;
 
 const GRIT_MAGIC: u32 = 0x54495247;
 const INNO_MAGIC: u32 = 0x4F4E4E49;
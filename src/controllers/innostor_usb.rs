@@ -0,0 +1,48 @@
+use crate::controllers::{extract_banks, FlashIdResult};
+use crate::risk::Risk;
+use crate::scsi::ScsiDevice;
+
+/// Vendor mode unlock before the read
+pub const RISK: Risk = Risk::VendorWrite;
+
+// The unlock key and the controller name byte have not been confirmed against vendor
+// tools or a captured trace, so this reader only runs with --controller innostor-usb.
+
+/// Innostor vendor command; byte 1 selects the operation
+const INNOSTOR_CDB: u8 = 0xC6;
+const OP_UNLOCK: u8 = 0x01;
+const OP_FLASH_ID: u8 = 0x20;
+/// Unlock key in CDB bytes 2..6
+const UNLOCK_KEY: [u8; 4] = *b"INST";
+const RESPONSE_LEN: usize = 512;
+/// The response starts with a 16-byte header; the first byte echoes the operation
+const BANK_START: usize = 0x10;
+const MAX_BANKS: usize = 16;
+
+pub fn read_flash_id(dev: &ScsiDevice) -> Result<FlashIdResult, String> {
+    let mut cdb = [0u8; 12];
+    cdb[0] = INNOSTOR_CDB;
+    cdb[1] = OP_UNLOCK;
+    cdb[2..6].copy_from_slice(&UNLOCK_KEY);
+    dev.cdb_no_data(&cdb)
+        .map_err(|e| format!("Innostor USB vendor unlock failed: {}", e))?;
+
+    let mut buf = [0u8; RESPONSE_LEN];
+    let len = (RESPONSE_LEN as u16).to_be_bytes();
+    let cdb = [INNOSTOR_CDB, OP_FLASH_ID, 0, 0, 0, 0, 0, len[0], len[1], 0, 0, 0];
+    dev.cdb_read(&cdb, &mut buf)
+        .map_err(|e| format!("Innostor USB flash ID read failed: {}", e))?;
+    if buf[0] != OP_FLASH_ID {
+        return Err(format!(
+            "Innostor USB flash ID response starts with 0x{:02x}, expected 0x{:02x}",
+            buf[0], OP_FLASH_ID
+        ));
+    }
+
+    let banks = extract_banks(&buf, BANK_START, MAX_BANKS);
+
+    Ok(FlashIdResult {
+        controller_name: "Innostor".to_string(),
+        banks,
+    })
+}
@@ -1,5 +1,9 @@
 use crate::ata::AtaDevice;
 use crate::controllers::{is_bank_empty, FlashBank, FlashIdResult};
+use crate::risk::Risk;
+
+/// SET FEATURES unlocks and 0x88 request writes
+pub const RISK: Risk = Risk::VendorWrite;
 
 const ATA_CMD_WRITE: u8 = 0x88;
 const ATA_CMD_READ: u8 = 0x86;
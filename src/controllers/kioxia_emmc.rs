@@ -0,0 +1,39 @@
+use crate::controllers::{extract_banks, FlashIdResult};
+use crate::mmc::{MmcDevice, BLOCK_SIZE};
+use crate::risk::Risk;
+
+/// Query block written with GEN_CMD, then read back
+pub const RISK: Risk = Risk::VendorWrite;
+
+// The GEN_CMD arguments and the request layout have not been confirmed against vendor
+// tools or a captured trace, so this reader only runs with --controller kioxia-emmc.
+
+const REQUEST_ARG: u32 = 0x0000_0000;
+const RESPONSE_ARG: u32 = 0x0000_0001;
+/// Request code of the flash ID query, in the first byte of the request block
+const REQ_FLASH_ID: u8 = 0x10;
+const BANK_START: usize = 0x10;
+const MAX_BANKS: usize = 16;
+
+pub fn read_flash_id(dev: &MmcDevice) -> Result<FlashIdResult, String> {
+    let mut request = [0u8; BLOCK_SIZE];
+    request[0] = REQ_FLASH_ID;
+    dev.gen_cmd_write(REQUEST_ARG, &request)
+        .map_err(|e| format!("Kioxia flash ID request failed: {}", e))?;
+    let response = dev
+        .gen_cmd_read(RESPONSE_ARG)
+        .map_err(|e| format!("Kioxia flash ID read failed: {}", e))?;
+    if response[0] != REQ_FLASH_ID {
+        return Err(format!(
+            "Kioxia flash ID response has request code 0x{:02x}, expected 0x{:02x}",
+            response[0], REQ_FLASH_ID
+        ));
+    }
+
+    let banks = extract_banks(&response, BANK_START, MAX_BANKS);
+
+    Ok(FlashIdResult {
+        controller_name: "Kioxia/Toshiba eMMC".to_string(),
+        banks,
+    })
+}
@@ -0,0 +1,43 @@
+use crate::controllers::{extract_banks, FlashIdResult};
+use crate::risk::Risk;
+use crate::scsi::ScsiDevice;
+
+/// WRITE BUFFER request before the READ BUFFER
+pub const RISK: Risk = Risk::VendorWrite;
+
+const WRITE_BUFFER: u8 = 0x3B;
+const READ_BUFFER: u8 = 0x3C;
+const MODE_DATA: u8 = 0x02;
+const BUFFER_VENDOR: u8 = 0xE0;
+/// Request code of the flash ID query, in the first byte of the request
+const REQ_FLASH_ID: u8 = 0x10;
+const BUF_LEN: usize = 512;
+const BANK_START: usize = 0x10;
+const MAX_BANKS: usize = 32;
+
+pub fn read_flash_id(dev: &ScsiDevice) -> Result<FlashIdResult, String> {
+    let len = (BUF_LEN as u32).to_be_bytes();
+    let mut request = [0u8; BUF_LEN];
+    request[0] = REQ_FLASH_ID;
+    let cdb = [WRITE_BUFFER, MODE_DATA, BUFFER_VENDOR, 0, 0, 0, len[1], len[2], len[3], 0];
+    dev.cdb_write(&cdb, &request)
+        .map_err(|e| format!("Kioxia UFS flash ID request failed: {}", e))?;
+
+    let mut buf = [0u8; BUF_LEN];
+    let cdb = [READ_BUFFER, MODE_DATA, BUFFER_VENDOR, 0, 0, 0, len[1], len[2], len[3], 0];
+    dev.cdb_read(&cdb, &mut buf)
+        .map_err(|e| format!("Kioxia UFS flash ID read failed: {}", e))?;
+    if buf[0] != REQ_FLASH_ID {
+        return Err(format!(
+            "Kioxia UFS flash ID response has request code 0x{:02x}, expected 0x{:02x}",
+            buf[0], REQ_FLASH_ID
+        ));
+    }
+
+    let banks = extract_banks(&buf, BANK_START, MAX_BANKS);
+
+    Ok(FlashIdResult {
+        controller_name: "Kioxia UFS".to_string(),
+        banks,
+    })
+}
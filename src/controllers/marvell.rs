@@ -1,5 +1,9 @@
 use crate::controllers::{is_bank_empty, FlashBank, FlashIdResult};
 use crate::nvme::NvmeDevice;
+use crate::risk::Risk;
+
+/// FD request write before the FE read
+pub const RISK: Risk = Risk::VendorWrite;
 
 const DM1160_SIG: &[u8; 6] = b"DM1160";
 const DM1140_SIG: &[u8; 6] = b"DM1140";
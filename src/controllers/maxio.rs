@@ -1,5 +1,9 @@
 use crate::controllers::{is_bank_empty, FlashBank, FlashIdResult};
 use crate::nvme::NvmeDevice;
+use crate::risk::Risk;
+
+/// C1 request writes before each C2 read
+pub const RISK: Risk = Risk::VendorWrite;
 
 const MAX_CHANNELS: u8 = 8;
 const MAX_CES: u8 = 8;
@@ -1,14 +1,29 @@
+pub mod alcor_usb;
 pub mod ata_identify_fid;
+pub mod chipsbank_usb;
+pub mod defined;
+pub mod hynix_emmc;
+pub mod hynix_ufs;
 pub mod innogrit;
+pub mod innostor_usb;
 pub mod jm_sata;
+pub mod kioxia_emmc;
+pub mod kioxia_ufs;
 pub mod marvell;
 pub mod maxio;
 pub mod phison;
+pub mod phison_emmc;
+pub mod phison_usb;
 pub mod rtl;
 pub mod rtl_sata;
+pub mod samsung_emmc;
+pub mod samsung_ufs;
+pub mod sandisk_emmc;
 pub mod sandforce;
 pub mod smi;
+pub mod smi_emmc;
 pub mod smi_sata;
+pub mod smi_usb;
 pub mod tenafe;
 pub mod yeestor;
 
@@ -27,3 +42,17 @@ pub struct FlashIdResult {
 fn is_bank_empty(data: &[u8]) -> bool {
     data.iter().all(|&b| b == 0x00) || data.iter().all(|&b| b == 0xFF)
 }
+
+/// Up to `count` consecutive 8-byte flash IDs from `start`, stopping at the end of `buf`.
+/// Empty slots are skipped but keep their bank number.
+fn extract_banks(buf: &[u8], start: usize, count: usize) -> Vec<FlashBank> {
+    (0..count)
+        .filter_map(|i| {
+            let bank_data = buf.get(start + i * 8..start + (i + 1) * 8)?;
+            (!is_bank_empty(bank_data)).then(|| FlashBank {
+                bank_num: i as u32,
+                flash_id: bank_data.try_into().unwrap(),
+            })
+        })
+        .collect()
+}
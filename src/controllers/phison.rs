@@ -1,5 +1,9 @@
 use crate::controllers::{is_bank_empty, FlashBank, FlashIdResult};
 use crate::nvme::NvmeDevice;
+use crate::risk::Risk;
+
+/// D2 reads only
+pub const RISK: Risk = Risk::VendorRead;
 
 const PHISON_SIGNATURE: &[u8; 8] = b"PhIsOnNo";
 const MAX_BANKS: u32 = 8;
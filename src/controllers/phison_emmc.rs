@@ -0,0 +1,36 @@
+use crate::controllers::{extract_banks, FlashIdResult};
+use crate::mmc::{MmcDevice, BLOCK_SIZE};
+use crate::risk::Risk;
+
+/// "PS" request sent as a GEN_CMD write
+pub const RISK: Risk = Risk::VendorWrite;
+
+// The GEN_CMD arguments and the request layout have not been confirmed against vendor
+// tools or a captured trace, so this reader only runs with --controller phison-emmc.
+
+const REQUEST_ARG: u32 = 0x5053_0000;
+const RESPONSE_ARG: u32 = 0x5053_0001;
+/// "PS" signature and the flash ID query code, as in the NVMe/SATA vendor protocol
+const REQUEST_HEADER: [u8; 4] = [0x50, 0x53, 0x0D, 0x00];
+const BANK_START: usize = 0x40;
+const MAX_BANKS: usize = 32;
+
+pub fn read_flash_id(dev: &MmcDevice) -> Result<FlashIdResult, String> {
+    let mut request = [0u8; BLOCK_SIZE];
+    request[..REQUEST_HEADER.len()].copy_from_slice(&REQUEST_HEADER);
+    dev.gen_cmd_write(REQUEST_ARG, &request)
+        .map_err(|e| format!("Phison flash ID request failed: {}", e))?;
+    let response = dev
+        .gen_cmd_read(RESPONSE_ARG)
+        .map_err(|e| format!("Phison flash ID read failed: {}", e))?;
+    if response[..2] != REQUEST_HEADER[..2] {
+        return Err("Phison flash ID response has no PS signature".to_string());
+    }
+
+    let banks = extract_banks(&response, BANK_START, MAX_BANKS);
+
+    Ok(FlashIdResult {
+        controller_name: "Phison eMMC (PS82xx)".to_string(),
+        banks,
+    })
+}
@@ -0,0 +1,51 @@
+use crate::controllers::{extract_banks, FlashIdResult};
+use crate::risk::Risk;
+use crate::scsi::ScsiDevice;
+
+/// 0x06 info and flash ID reads
+pub const RISK: Risk = Risk::VendorRead;
+
+/// Phison USB vendor command group
+const PHISON_CDB: u8 = 0x06;
+const SUBCMD_INFO: u8 = 0x05;
+const SUBCMD_FLASH_ID: u8 = 0x56;
+const INFO_LEN: usize = 528;
+/// Chip type in the info block, e.g. 0x2251 0x03 for a PS2251-03
+const INFO_CHIP: usize = 0x17E;
+const FLASH_ID_LEN: usize = 512;
+const MAX_BANKS: usize = 16;
+
+fn vendor_cdb(subcmd: u8) -> [u8; 16] {
+    let mut cdb = [0u8; 16];
+    cdb[0] = PHISON_CDB;
+    cdb[1] = subcmd;
+    cdb
+}
+
+/// Controller name from the info block; the PS2251 family reports its revision after
+/// the chip number.
+fn chip_name(info: &[u8]) -> String {
+    let chip = u16::from_be_bytes([info[INFO_CHIP], info[INFO_CHIP + 1]]);
+    match chip {
+        0x2251 => format!("Phison PS2251-{:02X}", info[INFO_CHIP + 2]),
+        0x0000 | 0xFFFF => "Phison PS2251".to_string(),
+        other => format!("Phison PS{:04X}", other),
+    }
+}
+
+pub fn read_flash_id(dev: &ScsiDevice) -> Result<FlashIdResult, String> {
+    let mut info = [0u8; INFO_LEN];
+    dev.cdb_read(&vendor_cdb(SUBCMD_INFO), &mut info)
+        .map_err(|e| format!("Phison USB info read failed: {}", e))?;
+
+    let mut buf = [0u8; FLASH_ID_LEN];
+    dev.cdb_read(&vendor_cdb(SUBCMD_FLASH_ID), &mut buf)
+        .map_err(|e| format!("Phison USB flash ID read failed: {}", e))?;
+
+    let banks = extract_banks(&buf, 0, MAX_BANKS);
+
+    Ok(FlashIdResult {
+        controller_name: chip_name(&info),
+        banks,
+    })
+}
@@ -2,9 +2,15 @@ use crate::controllers::{is_bank_empty, FlashBank, FlashIdResult};
 use crate::detect::RtlVariant;
 use crate::nand_db::manufacturer_name;
 use crate::nvme::NvmeDevice;
+use crate::risk::Risk;
+
+/// 0xFC unlock before the 0xFA read
+pub const RISK: Risk = Risk::VendorWrite;
 
 const BANK_SIZE: usize = 8;
 const MAX_BANKS: usize = 64;
+/// V2 reads hang some RTS5772DL firmware; fail fast instead of waiting the full default timeout
+const V2_TIMEOUT_MS: u32 = 3_000;
 
 fn unlock(dev: &NvmeDevice) -> Result<(), String> {
     dev.admin_no_data(0xFC, 0, 0, 0, 0, 0x0050FFFF, 0, 0)
@@ -12,7 +18,24 @@ fn unlock(dev: &NvmeDevice) -> Result<(), String> {
     Ok(())
 }
 
-pub fn read_flash_id(dev: &NvmeDevice, variant: &RtlVariant) -> Result<FlashIdResult, String> {
+/// Detection probe: the V1 flash ID read without the 0xFC unlock. Firmware that keeps
+/// the read locked rejects it or returns zeros; a match needs slot 0 to hold a flash ID
+/// with a known JEDEC manufacturer and a device byte, which is how every V1/V2 response
+/// starts.
+pub fn probe(dev: &NvmeDevice) -> bool {
+    let mut buf = [0u8; 512];
+    dev.admin_read(0xFA, 0, 0x80, 0, 0, 0x00410000, 0, 0, &mut buf).is_ok()
+        && extract_banks(&buf)
+            .first()
+            .is_some_and(|b| b.bank_num == 0 && !matches!(b.flash_id[1], 0x00 | 0xFF))
+}
+
+/// `timeout_ms` is the user's --timeout for the family, which replaces the V2 fail-fast.
+pub fn read_flash_id(
+    dev: &NvmeDevice,
+    variant: &RtlVariant,
+    timeout_ms: Option<u32>,
+) -> Result<FlashIdResult, String> {
     // Always try V1 first — V1 commands work on V2 hardware, but V2 commands
     // can hang some V2 controllers (e.g. RTS5772DL with non-standard firmware).
     let variants: &[(RtlVariant, &str)] = match variant {
@@ -31,9 +54,9 @@ pub fn read_flash_id(dev: &NvmeDevice, variant: &RtlVariant) -> Result<FlashIdRe
             RtlVariant::V1 => {
                 dev.admin_read(0xFA, 0, 0x80, 0, 0, 0x00410000, 0, 0, &mut buf).is_ok()
             }
-            RtlVariant::V2 => {
+            RtlVariant::V2 => dev.with_timeout(timeout_ms.unwrap_or(V2_TIMEOUT_MS), || {
                 dev.admin_read(0xFA, 0, 0x80, 0, 0xAFF03860, 0x00010001, 0, 0, &mut buf).is_ok()
-            }
+            }),
         };
         if !read_ok {
             continue;
@@ -1,5 +1,9 @@
 use crate::ata::AtaDevice;
 use crate::controllers::{is_bank_empty, FlashBank, FlashIdResult};
+use crate::risk::Risk;
+
+/// 0xFC setup command before the 0xFA read
+pub const RISK: Risk = Risk::VendorWrite;
 
 const BANK_SIZE: usize = 8;
 const MAX_BANKS: usize = 8;
@@ -26,6 +30,7 @@ fn fid_prefix(dev: &AtaDevice) -> Result<(), String> {
         0xFC, 0x50, 0xFF, 0x00, 0x00, 0x00, 0xE0,
         0x00, 0xFF, 0x00, 0x00, 0x00,
     )
+    .map_err(String::from)
 }
 
 fn read_fid(dev: &AtaDevice) -> Result<[u8; 512], String> {
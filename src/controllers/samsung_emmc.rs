@@ -0,0 +1,45 @@
+use crate::controllers::{extract_banks, FlashIdResult};
+use crate::mmc::{MmcCmd, MmcDevice, BLOCK_SIZE, MMC_READ_SINGLE_BLOCK};
+use crate::risk::Risk;
+
+/// CMD62 vendor mode entry, then a redirected block read
+pub const RISK: Risk = Risk::VendorWrite;
+
+const MMC_VENDOR_CMD: u32 = 62;
+/// Unlock key that precedes every vendor mode argument
+const VENDOR_KEY: u32 = 0xEFAC_62EC;
+const VENDOR_ENTER: u32 = 0x0000_CCEE;
+const VENDOR_EXIT: u32 = 0x00DE_CCEE;
+/// Block address that returns the NAND ID page while vendor mode is active
+const FLASH_ID_ARG: u32 = 0x0000_2000;
+
+const MAX_BANKS: usize = 16;
+
+pub fn read_flash_id(dev: &MmcDevice) -> Result<FlashIdResult, String> {
+    let mut page = [0u8; BLOCK_SIZE];
+    // One sequence: a regular read slipped in while vendor mode is active would be
+    // redirected too
+    dev.run(&mut [
+        MmcCmd::no_data(MMC_VENDOR_CMD, VENDOR_KEY, true),
+        MmcCmd::no_data(MMC_VENDOR_CMD, VENDOR_ENTER, true),
+        MmcCmd::read(MMC_READ_SINGLE_BLOCK, FLASH_ID_ARG, &mut page),
+        MmcCmd::no_data(MMC_VENDOR_CMD, VENDOR_KEY, true),
+        MmcCmd::no_data(MMC_VENDOR_CMD, VENDOR_EXIT, true),
+    ])
+    .map_err(|e| format!("Samsung vendor mode flash ID read failed: {}", e))?;
+
+    let banks = extract_banks(&page, 0, MAX_BANKS);
+
+    Ok(FlashIdResult {
+        controller_name: "Samsung eMMC".to_string(),
+        banks,
+    })
+}
+
+/// Leaves vendor mode, in case the sequence stopped before its exit commands.
+pub fn teardown(dev: &MmcDevice) -> Result<(), String> {
+    dev.run(&mut [
+        MmcCmd::no_data(MMC_VENDOR_CMD, VENDOR_KEY, true),
+        MmcCmd::no_data(MMC_VENDOR_CMD, VENDOR_EXIT, true),
+    ])
+}
@@ -0,0 +1,27 @@
+use crate::controllers::{extract_banks, FlashIdResult};
+use crate::risk::Risk;
+use crate::scsi::ScsiDevice;
+
+/// Single vendor read CDB
+pub const RISK: Risk = Risk::VendorRead;
+
+const VENDOR_READ: u8 = 0xC0;
+/// Sub-operation selecting the NAND ID table
+const SUBOP_FLASH_ID: u8 = 0x40;
+const RESPONSE_LEN: usize = 512;
+const MAX_BANKS: usize = 32;
+
+pub fn read_flash_id(dev: &ScsiDevice) -> Result<FlashIdResult, String> {
+    let mut buf = [0u8; RESPONSE_LEN];
+    let len = (RESPONSE_LEN as u16).to_be_bytes();
+    let cdb = [VENDOR_READ, SUBOP_FLASH_ID, 0, 0, 0, 0, 0, len[0], len[1], 0];
+    dev.cdb_read(&cdb, &mut buf)
+        .map_err(|e| format!("Samsung UFS flash ID read failed: {}", e))?;
+
+    let banks = extract_banks(&buf, 0, MAX_BANKS);
+
+    Ok(FlashIdResult {
+        controller_name: "Samsung UFS".to_string(),
+        banks,
+    })
+}
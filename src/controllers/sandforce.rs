@@ -1,5 +1,9 @@
 use crate::ata::AtaDevice;
 use crate::controllers::{is_bank_empty, FlashBank, FlashIdResult};
+use crate::risk::Risk;
+
+/// SMART WRITE LOG request to log 0xE0
+pub const RISK: Risk = Risk::VendorWrite;
 
 const BANK_SIZE: usize = 8;
 const MAX_BANKS: usize = 16;
@@ -8,6 +12,7 @@ fn smart_write_log(dev: &AtaDevice, log_addr: u8, payload: &[u8; 512]) -> Result
     // SMART WRITE LOG: Command=0xB0, Features=0xD6, Count=1
     // LBA_Low=log_addr, LBA_Mid=0x4F, LBA_High=0xC2 (SMART signature)
     dev.ata_write(0xB0, 0xD6, 0x01, log_addr, 0x4F, 0xC2, 0x00, payload)
+        .map_err(String::from)
 }
 
 fn smart_read_log(dev: &AtaDevice, log_addr: u8) -> Result<[u8; 512], String> {
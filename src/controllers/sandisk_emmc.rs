@@ -0,0 +1,27 @@
+use crate::controllers::{extract_banks, FlashIdResult};
+use crate::mmc::MmcDevice;
+use crate::risk::Risk;
+
+/// Device report read with GEN_CMD; nothing is written
+pub const RISK: Risk = Risk::VendorRead;
+
+/// GEN_CMD argument of the device report
+const DEVICE_REPORT_ARG: u32 = 0x0000_0010;
+/// Bank count byte, followed by the IDs
+const BANK_COUNT: usize = 0x100;
+const BANK_START: usize = 0x108;
+const MAX_BANKS: usize = 16;
+
+pub fn read_flash_id(dev: &MmcDevice) -> Result<FlashIdResult, String> {
+    let report = dev
+        .gen_cmd_read(DEVICE_REPORT_ARG)
+        .map_err(|e| format!("SanDisk device report read failed: {}", e))?;
+
+    let count = (report[BANK_COUNT] as usize).clamp(1, MAX_BANKS);
+    let banks = extract_banks(&report, BANK_START, count);
+
+    Ok(FlashIdResult {
+        controller_name: "SanDisk/WD iNAND".to_string(),
+        banks,
+    })
+}
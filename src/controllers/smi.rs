@@ -1,5 +1,9 @@
 use crate::controllers::{is_bank_empty, FlashBank, FlashIdResult};
 use crate::nvme::NvmeDevice;
+use crate::risk::Risk;
+
+/// C2 read only
+pub const RISK: Risk = Risk::VendorRead;
 
 const VALID_MANUFACTURER_IDS: &[u8] = &[
     0x01, 0x04, 0x07, 0x20, 0x2C, 0x45, 0x4A, 0x51, 0x89, 0x92, 0x98, 0x9B, 0xAD, 0xB5, 0xC2,
@@ -41,6 +45,17 @@ pub fn read_flash_id(dev: &NvmeDevice) -> Result<FlashIdResult, String> {
     })
 }
 
+/// Detection probe: the read-only C2 flash ID command, matching when the name region
+/// at 0x1F0 holds an SMI part number (e.g. "SM2262EN"). OEM firmware keeps this
+/// region even when the Identify firmware string and VID are rebranded.
+pub fn probe(dev: &NvmeDevice) -> Option<String> {
+    let mut buf = [0u8; 2048];
+    dev.admin_read(0xC2, 0, 0x200, 0, 0x40, 0x01, 0, 0, &mut buf).ok()?;
+    let name = extract_controller_name(&buf[CTRL_NAME_OFFSET..]);
+    let digits = name.strip_prefix("SM")?;
+    digits.starts_with(|c: char| c.is_ascii_digit()).then_some(name)
+}
+
 fn extract_controller_name(data: &[u8]) -> String {
     let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
     let s: String = data[..end]
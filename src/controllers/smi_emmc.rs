@@ -0,0 +1,32 @@
+use crate::controllers::{extract_banks, FlashIdResult};
+use crate::mmc::{MmcDevice, BLOCK_SIZE};
+use crate::risk::Risk;
+
+/// GEN_CMD write of the query code before the table read
+pub const RISK: Risk = Risk::VendorWrite;
+
+// The GEN_CMD arguments and the request layout have not been confirmed against vendor
+// tools or a captured trace, so this reader only runs with --controller smi-emmc.
+
+const REQUEST_ARG: u32 = 0x534D_0000;
+const RESPONSE_ARG: u32 = 0x534D_0001;
+/// Query code of the flash ID table
+const REQ_FLASH_ID: u8 = 0xF0;
+const MAX_BANKS: usize = 16;
+
+pub fn read_flash_id(dev: &MmcDevice) -> Result<FlashIdResult, String> {
+    let mut request = [0u8; BLOCK_SIZE];
+    request[0] = REQ_FLASH_ID;
+    dev.gen_cmd_write(REQUEST_ARG, &request)
+        .map_err(|e| format!("SMI flash ID request failed: {}", e))?;
+    let response = dev
+        .gen_cmd_read(RESPONSE_ARG)
+        .map_err(|e| format!("SMI flash ID read failed: {}", e))?;
+
+    let banks = extract_banks(&response, 0, MAX_BANKS);
+
+    Ok(FlashIdResult {
+        controller_name: "Silicon Motion eMMC (SM27xx)".to_string(),
+        banks,
+    })
+}
@@ -1,5 +1,9 @@
 use crate::ata::AtaDevice;
 use crate::controllers::{is_bank_empty, FlashBank, FlashIdResult};
+use crate::risk::Risk;
+
+/// SMART read, then READ SECTORS of magic user LBAs
+pub const RISK: Risk = Risk::UserLbaRead;
 
 const BANK_SIZE: usize = 8;
 const MAX_BANKS: usize = 16;
@@ -0,0 +1,39 @@
+use crate::controllers::{extract_banks, FlashIdResult};
+use crate::risk::Risk;
+use crate::scsi::ScsiDevice;
+
+/// 0xF0 read; leaves the stick state alone
+pub const RISK: Risk = Risk::VendorRead;
+
+/// SMI USB vendor read command
+const SMI_CDB: u8 = 0xF0;
+const SUBCMD_FLASH_ID: u8 = 0x0A;
+const RESPONSE_LEN: usize = 512;
+const MAX_BANKS: usize = 32;
+/// Controller model string (e.g. "SM3257ENLT"), NUL-padded
+const NAME_OFFSET: usize = 0x1F0;
+const NAME_LEN: usize = 16;
+
+pub fn read_flash_id(dev: &ScsiDevice) -> Result<FlashIdResult, String> {
+    let mut buf = [0u8; RESPONSE_LEN];
+    let len = (RESPONSE_LEN as u16).to_be_bytes();
+    let cdb = [SMI_CDB, SUBCMD_FLASH_ID, 0, 0, 0, 0, 0, 0, len[0], len[1], 0, 0, 0, 0, 0, 0];
+    dev.cdb_read(&cdb, &mut buf)
+        .map_err(|e| format!("SMI USB flash ID read failed: {}", e))?;
+
+    let banks = extract_banks(&buf[..NAME_OFFSET], 0, MAX_BANKS);
+
+    let name = String::from_utf8_lossy(&buf[NAME_OFFSET..NAME_OFFSET + NAME_LEN])
+        .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+        .to_string();
+    let controller_name = if name.starts_with("SM3") {
+        format!("SMI {}", name)
+    } else {
+        "SMI SM32xx".to_string()
+    };
+
+    Ok(FlashIdResult {
+        controller_name,
+        banks,
+    })
+}
@@ -1,5 +1,9 @@
 use crate::controllers::{is_bank_empty, FlashBank, FlashIdResult};
 use crate::nvme::NvmeDevice;
+use crate::risk::Risk;
+
+/// C1 config write before the C2 read
+pub const RISK: Risk = Risk::VendorWrite;
 
 const BANK_START: usize = 0x50;
 const BANK_SIZE: usize = 8;
@@ -0,0 +1,514 @@
+use std::path::Path;
+
+use crate::risk::Risk;
+
+/// Directory searched for definitions in addition to --defs.
+pub const SYSTEM_DEFS_DIR: &str = "/etc/ssd-flash-id/defs";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefTransport {
+    Nvme,
+    Ata,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefDir {
+    None,
+    In,
+    Out,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DetectRule {
+    FirmwarePrefix(String),
+    ModelEquals(String),
+    ModelContains(String),
+    /// Matches the Identify VID or SSVID (NVMe only)
+    Vid(u16),
+}
+
+impl std::fmt::Display for DetectRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DetectRule::FirmwarePrefix(p) => write!(f, "fw_prefix = {}", p),
+            DetectRule::ModelEquals(m) => write!(f, "model = {}", m),
+            DetectRule::ModelContains(m) => write!(f, "model_contains = {}", m),
+            DetectRule::Vid(v) => write!(f, "vid = 0x{:04x}", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DefCommand {
+    /// NVMe admin opcode, or ATA command register
+    pub opcode: u8,
+    pub nsid: u32,
+    /// cdw10..cdw15
+    pub cdw: [u32; 6],
+    pub features: u8,
+    pub count: u8,
+    pub lba_low: u8,
+    pub lba_mid: u8,
+    pub lba_high: u8,
+    pub device: u8,
+    /// ATA data-in via the DMA protocol instead of PIO
+    pub dma: bool,
+    pub dir: DefDir,
+    pub len: usize,
+    /// (offset, byte) pairs written into an otherwise zeroed data-out buffer
+    pub payload: Vec<(usize, u8)>,
+    /// Signature the response must contain at an offset, otherwise the read fails
+    pub expect: Option<(usize, Vec<u8>)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BankFilter {
+    /// Skip all-0x00 / all-0xFF slots
+    pub nonempty: bool,
+    /// Skip slots whose first byte is not a known JEDEC manufacturer
+    pub jedec: bool,
+    /// Stop at the first empty slot instead of skipping it
+    pub stop_at_empty: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseLayout {
+    /// Index into `commands` of the response holding the banks (default: last data-in)
+    pub response: Option<usize>,
+    pub bank_start: usize,
+    pub bank_size: usize,
+    pub bank_count: usize,
+    pub filter: BankFilter,
+    /// Controller name read as NUL-terminated ASCII from (response, offset, max len)
+    pub name: Option<(Option<usize>, usize, usize)>,
+}
+
+/// A controller family described by data: detection rules, the command sequence to
+/// send, and where the banks and controller name are in the response.
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub display: String,
+    pub transport: DefTransport,
+    pub controller: String,
+    pub risk: Risk,
+    pub detect: Vec<DetectRule>,
+    pub commands: Vec<DefCommand>,
+    /// Sent after the read, even when it failed, to restore normal operation
+    pub teardown: Vec<DefCommand>,
+    pub parse: ParseLayout,
+}
+
+impl Definition {
+    pub fn matches(&self, firmware: &str, model: &str, vid: u16, ssvid: u16) -> bool {
+        self.matching_rule(firmware, model, vid, ssvid).is_some()
+    }
+
+    /// First detect rule that matches the identify data. Firmware and model rules are
+    /// preferred over `vid =` rules, which match every drive of the OEM.
+    pub fn matching_rule(&self, firmware: &str, model: &str, vid: u16, ssvid: u16) -> Option<&DetectRule> {
+        let matches = |rule: &&DetectRule| match rule {
+            DetectRule::FirmwarePrefix(p) => firmware.starts_with(p.as_str()),
+            DetectRule::ModelEquals(m) => model == m,
+            DetectRule::ModelContains(m) => model.contains(m.as_str()),
+            DetectRule::Vid(v) => self.transport == DefTransport::Nvme && (vid == *v || ssvid == *v),
+        };
+        let mut matched = self.detect.iter().filter(matches);
+        let first = matched.next()?;
+        match first {
+            DetectRule::Vid(_) => Some(matched.find(|r| !matches!(r, DetectRule::Vid(_))).unwrap_or(first)),
+            _ => Some(first),
+        }
+    }
+
+    /// Index of the command whose response holds the banks.
+    pub fn bank_response(&self) -> Option<usize> {
+        self.parse
+            .response
+            .or_else(|| self.commands.iter().rposition(|c| c.dir == DefDir::In))
+    }
+}
+
+/// Loads every *.def file from the given paths (files or directories). Missing
+/// directories are skipped so the default system directory is optional, and a file there
+/// that fails to parse is skipped with a warning; only --defs paths are fatal.
+pub fn load(paths: &[String]) -> Result<Vec<Definition>, String> {
+    let mut defs = Vec::new();
+    for path in paths {
+        let p = Path::new(path);
+        if p.is_dir() {
+            let mut files: Vec<_> = std::fs::read_dir(p)
+                .map_err(|e| format!("failed to read '{}': {}", path, e))?
+                .flatten()
+                .map(|e| e.path())
+                .filter(|f| f.extension().is_some_and(|ext| ext == "def"))
+                .collect();
+            files.sort();
+            for f in files {
+                match load_file(&f) {
+                    Ok(def) => defs.push(def),
+                    Err(e) if path == SYSTEM_DEFS_DIR => eprintln!("warning: skipping {}", e),
+                    Err(e) => return Err(e),
+                }
+            }
+        } else if p.exists() {
+            defs.push(load_file(p)?);
+        } else if path != SYSTEM_DEFS_DIR {
+            return Err(format!("definition path '{}' does not exist", path));
+        }
+    }
+    Ok(defs)
+}
+
+fn load_file(path: &Path) -> Result<Definition, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read '{}': {}", path.display(), e))?;
+    parse(&text).map_err(|e| format!("{}:{}", path.display(), e))
+}
+
+/// Parses the definition format: top-level `key = value` lines, then a [detect]
+/// section, one [command] section per command in send order, a [parse] section, and
+/// optional [teardown] commands.
+pub fn parse(text: &str) -> Result<Definition, String> {
+    let mut def = Definition {
+        name: String::new(),
+        display: String::new(),
+        transport: DefTransport::Nvme,
+        controller: String::new(),
+        risk: Risk::VendorWrite,
+        detect: Vec::new(),
+        commands: Vec::new(),
+        teardown: Vec::new(),
+        parse: ParseLayout {
+            response: None,
+            bank_start: 0,
+            bank_size: 8,
+            bank_count: 0,
+            filter: BankFilter::default(),
+            name: None,
+        },
+    };
+    let mut section = String::new();
+    let mut name_response = None;
+    let mut name_offset = None;
+    let mut name_len = 16;
+
+    for (n, raw) in text.lines().enumerate() {
+        let line_no = n + 1;
+        let line = raw.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            match section.as_str() {
+                "detect" | "parse" => {}
+                "command" => def.commands.push(new_command(def.transport)),
+                "teardown" => def.teardown.push(new_command(def.transport)),
+                other => return Err(format!("{}: unknown section [{}]", line_no, other)),
+            }
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .map(|(k, v)| (k.trim(), v.trim()))
+            .ok_or_else(|| format!("{}: expected 'key = value'", line_no))?;
+        let err = |e: String| format!("{}: {}: {}", line_no, key, e);
+
+        match section.as_str() {
+            "" => match key {
+                "name" => def.name = value.to_string(),
+                "display" => def.display = value.to_string(),
+                "controller" => def.controller = value.to_string(),
+                "transport" => {
+                    def.transport = match value {
+                        "nvme" => DefTransport::Nvme,
+                        "ata" => DefTransport::Ata,
+                        _ => return Err(err("expected nvme or ata".into())),
+                    }
+                }
+                "risk" => {
+                    def.risk = Risk::from_level(value)
+                        .ok_or_else(|| err(format!("expected {}", crate::risk::LEVEL_NAMES)))?
+                }
+                _ => return Err(err("unknown key".into())),
+            },
+            "detect" => def.detect.push(match key {
+                "fw_prefix" => DetectRule::FirmwarePrefix(value.to_string()),
+                "model" => DetectRule::ModelEquals(value.to_string()),
+                "model_contains" => DetectRule::ModelContains(value.to_string()),
+                "vid" => DetectRule::Vid(parse_int(value).map_err(err)?),
+                _ => return Err(err("unknown detect rule".into())),
+            }),
+            "command" | "teardown" => {
+                let list = if section == "command" { &mut def.commands } else { &mut def.teardown };
+                let cmd = list.last_mut().expect("the section header pushes an entry");
+                match key {
+                    "opcode" | "command" => cmd.opcode = parse_int(value).map_err(err)?,
+                    "nsid" => cmd.nsid = parse_int(value).map_err(err)?,
+                    "cdw10" | "cdw11" | "cdw12" | "cdw13" | "cdw14" | "cdw15" => {
+                        let i: usize = key[3..].parse::<usize>().unwrap() - 10;
+                        cmd.cdw[i] = parse_int(value).map_err(err)?;
+                    }
+                    "features" => cmd.features = parse_int(value).map_err(err)?,
+                    "count" => cmd.count = parse_int(value).map_err(err)?,
+                    "lba_low" => cmd.lba_low = parse_int(value).map_err(err)?,
+                    "lba_mid" => cmd.lba_mid = parse_int(value).map_err(err)?,
+                    "lba_high" => cmd.lba_high = parse_int(value).map_err(err)?,
+                    "device" => cmd.device = parse_int(value).map_err(err)?,
+                    "protocol" => {
+                        cmd.dma = match value {
+                            "pio" => false,
+                            "dma" => true,
+                            _ => return Err(err("expected pio or dma".into())),
+                        }
+                    }
+                    "dir" => {
+                        cmd.dir = match value {
+                            "none" => DefDir::None,
+                            "in" => DefDir::In,
+                            "out" => DefDir::Out,
+                            _ => return Err(err("expected none, in or out".into())),
+                        }
+                    }
+                    "len" => cmd.len = parse_int(value).map_err(err)?,
+                    "payload" => {
+                        for pair in value.split_whitespace() {
+                            let (off, byte) = pair
+                                .split_once(':')
+                                .ok_or_else(|| err(format!("expected OFFSET:BYTE, got '{}'", pair)))?;
+                            let off = parse_hex_int(off).map_err(err)?;
+                            let byte = parse_hex_int(byte).map_err(err)?;
+                            cmd.payload.push((off, byte));
+                        }
+                    }
+                    "expect" => {
+                        let (off, text) = value
+                            .split_once(':')
+                            .ok_or_else(|| err("expected OFFSET:TEXT".into()))?;
+                        cmd.expect = Some((parse_int(off).map_err(err)?, text.as_bytes().to_vec()));
+                    }
+                    _ => return Err(err("unknown command key".into())),
+                }
+            }
+            "parse" => {
+                match key {
+                    "response" => def.parse.response = Some(parse_int(value).map_err(err)?),
+                    "bank_start" => def.parse.bank_start = parse_int(value).map_err(err)?,
+                    "bank_size" => def.parse.bank_size = parse_int(value).map_err(err)?,
+                    "bank_count" => def.parse.bank_count = parse_int(value).map_err(err)?,
+                    "filter" => {
+                        for f in value.split(|c: char| c == ',' || c.is_whitespace()).filter(|f| !f.is_empty()) {
+                            match f {
+                                "nonempty" => def.parse.filter.nonempty = true,
+                                "jedec" => def.parse.filter.jedec = true,
+                                "stop_at_empty" => def.parse.filter.stop_at_empty = true,
+                                _ => return Err(err(format!("unknown filter '{}'", f))),
+                            }
+                        }
+                    }
+                    "name_response" => name_response = Some(parse_int(value).map_err(err)?),
+                    "name_offset" => name_offset = Some(parse_int(value).map_err(err)?),
+                    "name_len" => name_len = parse_int(value).map_err(err)?,
+                    _ => return Err(err("unknown parse key".into())),
+                }
+            }
+            _ => unreachable!("sections are validated when opened"),
+        }
+    }
+
+    def.parse.name = name_offset.map(|off| (name_response, off, name_len));
+    if def.display.is_empty() {
+        def.display = def.name.clone();
+    }
+    if def.controller.is_empty() {
+        def.controller = def.display.clone();
+    }
+    validate(&def)?;
+    Ok(def)
+}
+
+fn new_command(transport: DefTransport) -> DefCommand {
+    DefCommand {
+        opcode: 0,
+        nsid: 0,
+        cdw: [0; 6],
+        features: 0,
+        count: 0,
+        lba_low: 0,
+        lba_mid: 0,
+        lba_high: 0,
+        device: if transport == DefTransport::Ata { 0xE0 } else { 0 },
+        dma: false,
+        dir: DefDir::None,
+        len: 0,
+        payload: Vec::new(),
+        expect: None,
+    }
+}
+
+/// ATA commands that read user LBAs (READ SECTOR(S), READ DMA, READ VERIFY and their
+/// EXT forms, READ FPDMA QUEUED).
+const ATA_LBA_READS: &[u8] = &[0x20, 0x21, 0x24, 0x25, 0x29, 0x40, 0x42, 0x60, 0xC4, 0xC8, 0xC9];
+
+/// Lowest risk level the command sequence needs: anything that sends data or is sent only
+/// for its side effect (a data-less vendor command, every teardown) is a vendor write,
+/// and an ATA read of user LBAs is a user-LBA read.
+pub fn min_risk(def: &Definition) -> Risk {
+    let all = || def.commands.iter().chain(&def.teardown);
+    if def.transport == DefTransport::Ata && all().any(|c| ATA_LBA_READS.contains(&c.opcode)) {
+        Risk::UserLbaRead
+    } else if !def.teardown.is_empty() || all().any(|c| c.dir != DefDir::In) {
+        Risk::VendorWrite
+    } else {
+        Risk::VendorRead
+    }
+}
+
+fn validate(def: &Definition) -> Result<(), String> {
+    if def.name.is_empty() {
+        return Err(" missing 'name'".to_string());
+    }
+    if def.teardown.iter().any(|c| c.dir == DefDir::In) {
+        return Err(format!(" {}: teardown commands cannot read data", def.name));
+    }
+    let min = min_risk(def);
+    if def.risk < min {
+        return Err(format!(
+            " {}: risk = {} is too low for its commands (needs at least {})",
+            def.name,
+            def.risk.level_name(),
+            min.level_name()
+        ));
+    }
+    if def.commands.is_empty() {
+        return Err(format!(" {}: no [command] sections", def.name));
+    }
+    if !(1..=8).contains(&def.parse.bank_size) || def.parse.bank_count == 0 {
+        return Err(format!(" {}: bank_size must be 1-8 and bank_count > 0", def.name));
+    }
+    for (i, cmd) in def.commands.iter().chain(&def.teardown).enumerate() {
+        if cmd.dir != DefDir::None && cmd.len == 0 {
+            return Err(format!(" {}: command {} has a data direction but len = 0", def.name, i));
+        }
+        if let Some(&(off, _)) = cmd.payload.iter().find(|&&(off, _)| off >= cmd.len) {
+            return Err(format!(" {}: command {} payload offset 0x{:x} is past len", def.name, i, off));
+        }
+    }
+    let Some(resp) = def.bank_response() else {
+        return Err(format!(" {}: no data-in command to parse banks from", def.name));
+    };
+    let cmd = def
+        .commands
+        .get(resp)
+        .filter(|c| c.dir == DefDir::In)
+        .ok_or_else(|| format!(" {}: response {} is not a data-in command", def.name, resp))?;
+    let end = def.parse.bank_start + def.parse.bank_size * def.parse.bank_count;
+    if end > cmd.len {
+        return Err(format!(
+            " {}: banks end at 0x{:x}, past the {} byte response",
+            def.name, end, cmd.len
+        ));
+    }
+    Ok(())
+}
+
+fn parse_int<T: TryFrom<u64>>(s: &str) -> Result<T, String> {
+    let n = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("invalid number '{}'", s))?;
+    T::try_from(n).map_err(|_| format!("'{}' is out of range", s))
+}
+
+/// Payload offsets and bytes are always hex, with or without 0x.
+fn parse_hex_int<T: TryFrom<u64>>(s: &str) -> Result<T, String> {
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    let n = u64::from_str_radix(hex, 16).map_err(|_| format!("invalid hex number '{}'", s))?;
+    T::try_from(n).map_err(|_| format!("'{}' is out of range", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shipped_definitions_parse() {
+        for text in [
+            include_str!("../defs/smi.def"),
+            include_str!("../defs/tenafe.def"),
+            include_str!("../defs/marvell.def"),
+            include_str!("../defs/realtek-v1.def"),
+            include_str!("../defs/sandforce.def"),
+        ] {
+            let def = parse(text).unwrap();
+            assert!(!def.detect.is_empty(), "{} has no detect rules", def.name);
+        }
+    }
+
+    #[test]
+    fn test_tenafe_layout() {
+        let def = parse(include_str!("../defs/tenafe.def")).unwrap();
+        assert_eq!(def.transport, DefTransport::Nvme);
+        assert_eq!(def.commands.len(), 2);
+        assert_eq!(def.commands[0].dir, DefDir::Out);
+        assert_eq!(def.commands[0].payload[1], (0x02, 0x0C));
+        assert_eq!(def.bank_response(), Some(1));
+        assert_eq!(def.parse.bank_start, 0x50);
+        assert!(def.matches("", "Merak Nvme Ssd Controller", 0, 0));
+    }
+
+    #[test]
+    fn test_bank_extraction() {
+        let def = parse(include_str!("../defs/smi.def")).unwrap();
+        assert!(def.matches("8366ROM", "", 0, 0) && def.matches("", "OEM SM2508 SSD", 0, 0));
+        // A model rule wins over a VID rule listed before it
+        let vid_first = parse(
+            "name = x\nrisk = read\n[detect]\nvid = 0x2646\nmodel_contains = SM25\n\
+             [command]\nopcode = 0xc2\ndir = in\nlen = 16\n[parse]\nbank_count = 2\n",
+        )
+        .unwrap();
+        assert!(matches!(vid_first.matching_rule("", "OEM SM2508 SSD", 0x2646, 0), Some(DetectRule::ModelContains(_))));
+        assert!(matches!(vid_first.matching_rule("", "KINGSTON SNV2S", 0x2646, 0), Some(DetectRule::Vid(0x2646))));
+        let mut buf = vec![0u8; 2048];
+        buf[0x30..0x38].copy_from_slice(&[0x2C, 0x84, 0x64, 0x54, 0xA9, 0x00, 0x00, 0x00]);
+        buf[0x38] = 0x13; // not a JEDEC manufacturer
+        buf[0x48..0x50].copy_from_slice(&[0x98, 0x3C, 0x98, 0xB3, 0x76, 0x72, 0x08, 0x1C]);
+        buf[0x1F0..0x1F6].copy_from_slice(b"SM2262");
+        let result = crate::controllers::defined::parse_result(&def, &[buf]).unwrap();
+        assert_eq!(result.controller_name, "SM2262");
+        let nums: Vec<u32> = result.banks.iter().map(|b| b.bank_num).collect();
+        assert_eq!(nums, [0, 3]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("name = x\n[command]\nopcode = 0xc2\n").is_err());
+        assert!(parse("name = x\n[bogus]\n").unwrap_err().contains("unknown section"));
+        let e = parse("name = x\n[command]\ndir = in\nlen = 16\n[parse]\nbank_count = 4\nbank_start = 0x10\n")
+            .unwrap_err();
+        assert!(e.contains("past the 16 byte response"), "{}", e);
+        // Values are range-checked instead of truncated
+        let e = parse("name = x\n[command]\nopcode = 0x1c2\n").unwrap_err();
+        assert!(e.contains("out of range"), "{}", e);
+        let e = parse("name = x\n[command]\npayload = 0:100\n").unwrap_err();
+        assert!(e.contains("out of range"), "{}", e);
+    }
+
+    #[test]
+    fn test_min_risk() {
+        let read = "name = x\nrisk = read\n[command]\nopcode = 0xc2\ndir = in\nlen = 16\n\
+                    [parse]\nbank_count = 2\n";
+        assert_eq!(min_risk(&parse(read).unwrap()), Risk::VendorRead);
+        // A data-out request, or a teardown, needs at least write
+        let e = parse(&read.replace("[command]", "[command]\ndir = out\nlen = 16\n[command]")).unwrap_err();
+        assert!(e.contains("needs at least write"), "{}", e);
+        let e = parse(&format!("{}[teardown]\nopcode = 0xfc\n", read)).unwrap_err();
+        assert!(e.contains("needs at least write"), "{}", e);
+        // READ DMA EXT on an ATA definition reads user LBAs
+        let ata = read.replace("risk = read", "transport = ata\nrisk = write").replace("0xc2", "0x25");
+        assert!(parse(&ata).unwrap_err().contains("needs at least all"));
+        assert_eq!(min_risk(&parse(&ata.replace("risk = write", "risk = all")).unwrap()), Risk::UserLbaRead);
+    }
+}
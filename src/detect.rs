@@ -1,4 +1,7 @@
-use crate::nvme::NvmeDevice;
+use crate::defs::{DefTransport, Definition, DetectRule};
+use crate::drive_db::DriveEntry;
+use crate::nvme::{HmbState, NvmeDevice};
+use crate::risk::Risk;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RtlVariant {
@@ -15,6 +18,8 @@ pub enum ControllerType {
     Marvell(String),
     Innogrit(String),
     Tenafe(String),
+    /// Family loaded from a definition file
+    Defined(Box<Definition>),
 }
 
 impl ControllerType {
@@ -27,6 +32,7 @@ impl ControllerType {
             | ControllerType::Marvell(n)
             | ControllerType::Innogrit(n)
             | ControllerType::Tenafe(n) => n,
+            ControllerType::Defined(def) => &def.controller,
         }
     }
 
@@ -39,6 +45,7 @@ impl ControllerType {
             ControllerType::Marvell(_) => "marvell",
             ControllerType::Innogrit(_) => "innogrit",
             ControllerType::Tenafe(_) => "tenafe",
+            ControllerType::Defined(def) => &def.name,
         }
     }
 }
@@ -70,41 +77,331 @@ const RTL_FW_PREFIXES: &[(&str, &str, RtlVariant)] = &[
     ("REALTEK_RL6817", "RTS5772", RtlVariant::V2),
 ];
 
+#[derive(Clone, Copy)]
+enum PciFamily {
+    Smi,
+    Realtek(RtlVariant),
+    Phison,
+    Maxio,
+    Marvell,
+    Innogrit,
+}
+
+/// PCI vendor:device IDs of the controller silicon, which OEM drives keep even when
+/// the Identify VID/SSVID and firmware string carry the brand.
+const PCI_IDS: &[(u16, u16, &str, PciFamily)] = &[
+    (0x126F, 0x2260, "SM2260", PciFamily::Smi),
+    (0x126F, 0x2262, "SM2262/SM2262EN", PciFamily::Smi),
+    (0x126F, 0x2263, "SM2263EN/SM2263XT", PciFamily::Smi),
+    (0x10EC, 0x5762, "RTS5762", PciFamily::Realtek(RtlVariant::V1)),
+    (0x10EC, 0x5763, "RTS5763DL", PciFamily::Realtek(RtlVariant::V1)),
+    (0x10EC, 0x5765, "RTS5765DL", PciFamily::Realtek(RtlVariant::V2)),
+    (0x10EC, 0x5772, "RTS5772DL", PciFamily::Realtek(RtlVariant::V2)),
+    (0x1987, 0x5012, "PS5012 (E12)", PciFamily::Phison),
+    (0x1987, 0x5016, "PS5016 (E16)", PciFamily::Phison),
+    (0x1987, 0x5018, "PS5018 (E18)", PciFamily::Phison),
+    (0x1987, 0x5019, "PS5019 (E19T)", PciFamily::Phison),
+    (0x1987, 0x5021, "PS5021 (E21T)", PciFamily::Phison),
+    (0x1987, 0x5026, "PS5026 (E26)", PciFamily::Phison),
+    (0x1987, 0x5027, "PS5027 (E27T)", PciFamily::Phison),
+    (0x1E4B, 0x1001, "MAP1001", PciFamily::Maxio),
+    (0x1E4B, 0x1002, "MAP1002", PciFamily::Maxio),
+    (0x1E4B, 0x1202, "MAP1202", PciFamily::Maxio),
+    (0x1E4B, 0x1602, "MAP1602", PciFamily::Maxio),
+    (0x1B4B, 0x1160, "88NV1160", PciFamily::Marvell),
+    (0x1DBE, 0x5216, "IG5216", PciFamily::Innogrit),
+    (0x1DBE, 0x5220, "IG5220", PciFamily::Innogrit),
+    (0x1DBE, 0x5236, "IG5236", PciFamily::Innogrit),
+];
+
 const SMI_VID: u16 = 0x2646;
 const RTL_VID: u16 = 0x10EC;
 const PHISON_VID: u16 = 0x1987;
 const MAXIO_VID: u16 = 0x1E4B;
 
-fn detect_realtek(fw: &str, vid: u16, ssvid: u16) -> Option<ControllerType> {
-    for &(prefix, name, variant) in RTL_FW_PREFIXES {
-        if fw.starts_with(prefix) {
-            return Some(ControllerType::Realtek(name.to_string(), variant));
+/// IEEE OUIs the controller vendors' reference firmware reports in Identify Controller,
+/// which OEM drives with a rebranded VID often keep.
+const CONTROLLER_OUIS: &[(u32, &str)] = &[(0x6479A7, "phison"), (0x00E04C, "rtl")];
+
+/// DRAM-less controllers (HMB or SRAM only), by controller name token.
+const DRAMLESS_CONTROLLERS: &[&str] = &[
+    "SM2263XT", "SM2267XT", "SM2268XT", "SM2269XT", "RTS5763", "RTS5763DL", "RTS5765DL",
+    "RTS5766DL", "RTS5772DL", "MAP1202", "MAP1602", "MAP1802", "PS5013", "E13T", "PS5019",
+    "E19T", "PS5021", "E21T", "PS5027", "E27T", "PS5031", "E31T", "IG5216", "IG5220",
+    "88NV1140", "88NV1160", "TC2200", "TC2201",
+];
+
+/// Controllers with an external DRAM interface that OEMs populate.
+const DRAM_CONTROLLERS: &[&str] = &[
+    "SM2260", "SM2262", "SM2262EN", "SM2263EN", "SM2264", "SM2508", "RTS5762", "MAP1001",
+    "PS5012", "E12", "PS5016", "E16", "PS5018", "E18", "PS5026", "E26", "IG5236", "IG5266",
+];
+
+/// How strongly the evidence points at the chosen family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// Only the PCI vendor ID or IEEE OUI matched; OEM drives often reuse another vendor's IDs
+    Low,
+    /// Model string match, or a drive database entry without a firmware pattern
+    Medium,
+    /// PCI device ID, firmware prefix or a vendor signature returned by the controller
+    High,
+}
+
+impl Confidence {
+    pub fn name(self) -> &'static str {
+        match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
         }
     }
-    if vid == RTL_VID || ssvid == RTL_VID {
-        return Some(ControllerType::Realtek("Realtek (by VID)".into(), RtlVariant::V1));
+}
+
+/// One heuristic evaluated during detection and what it found.
+pub struct Evidence {
+    pub heuristic: String,
+    pub found: String,
+    pub matched: bool,
+}
+
+fn note(ev: &mut Vec<Evidence>, heuristic: &str, found: String, matched: bool) {
+    ev.push(Evidence {
+        heuristic: heuristic.to_string(),
+        found,
+        matched,
+    });
+}
+
+fn vid_matches(ev: &mut Vec<Evidence>, family: &str, want: u16, vid: u16, ssvid: u16) -> bool {
+    let matched = vid == want || ssvid == want;
+    note(
+        ev,
+        &format!("{} VID/SSVID 0x{:04x}", family, want),
+        format!("vid 0x{:04x}, ssvid 0x{:04x}", vid, ssvid),
+        matched,
+    );
+    matched
+}
+
+/// DRAM from the controller name: Some(true) for a DRAM controller, Some(false) for a
+/// DRAM-less one, None when the name is unknown or ambiguous ("SM2263EN/SM2263XT"). A
+/// bare number after a slash replaces the previous part's last digits, so "RTS5762/63"
+/// names both the RTS5762 and the RTS5763.
+fn controller_has_dram(controller: &str) -> Option<bool> {
+    let upper = controller.to_ascii_uppercase();
+    let mut tokens: Vec<String> = Vec::new();
+    for part in upper.split(|c: char| !c.is_ascii_alphanumeric()).filter(|t| !t.is_empty()) {
+        let expanded = match tokens.last() {
+            Some(prev)
+                if part.bytes().all(|b| b.is_ascii_digit())
+                    && part.len() < prev.len()
+                    && prev.bytes().rev().take(part.len()).all(|b| b.is_ascii_digit()) =>
+            {
+                format!("{}{}", &prev[..prev.len() - part.len()], part)
+            }
+            _ => part.to_string(),
+        };
+        tokens.push(expanded);
+    }
+    let dramless = tokens.iter().any(|t| DRAMLESS_CONTROLLERS.contains(&t.as_str()));
+    let dram = tokens.iter().any(|t| DRAM_CONTROLLERS.contains(&t.as_str()));
+    match (dram, dramless) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// "DRAM" or "DRAM-less (...)" for the header. A DRAM-less controller model decides on
+/// its own; otherwise a drive that asks for a Host Memory Buffer (HMPRE > 0) is DRAM-less,
+/// even on a controller that has a DRAM interface the OEM may have left unpopulated.
+/// None when neither tells.
+pub fn dram_status(controller: &str, hmpre: u32, hmb: Option<&HmbState>) -> Option<String> {
+    let has_dram = match controller_has_dram(controller) {
+        Some(false) => false,
+        _ if hmpre > 0 => false,
+        Some(true) => true,
+        None => return None,
+    };
+    if has_dram {
+        return Some("DRAM".to_string());
+    }
+    let detail = match hmb {
+        _ if hmpre == 0 => "no HMB".to_string(),
+        Some(h) if h.enabled => format!("HMB {} MiB enabled", h.size / (1024 * 1024)),
+        Some(_) => "HMB disabled".to_string(),
+        None => "HMB state unknown".to_string(),
+    };
+    Some(format!("DRAM-less ({})", detail))
+}
+
+/// Last-resort match on the Identify Controller IEEE OUI.
+fn detect_oui(oui: u32, ev: &mut Vec<Evidence>) -> Option<(ControllerType, Confidence)> {
+    let hit = CONTROLLER_OUIS.iter().find(|&&(o, _)| o == oui);
+    note(
+        ev,
+        "controller vendor IEEE OUI (Phison 6479a7, Realtek 00e04c)",
+        format!("ieee oui {:06x}", oui),
+        hit.is_some(),
+    );
+    let ct = match hit?.1 {
+        "phison" => ControllerType::Phison("Phison (by OUI)".into()),
+        _ => ControllerType::Realtek("Realtek (by OUI)".into(), RtlVariant::V1),
+    };
+    Some((ct, Confidence::Low))
+}
+
+fn detect_realtek(
+    fw: &str,
+    vid: u16,
+    ssvid: u16,
+    ev: &mut Vec<Evidence>,
+) -> Option<(ControllerType, Confidence)> {
+    let prefix = RTL_FW_PREFIXES.iter().find(|(p, _, _)| fw.starts_with(p));
+    note(
+        ev,
+        "Realtek firmware prefix",
+        match prefix {
+            Some((p, _, _)) => format!("firmware '{}' starts with '{}'", fw, p),
+            None => format!("firmware '{}' has no REALTEK_RL* prefix", fw),
+        },
+        prefix.is_some(),
+    );
+    if let Some(&(_, name, variant)) = prefix {
+        return Some((ControllerType::Realtek(name.to_string(), variant), Confidence::High));
+    }
+    if vid_matches(ev, "Realtek", RTL_VID, vid, ssvid) {
+        return Some((
+            ControllerType::Realtek("Realtek (by VID)".into(), RtlVariant::V1),
+            Confidence::Low,
+        ));
     }
     None
 }
 
-fn detect_smi(fw: &str, model: &str, vid: u16, ssvid: u16) -> Option<ControllerType> {
-    for &(prefix, name) in SMI_FW_PREFIXES {
-        if fw.starts_with(prefix) {
-            return Some(ControllerType::Smi(name.to_string()));
-        }
+fn detect_smi(
+    fw: &str,
+    model: &str,
+    vid: u16,
+    ssvid: u16,
+    ev: &mut Vec<Evidence>,
+) -> Option<(ControllerType, Confidence)> {
+    let prefix = SMI_FW_PREFIXES.iter().find(|(p, _)| fw.starts_with(p));
+    note(
+        ev,
+        "SMI firmware prefix",
+        match prefix {
+            Some((p, _)) => format!("firmware '{}' starts with '{}'", fw, p),
+            None => format!("firmware '{}' matches no SMI prefix", fw),
+        },
+        prefix.is_some(),
+    );
+    if let Some(&(_, name)) = prefix {
+        return Some((ControllerType::Smi(name.to_string()), Confidence::High));
     }
-    if model.contains("SM22") || model.contains("SM25") || model.contains("SM83") {
-        return Some(ControllerType::Smi("SMI (by model)".to_string()));
+    let substr = ["SM22", "SM25", "SM83"].into_iter().find(|s| model.contains(s));
+    note(
+        ev,
+        "SMI model substring (SM22/SM25/SM83)",
+        match substr {
+            Some(s) => format!("model '{}' contains '{}'", model, s),
+            None => format!("model '{}'", model),
+        },
+        substr.is_some(),
+    );
+    if substr.is_some() {
+        return Some((ControllerType::Smi("SMI (by model)".to_string()), Confidence::Medium));
     }
-    if vid == SMI_VID || ssvid == SMI_VID {
-        return Some(ControllerType::Smi("SMI (by VID)".to_string()));
+    if vid_matches(ev, "SMI", SMI_VID, vid, ssvid) {
+        return Some((ControllerType::Smi("SMI (by VID)".to_string()), Confidence::Low));
     }
     None
 }
 
-fn detect_tenafe(model: &str) -> Option<ControllerType> {
-    if model == "Merak Nvme Ssd Controller" {
-        return Some(ControllerType::Tenafe("Merak".to_string()));
+fn detect_pci(
+    pci: Option<&crate::sysfs::PciInfo>,
+    ev: &mut Vec<Evidence>,
+) -> Option<(ControllerType, Confidence)> {
+    let Some(pci) = pci else {
+        note(ev, "PCI vendor:device ID", "not available from sysfs".to_string(), false);
+        return None;
+    };
+    let entry = PCI_IDS.iter().find(|&&(v, d, _, _)| v == pci.vendor && d == pci.device);
+    note(
+        ev,
+        "PCI vendor:device ID",
+        match entry {
+            Some((_, _, name, _)) => format!("{:04x}:{:04x} is {}", pci.vendor, pci.device, name),
+            None => format!("{:04x}:{:04x} not in the table", pci.vendor, pci.device),
+        },
+        entry.is_some(),
+    );
+    let &(_, _, name, family) = entry?;
+    let name = name.to_string();
+    let ct = match family {
+        PciFamily::Smi => ControllerType::Smi(name),
+        PciFamily::Realtek(variant) => ControllerType::Realtek(name, variant),
+        PciFamily::Phison => ControllerType::Phison(name),
+        PciFamily::Maxio => ControllerType::Maxio(name),
+        PciFamily::Marvell => ControllerType::Marvell(name),
+        PciFamily::Innogrit => ControllerType::Innogrit(name),
+    };
+    Some((ct, Confidence::High))
+}
+
+/// NVMe controller type for a drive database family name: a built-in family or an
+/// NVMe definition.
+fn db_family_type(entry: &DriveEntry, defs: &[Definition]) -> Option<ControllerType> {
+    let name = entry.controller.clone();
+    Some(match entry.family.as_str() {
+        "smi" => ControllerType::Smi(name),
+        "rtl" => ControllerType::Realtek(name, entry.rtl_variant.unwrap_or(RtlVariant::V1)),
+        "phison" => ControllerType::Phison(name),
+        "maxio" => ControllerType::Maxio(name),
+        "marvell" => ControllerType::Marvell(name),
+        "innogrit" => ControllerType::Innogrit(name),
+        "tenafe" => ControllerType::Tenafe(name),
+        family => {
+            let def = defs.iter().find(|d| d.transport == DefTransport::Nvme && d.name == family)?;
+            ControllerType::Defined(Box::new(def.clone()))
+        }
+    })
+}
+
+fn detect_drive_db(
+    info: &crate::nvme::ControllerInfo,
+    db: &[DriveEntry],
+    defs: &[Definition],
+    ev: &mut Vec<Evidence>,
+) -> Option<(ControllerType, Confidence)> {
+    let hit = db
+        .iter()
+        .filter(|e| e.matches(&info.model, &info.firmware))
+        .find_map(|e| db_family_type(e, defs).map(|ct| (e, ct)));
+    note(
+        ev,
+        "drive database (model/firmware patterns)",
+        match &hit {
+            Some((e, _)) => format!(
+                "model '{}' firmware '{}' match '{}' / '{}'",
+                info.model, info.firmware, e.model, e.firmware
+            ),
+            None => format!("no entry for model '{}' firmware '{}'", info.model, info.firmware),
+        },
+        hit.is_some(),
+    );
+    let (entry, ct) = hit?;
+    // A firmware pattern pins the drive revision; a model alone may span controller changes
+    let confidence = if entry.firmware == "*" { Confidence::Medium } else { Confidence::High };
+    Some((ct, confidence))
+}
+
+fn detect_tenafe(model: &str, ev: &mut Vec<Evidence>) -> Option<(ControllerType, Confidence)> {
+    let matched = model == "Merak Nvme Ssd Controller";
+    note(ev, "Tenafe model 'Merak Nvme Ssd Controller'", format!("model '{}'", model), matched);
+    if matched {
+        return Some((ControllerType::Tenafe("Merak".to_string()), Confidence::Medium));
     }
     None
 }
@@ -121,21 +418,29 @@ fn probe_phison(dev: &NvmeDevice) -> Option<ControllerType> {
     None
 }
 
-fn probe_maxio(dev: &NvmeDevice) -> Option<ControllerType> {
+fn probe_maxio_opcode(dev: &NvmeDevice, opcode: u8) -> Option<ControllerType> {
     let mut buf = [0u8; 4096];
-    for opcode in [0xC1, 0xC2] {
-        if dev
-            .admin_read(opcode, 0, 0, 0, 0, 0, 0, 0, &mut buf)
-            .is_ok()
-            && let Ok(s) = std::str::from_utf8(&buf)
-            && s.contains(",MAP1")
-        {
-            return Some(ControllerType::Maxio("Maxio".to_string()));
-        }
+    if dev
+        .admin_read(opcode, 0, 0, 0, 0, 0, 0, 0, &mut buf)
+        .is_ok()
+        && let Ok(s) = std::str::from_utf8(&buf)
+        && s.contains(",MAP1")
+    {
+        return Some(ControllerType::Maxio("Maxio".to_string()));
     }
     None
 }
 
+fn probe_maxio(dev: &NvmeDevice) -> Option<ControllerType> {
+    probe_maxio_opcode(dev, 0xC2)
+}
+
+/// Some Maxio firmware only answers the string query on 0xC1, whose opcode direction
+/// bits mean host-to-controller data, so the firmware may take it as a request write.
+fn probe_maxio_c1(dev: &NvmeDevice) -> Option<ControllerType> {
+    probe_maxio_opcode(dev, 0xC1)
+}
+
 fn probe_marvell(dev: &NvmeDevice) -> Option<ControllerType> {
     let mut buf = [0u8; 4096];
     if dev
@@ -175,39 +480,192 @@ fn probe_innogrit(dev: &NvmeDevice) -> Option<ControllerType> {
     None
 }
 
-pub fn detect(dev: &NvmeDevice, info: &crate::nvme::ControllerInfo) -> Option<ControllerType> {
-    // Firmware/model/VID-based detection (no vendor commands)
-    if let Some(ct) = detect_realtek(&info.firmware, info.vid, info.ssvid) {
-        return Some(ct);
+fn probe_smi(dev: &NvmeDevice) -> Option<ControllerType> {
+    crate::controllers::smi::probe(dev).map(ControllerType::Smi)
+}
+
+fn probe_realtek(dev: &NvmeDevice) -> Option<ControllerType> {
+    if crate::controllers::rtl::probe(dev) {
+        return Some(ControllerType::Realtek("Realtek (by probe)".to_string(), RtlVariant::V1));
     }
-    if let Some(ct) = detect_smi(&info.firmware, &info.model, info.vid, info.ssvid) {
-        return Some(ct);
+    None
+}
+
+type Probe = fn(&NvmeDevice) -> Option<ControllerType>;
+
+/// Matches the loaded NVMe definitions. The first pass (`by_vid` false) takes firmware
+/// and model rules; `vid =` rules are only taken by the second pass.
+fn detect_definition(
+    defs: &[Definition],
+    info: &crate::nvme::ControllerInfo,
+    by_vid: bool,
+    ev: &mut Vec<Evidence>,
+) -> Option<(ControllerType, Confidence)> {
+    for def in defs.iter().filter(|d| d.transport == DefTransport::Nvme) {
+        let rule = def.matching_rule(&info.firmware, &info.model, info.vid, info.ssvid);
+        let vid_rule = matches!(rule, Some(DetectRule::Vid(_)));
+        if by_vid && !vid_rule {
+            continue;
+        }
+        let heuristic = format!("definition '{}'", def.name);
+        let Some(rule) = rule else {
+            note(ev, &heuristic, "no detect rule matched".to_string(), false);
+            continue;
+        };
+        if vid_rule && !by_vid {
+            let found = format!("rule {} matched, tried after the PCI ID and drive database", rule);
+            note(ev, &heuristic, found, false);
+            continue;
+        }
+        let confidence = match rule {
+            DetectRule::FirmwarePrefix(_) => Confidence::High,
+            DetectRule::ModelEquals(_) | DetectRule::ModelContains(_) => Confidence::Medium,
+            DetectRule::Vid(_) => Confidence::Low,
+        };
+        note(ev, &heuristic, format!("rule {} matched", rule), true);
+        return Some((ControllerType::Defined(Box::new(def.clone())), confidence));
     }
-    if let Some(ct) = detect_tenafe(&info.model) {
-        return Some(ct);
+    None
+}
+
+/// Vendor probes in the order they are tried, with the risk of the commands each sends
+/// and what the probe looks for. A probe's risk is its own, not its family reader's:
+/// the Maxio and Marvell readers write requests, their signature reads do not.
+const PROBES: &[(Probe, Risk, &str)] = &[
+    (probe_phison, Risk::VendorRead, "Phison probe (0xD2, 'PhIsOnNo' signature)"),
+    (probe_maxio, Risk::VendorRead, "Maxio probe (0xC2, ',MAP1' string)"),
+    (probe_marvell, Risk::VendorRead, "Marvell probe (0xFE, 'DM1160'/'DM1140' signature)"),
+    (probe_innogrit, Risk::VendorRead, "Innogrit probe (0xF2, non-empty response)"),
+    // After the vendor-specific signatures: the SMI C2 read is accepted by other families
+    (probe_smi, Risk::VendorRead, "SMI probe (0xC2, 'SMxxxx' name at 0x1F0)"),
+    // Write-level probes only after every read-only probe missed
+    (probe_maxio_c1, Risk::VendorWrite, "Maxio probe (0xC1, ',MAP1' string)"),
+    // Reads without the unlock, but stays at the Realtek reader's level: how locked
+    // firmware handles the read is undocumented. Last, a JEDEC ID in slot 0 is a weaker
+    // signature than the strings above
+    (probe_realtek, Risk::VendorWrite, "Realtek probe (0xFA read without unlock, JEDEC ID in slot 0)"),
+];
+
+/// Detects the controller family. Every heuristic evaluated is appended to `ev`, up to
+/// and including the one that decided.
+pub fn detect(
+    dev: &NvmeDevice,
+    info: &crate::nvme::ControllerInfo,
+    max_risk: Risk,
+    defs: &[Definition],
+    db: &[DriveEntry],
+    ev: &mut Vec<Evidence>,
+) -> Option<(ControllerType, Confidence)> {
+    // Loaded definitions come first so they can override a built-in family
+    if let Some(found) = detect_definition(defs, info, false, ev) {
+        return Some(found);
     }
 
-    // Probe-based detection (sends vendor commands)
-    if let Some(ct) = probe_phison(dev) {
-        return Some(ct);
+    // The PCI device ID names the controller chip exactly
+    if let Some(found) = detect_pci(info.pci.as_ref(), ev) {
+        return Some(found);
     }
-    if let Some(ct) = probe_maxio(dev) {
-        return Some(ct);
+
+    // Known OEM drives whose strings don't reveal the controller
+    if let Some(found) = detect_drive_db(info, db, defs, ev) {
+        return Some(found);
+    }
+
+    // A VID is shared by every controller an OEM ships, so a definition matching only
+    // by VID must not override the PCI ID or the drive database
+    if let Some(found) = detect_definition(defs, info, true, ev) {
+        return Some(found);
+    }
+
+    // Firmware/model/VID-based detection (no vendor commands)
+    if let Some(found) = detect_realtek(&info.firmware, info.vid, info.ssvid, ev) {
+        return Some(found);
     }
-    if let Some(ct) = probe_marvell(dev) {
-        return Some(ct);
+    if let Some(found) = detect_smi(&info.firmware, &info.model, info.vid, info.ssvid, ev) {
+        return Some(found);
     }
-    if let Some(ct) = probe_innogrit(dev) {
-        return Some(ct);
+    if let Some(found) = detect_tenafe(&info.model, ev) {
+        return Some(found);
+    }
+
+    // Probe-based detection (sends vendor commands)
+    for &(probe, risk, what) in PROBES {
+        if risk > max_risk {
+            note(ev, what, format!("skipped (needs --probe-level {})", risk.level_name()), false);
+            continue;
+        }
+        crate::trace::start_capture();
+        let found = probe(dev);
+        let outcomes = crate::trace::take_capture();
+        let detail = match (&found, outcomes.iter().find_map(|o| o.error.as_deref())) {
+            (Some(ct), _) => format!("signature found ({})", ct.name()),
+            (None, Some(e)) => format!("command rejected: {}", e),
+            (None, None) => "command accepted, signature absent".to_string(),
+        };
+        note(ev, what, detail, found.is_some());
+        if let Some(ct) = found {
+            return Some((ct, Confidence::High));
+        }
     }
 
     // VID fallback for controllers whose probes might not respond on all variants
-    if info.vid == PHISON_VID || info.ssvid == PHISON_VID {
-        return Some(ControllerType::Phison("Phison (by VID)".into()));
+    if vid_matches(ev, "Phison", PHISON_VID, info.vid, info.ssvid) {
+        return Some((ControllerType::Phison("Phison (by VID)".into()), Confidence::Low));
+    }
+    if vid_matches(ev, "Maxio", MAXIO_VID, info.vid, info.ssvid) {
+        return Some((ControllerType::Maxio("Maxio (by VID)".into()), Confidence::Low));
     }
-    if info.vid == MAXIO_VID || info.ssvid == MAXIO_VID {
-        return Some(ControllerType::Maxio("Maxio (by VID)".into()));
+    if let Some(found) = detect_oui(info.ieee_oui, ev) {
+        return Some(found);
     }
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smi_confidence() {
+        let mut ev = Vec::new();
+        let (_, c) = detect_smi("2262ROM:1.0", "", 0, 0, &mut ev).unwrap();
+        assert_eq!(c, Confidence::High);
+        assert_eq!(ev.len(), 1);
+
+        let mut ev = Vec::new();
+        let (ct, c) = detect_smi("EIFM31.6", "OEM NVMe SSD", 0x2646, 0x1234, &mut ev).unwrap();
+        assert_eq!((ct.name(), c), ("SMI (by VID)", Confidence::Low));
+        let matched: Vec<bool> = ev.iter().map(|e| e.matched).collect();
+        assert_eq!(matched, [false, false, true]);
+    }
+
+    #[test]
+    fn test_dram_status() {
+        let on = HmbState { enabled: true, size: 64 << 20 };
+        assert_eq!(dram_status("SM2263XT", 8192, Some(&on)).as_deref(), Some("DRAM-less (HMB 64 MiB enabled)"));
+        assert_eq!(dram_status("PS5012 (E12)", 0, None).as_deref(), Some("DRAM"));
+        // A DRAM controller that asks for an HMB was built without DRAM
+        assert_eq!(dram_status("PS5012 (E12)", 8192, None).as_deref(), Some("DRAM-less (HMB state unknown)"));
+        // "RTS5762/63" covers the RTS5762 (DRAM) and the RTS5763DL (DRAM-less)
+        assert_eq!(dram_status("RTS5762/63", 0, None), None);
+        assert_eq!(dram_status("RTS5762", 0, None).as_deref(), Some("DRAM"));
+        // Ambiguous name: the HMB request decides
+        assert_eq!(dram_status("SM2263EN/SM2263XT", 0, None), None);
+        let off = HmbState { enabled: false, size: 0 };
+        assert_eq!(
+            dram_status("SM2263EN/SM2263XT", 8192, Some(&off)).as_deref(),
+            Some("DRAM-less (HMB disabled)")
+        );
+        assert_eq!(dram_status("Phison E13T", 0, None).as_deref(), Some("DRAM-less (no HMB)"));
+    }
+
+    #[test]
+    fn test_oui_fallback() {
+        let mut ev = Vec::new();
+        let (ct, c) = detect_oui(0x6479A7, &mut ev).unwrap();
+        assert_eq!((ct.family(), c), ("phison", Confidence::Low));
+        assert!(detect_oui(0x002538, &mut ev).is_none());
+        assert_eq!(ev.len(), 2);
+    }
+}
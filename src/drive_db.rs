@@ -0,0 +1,135 @@
+use crate::detect::RtlVariant;
+
+/// Loaded in addition to --drive-db when present.
+pub const SYSTEM_DRIVE_DB: &str = "/etc/ssd-flash-id/drives.db";
+
+/// Retail drives whose Identify strings don't reveal the controller. Same format as
+/// the loadable file: MODEL GLOB | FIRMWARE GLOB | FAMILY[:VARIANT] | CONTROLLER NAME
+const BUILTIN: &str = "\
+INTEL SSDPEKKW*   | *  | smi    | SM2262
+INTEL SSDPEKNW*   | *  | smi    | SM2263
+KINGSTON SA2000*  | *  | smi    | SM2263
+KINGSTON SKC2000* | *  | smi    | SM2262EN
+CT*P1SSD8         | *  | smi    | SM2263
+ADATA SX8200PNP   | *  | smi    | SM2262/SM2262EN
+HP SSD EX900*     | *  | smi    | SM2263XT
+ADATA SX6000PNP   | *  | rtl:v1 | RTS5763DL
+";
+
+/// One known drive: glob patterns over the Identify model and firmware strings and
+/// the family (a --controller name) they map to.
+#[derive(Debug, Clone)]
+pub struct DriveEntry {
+    pub model: String,
+    pub firmware: String,
+    pub family: String,
+    pub rtl_variant: Option<RtlVariant>,
+    pub controller: String,
+}
+
+impl DriveEntry {
+    pub fn matches(&self, model: &str, firmware: &str) -> bool {
+        glob_match(&self.model, model) && glob_match(&self.firmware, firmware)
+    }
+}
+
+/// Entries from the given files first, so they can override the built-in table.
+pub fn load(paths: &[String]) -> Result<Vec<DriveEntry>, String> {
+    let mut entries = Vec::new();
+    for path in paths {
+        if path == SYSTEM_DRIVE_DB && !std::path::Path::new(path).exists() {
+            continue;
+        }
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read '{}': {}", path, e))?;
+        entries.extend(parse(&text).map_err(|e| format!("{}:{}", path, e))?);
+    }
+    entries.extend(parse(BUILTIN).expect("built-in drive table parses"));
+    Ok(entries)
+}
+
+pub fn parse(text: &str) -> Result<Vec<DriveEntry>, String> {
+    let mut entries = Vec::new();
+    for (n, raw) in text.lines().enumerate() {
+        let line = raw.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+        let [model, firmware, family, controller] = fields[..] else {
+            return Err(format!(
+                "{}: expected 'MODEL | FIRMWARE | FAMILY[:VARIANT] | CONTROLLER'",
+                n + 1
+            ));
+        };
+        let (family, rtl_variant) = match family.split_once(':') {
+            Some((f, "v1")) => (f, Some(RtlVariant::V1)),
+            Some((f, "v2")) => (f, Some(RtlVariant::V2)),
+            Some((_, v)) => return Err(format!("{}: unknown variant '{}' (expected v1 or v2)", n + 1, v)),
+            None => (family, None),
+        };
+        if model.is_empty() || family.is_empty() || controller.is_empty() {
+            return Err(format!("{}: model, family and controller must not be empty", n + 1));
+        }
+        entries.push(DriveEntry {
+            model: model.to_string(),
+            firmware: if firmware.is_empty() { "*".to_string() } else { firmware.to_string() },
+            family: family.to_string(),
+            rtl_variant,
+            controller: controller.to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Case-insensitive glob with `*` (any run) and `?` (one character).
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.to_ascii_uppercase().chars().collect();
+    let t: Vec<char> = text.trim().to_ascii_uppercase().chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            // Let the last * absorb one more character and retry
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("INTEL SSDPEKNW*", "INTEL SSDPEKNW010T8"));
+        assert!(glob_match("ct*p1ssd8", "CT1000P1SSD8"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("SBM0?106", "SBM02106"));
+        assert!(!glob_match("CT*P1SSD8", "CT1000P2SSD8"));
+        assert!(!glob_match("KINGSTON SA2000", "KINGSTON SA2000M8500G"));
+    }
+
+    #[test]
+    fn test_parse_and_lookup() {
+        let db = parse("FOO NVME* | 1.0* | rtl:v2 | RTS5766DL\nFOO NVME* | * | smi | SM2263\n").unwrap();
+        let lookup = |model, fw| db.iter().find(|e| e.matches(model, fw));
+        let hit = lookup("FOO NVMe 1TB", "1.0.3").unwrap();
+        assert_eq!((hit.family.as_str(), hit.rtl_variant), ("rtl", Some(RtlVariant::V2)));
+        assert_eq!(lookup("FOO NVMe 1TB", "2.0").unwrap().family, "smi");
+        assert!(parse("FOO | * | smi").is_err());
+        assert!(parse("FOO | * | rtl:v3 | X").is_err());
+        assert!(!parse(BUILTIN).unwrap().is_empty());
+    }
+}
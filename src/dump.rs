@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::trace::CommandRecord;
+
+static DUMP: Mutex<Option<DumpDir>> = Mutex::new(None);
+
+struct DumpDir {
+    dir: PathBuf,
+    manifest: File,
+    seq: u32,
+    label: String,
+}
+
+/// Saves every data-in response into `dir` for offline analysis. Each response gets a
+/// sequence-numbered file named after the step that sent it (e.g. 004-phison-nvme-d2.bin)
+/// and a manifest.txt line describing the command that produced it.
+pub fn open(dir: &str) -> Result<(), String> {
+    let dir = PathBuf::from(dir);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("failed to create dump directory '{}': {}", dir.display(), e))?;
+    let manifest_path = dir.join("manifest.txt");
+    let mut manifest = File::create(&manifest_path)
+        .map_err(|e| format!("failed to create '{}': {}", manifest_path.display(), e))?;
+    let _ = writeln!(manifest, "# file\tbytes\tstep\tcommand\tcompletion");
+    *DUMP.lock().unwrap() = Some(DumpDir {
+        dir,
+        manifest,
+        seq: 0,
+        label: "identify".to_string(),
+    });
+    Ok(())
+}
+
+/// Names the step the following commands belong to (detection, a family reader, teardown).
+pub fn set_label(label: &str) {
+    if let Some(dump) = DUMP.lock().unwrap().as_mut() {
+        dump.label = label.to_string();
+    }
+}
+
+pub fn save(rec: &CommandRecord, data: &[u8]) {
+    let mut guard = DUMP.lock().unwrap();
+    let Some(dump) = guard.as_mut() else {
+        return;
+    };
+    dump.seq += 1;
+    let file_name = format!("{:03}-{}-{}.bin", dump.seq, dump.label, rec.tag);
+    let path = dump.dir.join(&file_name);
+    if let Err(e) = std::fs::write(&path, data) {
+        eprintln!("warning: failed to write '{}': {}", path.display(), e);
+        return;
+    }
+    let _ = writeln!(
+        dump.manifest,
+        "{}\t{}\t{}\t{}\t{}",
+        file_name,
+        data.len(),
+        dump.label,
+        rec.desc,
+        rec.status
+    );
+}
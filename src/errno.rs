@@ -0,0 +1,18 @@
+/// Short description of an errno returned by the transport ioctls and opens.
+pub fn errno_to_str(errno: i32) -> &'static str {
+    match errno {
+        libc::EACCES => "permission denied",
+        libc::ENOENT => "no such file or directory",
+        libc::EBUSY => "device busy",
+        libc::EIO => "i/o error",
+        libc::EINVAL => "invalid argument",
+        libc::ENOTTY => "inappropriate ioctl for device",
+        libc::ENOMEM => "out of memory",
+        libc::EPERM => "operation not permitted",
+        libc::ENODEV => "no such device",
+        libc::EAGAIN => "device busy (try again)",
+        libc::ETIMEDOUT => "timed out",
+        libc::EINTR => "interrupted",
+        _ => "unknown error",
+    }
+}
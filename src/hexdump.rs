@@ -0,0 +1,39 @@
+/// Formats a buffer as offset/hex/ASCII lines (16 bytes per line). Runs of identical
+/// lines are collapsed into a single "*" line like `hexdump -C`, which keeps mostly
+/// empty 4 KB vendor buffers readable.
+pub fn hexdump(data: &[u8], indent: &str) -> String {
+    let mut out = String::new();
+    let mut prev: Option<&[u8]> = None;
+    let mut collapsed = false;
+    for (i, line) in data.chunks(16).enumerate() {
+        let offset = i * 16;
+        let is_last = offset + 16 >= data.len();
+        if prev == Some(line) && !is_last {
+            if !collapsed {
+                out.push_str(indent);
+                out.push_str("*\n");
+                collapsed = true;
+            }
+            continue;
+        }
+        collapsed = false;
+        prev = Some(line);
+
+        out.push_str(&format!("{}{:04x}: ", indent, offset));
+        for j in 0..16 {
+            match line.get(j) {
+                Some(b) => out.push_str(&format!("{:02x} ", b)),
+                None => out.push_str("   "),
+            }
+            if j == 7 {
+                out.push(' ');
+            }
+        }
+        out.push_str(" |");
+        for &b in line {
+            out.push(if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' });
+        }
+        out.push_str("|\n");
+    }
+    out
+}
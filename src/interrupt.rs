@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, Ordering};
+
+static PENDING_SIGNAL: AtomicI32 = AtomicI32::new(0);
+static IN_TEARDOWN: AtomicBool = AtomicBool::new(false);
+/// Outcome of the family teardowns: 0 none ran, 1 all succeeded, 2 one failed
+static TEARDOWN_STATE: AtomicU8 = AtomicU8::new(0);
+
+extern "C" fn on_signal(sig: libc::c_int) {
+    // A second Ctrl-C while the teardown is running aborts immediately
+    if PENDING_SIGNAL.swap(sig, Ordering::SeqCst) != 0 {
+        unsafe { libc::_exit(128 + sig) };
+    }
+}
+
+/// Installs SIGINT/SIGTERM handlers that only record the signal. The command in flight
+/// completes, further vendor commands are refused, and the caller runs the family
+/// teardown before exiting via `exit_if_interrupted`.
+pub fn install_handlers() {
+    unsafe {
+        let mut sa: libc::sigaction = std::mem::zeroed();
+        sa.sa_sigaction = on_signal as *const () as libc::sighandler_t;
+        libc::sigemptyset(&mut sa.sa_mask);
+        libc::sigaction(libc::SIGINT, &sa, std::ptr::null_mut());
+        libc::sigaction(libc::SIGTERM, &sa, std::ptr::null_mut());
+    }
+}
+
+/// Called by the transports before each command.
+pub fn check() -> Result<(), String> {
+    let sig = PENDING_SIGNAL.load(Ordering::SeqCst);
+    if sig != 0 && !IN_TEARDOWN.load(Ordering::SeqCst) {
+        return Err(format!("interrupted by {}", signal_name(sig)));
+    }
+    Ok(())
+}
+
+/// Runs a teardown sequence; commands are allowed even if a signal is pending.
+pub fn run_teardown<T>(f: impl FnOnce() -> T) -> T {
+    IN_TEARDOWN.store(true, Ordering::SeqCst);
+    let ret = f();
+    IN_TEARDOWN.store(false, Ordering::SeqCst);
+    ret
+}
+
+/// Records the outcome of a family teardown; a failure is never overwritten by a
+/// later success.
+pub fn record_teardown(ok: bool) {
+    let state = if ok { 1 } else { 2 };
+    TEARDOWN_STATE.fetch_max(state, Ordering::SeqCst);
+}
+
+pub fn exit_if_interrupted() {
+    let sig = PENDING_SIGNAL.load(Ordering::SeqCst);
+    if sig != 0 {
+        match TEARDOWN_STATE.load(Ordering::SeqCst) {
+            0 => eprintln!("interrupted by {}", signal_name(sig)),
+            1 => eprintln!("interrupted by {}, controller state restored", signal_name(sig)),
+            _ => eprintln!(
+                "interrupted by {}, teardown failed, controller may still be in vendor mode",
+                signal_name(sig)
+            ),
+        }
+        std::process::exit(128 + sig);
+    }
+}
+
+fn signal_name(sig: i32) -> &'static str {
+    match sig {
+        libc::SIGINT => "SIGINT",
+        libc::SIGTERM => "SIGTERM",
+        _ => "signal",
+    }
+}
@@ -0,0 +1,131 @@
+use std::ffi::CString;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const LOCK_DIRS: &[&str] = &["/run/lock", "/var/lock", "/tmp"];
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub const DEFAULT_LOCK_TIMEOUT_SECS: u64 = 30;
+
+/// Exclusive advisory lock held for the whole vendor command session on one device.
+/// Vendor readers are multi-step stateful sequences, so two concurrent instances
+/// would interleave their requests and read each other's responses.
+pub struct DeviceLock {
+    fd: i32,
+}
+
+impl DeviceLock {
+    pub fn acquire(dev_path: &str, timeout: Duration) -> Result<Self, String> {
+        let key = device_key(dev_path)?;
+        let dir = LOCK_DIRS
+            .iter()
+            .find(|d| Path::new(d).is_dir())
+            .ok_or_else(|| "no usable lock directory (/run/lock, /var/lock, /tmp)".to_string())?;
+        let lock_path = format!("{}/ssd-flash-id-{}.lock", dir, key);
+
+        let c_path = CString::new(lock_path.clone())
+            .map_err(|e| format!("invalid lock path '{}': {}", lock_path, e))?;
+        let fd = unsafe {
+            libc::open(
+                c_path.as_ptr(),
+                libc::O_RDWR | libc::O_CREAT | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+                0o644,
+            )
+        };
+        if fd < 0 {
+            let err = std::io::Error::last_os_error();
+            return Err(format!("failed to open lock file '{}': {}", lock_path, err));
+        }
+        // In a world-writable fallback like /tmp the file may have been planted by someone else
+        let mut st: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstat(fd, &mut st) } != 0 {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(format!("failed to stat lock file '{}': {}", lock_path, err));
+        }
+        if st.st_mode & libc::S_IFMT != libc::S_IFREG || st.st_uid != 0 {
+            unsafe { libc::close(fd) };
+            return Err(format!(
+                "refusing lock file '{}': not a regular file owned by root",
+                lock_path
+            ));
+        }
+
+        let start = Instant::now();
+        loop {
+            if unsafe { libc::flock(fd, libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+                break;
+            }
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::EWOULDBLOCK) {
+                unsafe { libc::close(fd) };
+                return Err(format!("failed to lock '{}': {}", lock_path, err));
+            }
+            if start.elapsed() >= timeout {
+                let holder = std::fs::read_to_string(&lock_path).unwrap_or_default();
+                unsafe { libc::close(fd) };
+                let holder = holder.trim();
+                return Err(format!(
+                    "device {} is busy: another ssd-flash-id instance{} is running vendor commands \
+                     (waited {}s, lock file {})",
+                    dev_path,
+                    if holder.is_empty() { String::new() } else { format!(" (pid {})", holder) },
+                    timeout.as_secs(),
+                    lock_path
+                ));
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        // Record the holder for the "busy" message of other instances. Fixed width, so a
+        // shorter pid overwrites a longer one completely without truncating the file
+        let pid = format!("{:>10}\n", std::process::id());
+        unsafe {
+            libc::pwrite(fd, pid.as_ptr() as *const libc::c_void, pid.len(), 0);
+        }
+
+        Ok(DeviceLock { fd })
+    }
+}
+
+impl Drop for DeviceLock {
+    fn drop(&mut self) {
+        unsafe {
+            libc::flock(self.fd, libc::LOCK_UN);
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Lock key derived from the sysfs device the node belongs to (e.g. the PCI function of an
+/// NVMe controller or the SCSI address of a disk), so /dev/sda and its /dev/sg and /dev/bsg
+/// nodes share one lock. An NVMe namespace's `device` is its controller, not the PCI
+/// function, so namespace nodes must be resolved to the controller node before locking.
+/// Falls back to the device number.
+fn device_key(dev_path: &str) -> Result<String, String> {
+    let name = Path::new(dev_path)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    for class in [
+        "/sys/class/nvme",
+        "/sys/class/block",
+        "/sys/class/scsi_generic",
+        "/sys/class/bsg",
+    ] {
+        if let Ok(dev) = std::fs::canonicalize(Path::new(class).join(&name).join("device")) {
+            let key: String = dev
+                .to_string_lossy()
+                .trim_start_matches("/sys/devices/")
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+                .collect();
+            return Ok(key);
+        }
+    }
+    let meta = std::fs::metadata(dev_path)
+        .map_err(|e| format!("failed to stat '{}': {}", dev_path, e))?;
+    let rdev = meta.rdev();
+    Ok(format!("dev{}_{}", libc::major(rdev), libc::minor(rdev)))
+}
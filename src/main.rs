@@ -1,24 +1,77 @@
 mod ata;
+mod classify;
+mod console;
 mod controllers;
+mod defs;
 mod detect;
+mod drive_db;
+mod dump;
+mod errno;
+mod hexdump;
+mod interrupt;
+mod lock;
+mod mmc;
 mod nand_db;
 mod nvme;
+mod research;
+mod risk;
+mod scsi;
+mod sysfs;
+mod trace;
+mod ufs;
+mod usb_bridge;
+mod usb_flash;
 
 use std::os::unix::fs::FileTypeExt;
 
-use crate::ata::{parse_ata_identify, AtaDevice};
+use crate::ata::{parse_ata_identify, AtaDevice, AtaMethod};
 use crate::controllers::FlashIdResult;
-use crate::detect::{ControllerType, RtlVariant};
+use crate::defs::{DefTransport, Definition};
+use crate::drive_db::DriveEntry;
+use crate::detect::{Confidence, ControllerType, Evidence, RtlVariant};
+use crate::lock::{DeviceLock, DEFAULT_LOCK_TIMEOUT_SECS};
+use crate::mmc::MmcDevice;
 use crate::nand_db::{describe_flash, format_flash_id_hex};
-use crate::nvme::{parse_identify, NvmeDevice};
+use crate::nvme::{namespace_wwns, parse_identify, NvmeDevice};
+use crate::risk::Risk;
+use crate::scsi::ScsiDevice;
+use crate::sysfs::{normalize_wwn, resolve_device, BlockTransport, DeviceKind};
+use crate::ufs::UfsDevice;
+use crate::usb_bridge::NvmeBridge;
 
 struct Args {
     device: Option<String>,
     controller: Option<String>,
     rtl_variant: Option<RtlVariant>,
+    /// --bridge: Some(None) for "none", i.e. treat a USB disk as SATA
+    bridge: Option<Option<NvmeBridge>>,
+    ata_method: Option<AtaMethod>,
+    serial: Option<String>,
+    wwn: Option<String>,
+    lock_timeout: u64,
+    /// (family, ms); family None applies to every command
+    timeouts: Vec<(Option<String>, u32)>,
+    recover: bool,
+    probe_level: Risk,
+    dry_run: bool,
+    verbose: u8,
+    audit_log: Option<String>,
+    dump_dir: Option<String>,
+    /// Extra definition files or directories
+    defs: Vec<String>,
+    /// Extra drive database files
+    drive_db: Vec<String>,
+    console: bool,
+    research: bool,
+    explain_detect: bool,
+    all: bool,
+    /// Send vendor commands to rotational and non-ATA SCSI devices too
+    force: bool,
     help: bool,
     list: bool,
     raw: bool,
+    /// Print the decoded Identify Controller fields under the NVMe header
+    details: bool,
 }
 
 fn parse_args() -> Args {
@@ -26,9 +79,29 @@ fn parse_args() -> Args {
         device: None,
         controller: None,
         rtl_variant: None,
+        bridge: None,
+        ata_method: None,
+        serial: None,
+        wwn: None,
+        lock_timeout: DEFAULT_LOCK_TIMEOUT_SECS,
+        timeouts: Vec::new(),
+        recover: false,
+        probe_level: Risk::UserLbaRead,
+        dry_run: false,
+        verbose: 0,
+        audit_log: None,
+        dump_dir: None,
+        defs: Vec::new(),
+        drive_db: Vec::new(),
+        console: false,
+        research: false,
+        explain_detect: false,
+        all: false,
+        force: false,
         help: false,
         list: false,
         raw: false,
+        details: false,
     };
 
     let argv: Vec<String> = std::env::args().skip(1).collect();
@@ -38,6 +111,51 @@ fn parse_args() -> Args {
             "--help" | "-h" => args.help = true,
             "--list" | "-l" => args.list = true,
             "--raw" => args.raw = true,
+            "--details" => args.details = true,
+            "--recover" => args.recover = true,
+            "--dry-run" => args.dry_run = true,
+            "--research" => args.research = true,
+            "--explain-detect" => args.explain_detect = true,
+            "--all" | "-a" => args.all = true,
+            "--force" => args.force = true,
+            "-v" | "--verbose" => args.verbose += 1,
+            "-vv" => args.verbose += 2,
+            "--dump-dir" => {
+                i += 1;
+                if i < argv.len() {
+                    args.dump_dir = Some(argv[i].clone());
+                } else {
+                    eprintln!("error: --dump-dir requires a value");
+                    std::process::exit(1);
+                }
+            }
+            "--defs" => {
+                i += 1;
+                if i < argv.len() {
+                    args.defs.push(argv[i].clone());
+                } else {
+                    eprintln!("error: --defs requires a value");
+                    std::process::exit(1);
+                }
+            }
+            "--drive-db" => {
+                i += 1;
+                if i < argv.len() {
+                    args.drive_db.push(argv[i].clone());
+                } else {
+                    eprintln!("error: --drive-db requires a value");
+                    std::process::exit(1);
+                }
+            }
+            "--audit-log" => {
+                i += 1;
+                if i < argv.len() {
+                    args.audit_log = Some(argv[i].clone());
+                } else {
+                    eprintln!("error: --audit-log requires a value");
+                    std::process::exit(1);
+                }
+            }
             "--controller" | "-c" => {
                 i += 1;
                 if i < argv.len() {
@@ -63,11 +181,128 @@ fn parse_args() -> Args {
                     std::process::exit(1);
                 }
             }
+            "--bridge" => {
+                i += 1;
+                if i < argv.len() {
+                    args.bridge = match argv[i].as_str() {
+                        "none" => Some(None),
+                        name => match NvmeBridge::from_name(name) {
+                            Some(b) => Some(Some(b)),
+                            None => {
+                                eprintln!(
+                                    "error: unknown bridge '{}' (expected {})",
+                                    name,
+                                    usb_bridge::BRIDGE_NAMES
+                                );
+                                std::process::exit(1);
+                            }
+                        },
+                    };
+                } else {
+                    eprintln!("error: --bridge requires a value");
+                    std::process::exit(1);
+                }
+            }
+            "--ata-method" => {
+                i += 1;
+                if i < argv.len() {
+                    args.ata_method = match AtaMethod::from_name(&argv[i]) {
+                        Some(m) => Some(m),
+                        None => {
+                            eprintln!(
+                                "error: unknown ata method '{}' (expected {})",
+                                argv[i],
+                                ata::METHOD_NAMES
+                            );
+                            std::process::exit(1);
+                        }
+                    };
+                } else {
+                    eprintln!("error: --ata-method requires a value");
+                    std::process::exit(1);
+                }
+            }
+            "--serial" => {
+                i += 1;
+                if i < argv.len() {
+                    args.serial = Some(argv[i].clone());
+                } else {
+                    eprintln!("error: --serial requires a value");
+                    std::process::exit(1);
+                }
+            }
+            "--wwn" => {
+                i += 1;
+                if i < argv.len() {
+                    args.wwn = Some(argv[i].clone());
+                } else {
+                    eprintln!("error: --wwn requires a value");
+                    std::process::exit(1);
+                }
+            }
+            "--lock-timeout" => {
+                i += 1;
+                if i < argv.len() {
+                    args.lock_timeout = match argv[i].parse() {
+                        Ok(secs) => secs,
+                        Err(_) => {
+                            eprintln!("error: invalid lock timeout '{}' (expected seconds)", argv[i]);
+                            std::process::exit(1);
+                        }
+                    };
+                } else {
+                    eprintln!("error: --lock-timeout requires a value");
+                    std::process::exit(1);
+                }
+            }
+            "--probe-level" => {
+                i += 1;
+                if i < argv.len() {
+                    args.probe_level = match Risk::from_level(&argv[i]) {
+                        Some(level) => level,
+                        None => {
+                            eprintln!(
+                                "error: unknown probe level '{}' (expected {})",
+                                argv[i],
+                                risk::LEVEL_NAMES
+                            );
+                            std::process::exit(1);
+                        }
+                    };
+                } else {
+                    eprintln!("error: --probe-level requires a value");
+                    std::process::exit(1);
+                }
+            }
+            "--timeout" => {
+                i += 1;
+                if i < argv.len() {
+                    let (family, ms) = match argv[i].split_once('=') {
+                        Some((f, ms)) => (Some(f.to_string()), ms),
+                        None => (None, argv[i].as_str()),
+                    };
+                    match ms.parse() {
+                        Ok(0) => {
+                            eprintln!("error: invalid timeout '{}' (must be at least 1 ms)", argv[i]);
+                            std::process::exit(1);
+                        }
+                        Ok(ms) => args.timeouts.push((family, ms)),
+                        Err(_) => {
+                            eprintln!("error: invalid timeout '{}' (expected MS or FAMILY=MS)", argv[i]);
+                            std::process::exit(1);
+                        }
+                    }
+                } else {
+                    eprintln!("error: --timeout requires a value");
+                    std::process::exit(1);
+                }
+            }
             s if s.starts_with('-') => {
                 eprintln!("error: unknown option '{}'", s);
                 eprintln!("try: ssd-flash-id --help");
                 std::process::exit(1);
             }
+            "console" if args.device.is_none() && !args.console => args.console = true,
             _ => {
                 args.device = Some(argv[i].clone());
             }
@@ -80,24 +315,112 @@ fn parse_args() -> Args {
 fn print_usage() {
     println!(
         "\
-ssd-flash-id - Identify NAND flash chips on NVMe and SATA SSDs
+ssd-flash-id - Identify NAND flash chips on NVMe and SATA SSDs, eMMC and UFS
 
 usage: ssd-flash-id [options] [device]
+       ssd-flash-id [options] --all
+       ssd-flash-id [options] console <device>
 
 arguments:
-    device              device path (e.g. /dev/nvme0, /dev/sda)
+    device              device path (e.g. /dev/nvme0, /dev/sda, /dev/sg2, /dev/mmcblk0,
+                        /dev/bsg/ufs-bsg0, /dev/disk/by-id/...); namespaces and
+                        partitions resolve to their controller/disk, UFS logical
+                        units to their ufs-bsg node; without one, the only NVMe
+                        device, ATA SSD, eMMC or UFS device is used
+    console             interactive console for raw NVMe admin / ATA commands
 
 options:
     -h, --help          show this help
-    -l, --list          list NVMe and SATA devices
+    -l, --list          list NVMe, SATA, eMMC and UFS devices (SATA with class and
+                        transport, NVMe in USB enclosures with their bridge)
+    -a, --all           run on every NVMe device (including USB enclosures), every
+                        ATA SSD, every eMMC and every UFS device in turn
+    --force             allow vendor commands on rotational (HDD) and non-ATA SCSI
+                        devices (SAS, iSCSI, USB sticks with an unknown controller),
+                        which are refused by default
     -c, --controller    force controller type:
                         nvme: smi, rtl, phison, maxio, marvell, innogrit, tenafe
                         sata: jm, smi-sata, yeestor, sandforce, rtl-sata
+                        emmc: samsung-emmc, hynix-emmc, kioxia-emmc, sandisk-emmc,
+                              phison-emmc, smi-emmc
+                        ufs: samsung-ufs, hynix-ufs, kioxia-ufs
+                        usb: phison-usb, smi-usb, alcor-usb, innostor-usb,
+                             chipsbank-usb (USB flash drives)
+                        or the name of a loaded definition (see --defs)
     --rtl-variant       force Realtek variant: v1 (RTS5762/63), v2 (RTS5765/66/72)
+    --bridge NAME       USB-NVMe bridge of a /dev/sdX enclosure: rtl9210, jms583,
+                        asm2362, or none to treat it as SATA (default: detected from
+                        the USB ID and SCSI INQUIRY)
+    --ata-method NAME   how ATA commands reach a SATA drive: pt16, pt12, jmicron
+                        (JMicron USB bridges), cypress (Cypress USB bridges);
+                        default: the first that answers IDENTIFY, in that order
+    --serial SN         select the device with this serial number
+    --wwn WWN           select the device with this WWN/EUI64/NGUID
+    --lock-timeout SECS wait up to SECS for another instance using the device (default 30)
+    --timeout [FAMILY=]MS
+                        command timeout in ms, for all commands or one controller
+                        family (e.g. --timeout 5000 --timeout rtl=2000); default 10000
+    --recover           reset the controller (NVMe) or device (SATA) if it stops
+                        responding after a command timed out
+    --probe-level LEVEL most invasive commands allowed (default all):
+                        passive: standard Identify only
+                        read:    + vendor commands that only read data
+                        write:   + vendor commands that send data or unlock
+                        all:     + reads of magic user LBAs (SATA)
+    --dry-run           print the vendor commands that would be sent (opcode, cdws or
+                        taskfile, direction, payload) without sending them; only the
+                        standard Identify is issued
+    -v, --verbose       trace every command with duration and status; -vv adds
+                        hexdumps of payloads, responses and sense data
+    --audit-log DEST    append a record of every non-Identify command sent (device,
+                        serial, command, outcome) to file DEST, or 'syslog'
+    --dump-dir DIR      save every full command response to DIR as a binary file,
+                        with a manifest.txt describing the command behind each one
+                        (with --all, in one subdirectory per device node)
+    --explain-detect    list every detection heuristic evaluated (firmware prefix, model,
+                        VID/SSVID, probe signatures), what it found, and the
+                        confidence of the result
+    --research          run every controller family's reader allowed by --probe-level
+                        and report which commands were accepted, rejected or
+                        returned data, and the banks each family found
+    --defs PATH         load controller definitions from a .def file or a directory
+                        of them (also read from /etc/ssd-flash-id/defs); a matching
+                        definition takes precedence over the built-in detection
+    --drive-db FILE     load extra model/firmware -> controller entries for OEM drives
+                        (also read from /etc/ssd-flash-id/drives.db), one per line:
+                        MODEL GLOB | FIRMWARE GLOB | FAMILY[:v1|v2] | CONTROLLER
+    --details           show the decoded NVMe Identify Controller fields (version,
+                        OACS, firmware slots, HMB, temperature thresholds, capacity,
+                        power states) under the header
     --raw               dump raw flash ID bytes as hex"
     );
 }
 
+fn default_timeout(args: &Args) -> Option<u32> {
+    args.timeouts.iter().rev().find(|(f, _)| f.is_none()).map(|&(_, ms)| ms)
+}
+
+/// Every family name a --timeout FAMILY=MS entry can refer to.
+fn timeout_families(defs: &[Definition]) -> Vec<&str> {
+    let mut names: Vec<&str> = NVME_TYPES.to_vec();
+    names.extend(SATA_FAMILIES.iter().map(|f| f.name));
+    names.extend(MMC_FAMILIES.iter().map(|f| f.name));
+    names.extend(UFS_FAMILIES.iter().map(|f| f.name));
+    names.extend(USB_FAMILIES.iter().map(|f| f.name));
+    names.extend(defs.iter().map(|d| d.name.as_str()));
+    names
+}
+
+/// Timeout for a family: a FAMILY=MS entry wins over a plain MS entry.
+fn timeout_for(args: &Args, family: &str) -> Option<u32> {
+    args.timeouts
+        .iter()
+        .rev()
+        .find(|(f, _)| f.as_deref() == Some(family))
+        .map(|&(_, ms)| ms)
+        .or_else(|| default_timeout(args))
+}
+
 fn check_root() {
     if unsafe { libc::geteuid() } != 0 {
         eprintln!("error: root privileges required\n");
@@ -163,33 +486,246 @@ fn find_sata_devices() -> Vec<String> {
         }
     }
     devices.sort();
+    // UFS logical units are handled through their host's ufs-bsg node
+    devices.retain(|p| sysfs::ufs_bsg_for(p).is_none());
+    // Disks without a block device are only reachable through their sg node
+    devices.extend(sysfs::sg_only_devices());
+    devices
+}
+
+/// eMMC user areas (mmcblk0, mmcblk1); SD cards are left out.
+fn find_mmc_devices() -> Vec<String> {
+    let mut devices = Vec::new();
+    let dir = match std::fs::read_dir("/dev") {
+        Ok(d) => d,
+        Err(_) => return devices,
+    };
+    for entry in dir.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let Some(suffix) = name.strip_prefix("mmcblk") else { continue };
+        // Must be digits only, not partitions (mmcblk0p1) or boot areas (mmcblk0boot0)
+        if suffix.is_empty() || !suffix.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        let path = format!("/dev/{}", name);
+        if sysfs::is_emmc(&path) {
+            devices.push(path);
+        }
+    }
+    devices.sort();
     devices
 }
 
-fn is_sata_path(path: &str) -> bool {
-    let name = std::path::Path::new(path)
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("");
-    if name.starts_with("sd") {
-        return true;
+/// Devices the tool runs on without an explicit path: every NVMe controller, every NVMe
+/// drive in a USB enclosure with a known bridge, every SATA device that classifies as
+/// an ATA SSD, every eMMC and every UFS device.
+fn find_default_targets() -> Vec<String> {
+    let mut targets = find_nvme_devices();
+    targets.extend(find_sata_devices().into_iter().filter(|p| {
+        nvme_bridge_for(p, None).is_some()
+            || classify::classify_path(p).is_ok_and(|c| c.class == classify::SataClass::Ssd)
+    }));
+    targets.extend(find_mmc_devices());
+    targets.extend(sysfs::ufs_bsg_nodes());
+    targets
+}
+
+/// --all: runs this program once per target so each device gets its own lock, and an
+/// error on one device (which exits the process) doesn't stop the others.
+fn run_all(targets: &[String]) -> ! {
+    let exe = std::env::current_exe().unwrap_or_else(|_| "ssd-flash-id".into());
+    let passthrough: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|a| a != "--all" && a != "-a")
+        .collect();
+    let mut failed = 0;
+    for (i, target) in targets.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("=== {} ===", target);
+        let _ = std::io::Write::flush(&mut std::io::stdout());
+        // Each device dumps into its own subdirectory, or every child would replace the
+        // previous one's manifest.txt and reuse its file names
+        let node = std::path::Path::new(target)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| target.clone());
+        let mut args = passthrough.clone();
+        if let Some(pos) = args.iter().position(|a| a == "--dump-dir")
+            && let Some(dir) = args.get_mut(pos + 1)
+        {
+            *dir = std::path::Path::new(dir.as_str()).join(&node).to_string_lossy().into_owned();
+        }
+        let ok = std::process::Command::new(&exe)
+            .args(&args)
+            .arg(target)
+            .status()
+            .is_ok_and(|s| s.success());
+        if !ok {
+            failed += 1;
+        }
+        interrupt::exit_if_interrupted();
+    }
+    if failed > 0 {
+        eprintln!("\n{} of {} device(s) failed", failed, targets.len());
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
+
+/// Refuses vendor commands on anything that isn't an ATA SSD unless --force is given:
+/// hard disks gain nothing from them and SCSI devices may misinterpret the CDBs.
+fn sata_guard(dev_path: &str, dev: &AtaDevice, id_data: &[u8; 512], args: &Args) {
+    let class = classify::classify(dev_path, dev, Some(id_data));
+    if class.class == classify::SataClass::Ssd || args.force {
+        return;
+    }
+    eprintln!(
+        "error: {} is not an ATA SSD ({}, {} transport: {})",
+        dev_path,
+        class.class.name(),
+        class.transport.name(),
+        class.reason
+    );
+    eprintln!("vendor commands were not sent; use --force to probe it anyway");
+    std::process::exit(1);
+}
+
+/// The USB-NVMe bridge a /dev/sdX sits behind: the --bridge choice, else detected from
+/// the USB ID or the SCSI INQUIRY strings. Only USB-attached disks are considered.
+fn nvme_bridge_for(dev_path: &str, forced: Option<Option<NvmeBridge>>) -> Option<NvmeBridge> {
+    if let Some(bridge) = forced {
+        return bridge;
     }
-    if name.starts_with("nvme") {
-        return false;
+    if sysfs::block_transport(dev_path) != BlockTransport::Usb {
+        return None;
     }
-    // Unknown name pattern: check if block device (SATA) vs char device (NVMe)
-    if let Ok(meta) = std::fs::metadata(path) {
-        meta.file_type().is_block_device()
-    } else {
-        false
+    let inquiry = AtaDevice::open(dev_path).ok().and_then(|d| d.inquiry().ok());
+    usb_bridge::detect(dev_path, inquiry.as_ref())
+}
+
+/// Opens a SATA device with the --ata-method choice, if any.
+fn open_ata(dev_path: &str, args: &Args) -> AtaDevice {
+    match AtaDevice::open(dev_path) {
+        Ok(d) => {
+            if let Some(method) = args.ata_method {
+                d.set_method(method);
+            }
+            d
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Opens an NVMe controller, or the SCSI disk of a USB enclosure through its bridge.
+fn open_nvme(dev_path: &str, bridge: Option<NvmeBridge>) -> NvmeDevice {
+    let opened = match bridge {
+        Some(bridge) => NvmeDevice::open_bridged(dev_path, bridge),
+        None => NvmeDevice::open(dev_path),
+    };
+    match opened {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Finds the single NVMe or SATA device whose identify data matches the given serial
+/// and/or WWN, so scripts can address a drive independently of enumeration order.
+fn find_device_by_identity(serial: Option<&str>, wwn: Option<&str>) -> Result<String, String> {
+    let wwn = wwn.map(normalize_wwn);
+    let mut matches = Vec::new();
+
+    let nvme_matches = |dev: &NvmeDevice| {
+        let Ok(id_data) = dev.identify_controller() else { return false };
+        let info = parse_identify(&id_data);
+        if serial.is_some_and(|sn| sn.trim() != info.serial) {
+            return false;
+        }
+        match wwn {
+            // Any namespace may carry the WWN, as its NGUID or its EUI64; NVMe 1.0 has no
+            // namespace list, so only nsid 1
+            Some(ref want) => {
+                let nsids = dev.active_namespaces().ok().filter(|l| !l.is_empty()).unwrap_or_else(|| vec![1]);
+                nsids.into_iter().any(|nsid| {
+                    dev.identify_namespace(nsid)
+                        .is_ok_and(|d| namespace_wwns(&d).iter().any(|id| id == want))
+                })
+            }
+            None => true,
+        }
+    };
+
+    for dev_path in find_nvme_devices() {
+        let Ok(dev) = NvmeDevice::open(&dev_path) else { continue };
+        if nvme_matches(&dev) {
+            matches.push(dev_path);
+        }
+    }
+
+    for dev_path in find_sata_devices() {
+        if let Some(bridge) = nvme_bridge_for(&dev_path, None) {
+            if NvmeDevice::open_bridged(&dev_path, bridge).is_ok_and(|dev| nvme_matches(&dev)) {
+                matches.push(dev_path);
+            }
+            continue;
+        }
+        let Ok(dev) = AtaDevice::open(&dev_path) else { continue };
+        let Ok(id_data) = dev.ata_identify() else { continue };
+        let info = parse_ata_identify(&id_data);
+        if serial.is_some_and(|sn| sn.trim() != info.serial) {
+            continue;
+        }
+        if let Some(ref want) = wwn
+            && info.wwn.as_deref() != Some(want.as_str())
+        {
+            continue;
+        }
+        matches.push(dev_path);
+    }
+
+    // eMMC and UFS have no WWN; eMMC's serial is the CID product serial number as sysfs
+    // shows it, UFS's the serial number string descriptor
+    if wwn.is_none() {
+        for dev_path in find_mmc_devices() {
+            let Some(cid) = sysfs::mmc_cid(&dev_path) else { continue };
+            let cid = mmc::parse_cid(&cid);
+            if serial.is_some_and(|sn| sn.trim() == format!("0x{:08x}", cid.serial)) {
+                matches.push(dev_path);
+            }
+        }
+        for dev_path in sysfs::ufs_bsg_nodes() {
+            let Ok(info) = UfsDevice::open(&dev_path).and_then(|d| ufs::identify(&d)) else { continue };
+            if serial.is_some_and(|sn| sn.trim() == info.serial) {
+                matches.push(dev_path);
+            }
+        }
+    }
+
+    match matches.len() {
+        0 => Err("no device matches the given serial/WWN".to_string()),
+        1 => Ok(matches.remove(0)),
+        _ => Err(format!(
+            "multiple devices match the given serial/WWN: {}",
+            matches.join(", ")
+        )),
     }
 }
 
 fn list_devices() {
     let nvme_devices = find_nvme_devices();
     let sata_devices = find_sata_devices();
+    let mmc_devices = find_mmc_devices();
+    let ufs_devices = sysfs::ufs_bsg_nodes();
 
-    if nvme_devices.is_empty() && sata_devices.is_empty() {
+    if nvme_devices.is_empty() && sata_devices.is_empty() && mmc_devices.is_empty() && ufs_devices.is_empty() {
         println!("no devices found");
         return;
     }
@@ -211,23 +747,124 @@ fn list_devices() {
     }
 
     for dev_path in &sata_devices {
+        if let Some(bridge) = nvme_bridge_for(dev_path, None) {
+            match NvmeDevice::open_bridged(dev_path, bridge).and_then(|d| d.identify_controller()) {
+                Ok(id_data) => {
+                    let info = parse_identify(&id_data);
+                    println!(
+                        "{}  {}  sn:{}  fw:{}  [nvme, usb: {}]",
+                        dev_path,
+                        info.model,
+                        info.serial,
+                        info.firmware,
+                        bridge.name()
+                    );
+                }
+                Err(e) => println!("{}  (identify failed: {})  [nvme, usb: {}]", dev_path, e, bridge.name()),
+            }
+            continue;
+        }
+        if let Some(family) = usb_flash_for(dev_path, None, false) {
+            match ScsiDevice::open(dev_path).and_then(|d| d.inquiry()) {
+                Ok(inq) => {
+                    let (vendor, product, revision) = usb_flash::inquiry_strings(&inq);
+                    println!(
+                        "{}  {} {}  sn:{}  fw:{}  [usb flash, {}]",
+                        dev_path,
+                        vendor,
+                        product,
+                        sysfs::usb_serial(dev_path).unwrap_or_default(),
+                        revision,
+                        family.display
+                    );
+                }
+                Err(e) => println!("{}  (inquiry failed: {})  [usb flash, {}]", dev_path, e, family.display),
+            }
+            continue;
+        }
         match AtaDevice::open(dev_path) {
             Ok(dev) => match dev.ata_identify() {
                 Ok(id_data) => {
                     let info = parse_ata_identify(&id_data);
+                    let class = classify::classify(dev_path, &dev, Some(&id_data));
+                    let mut method = match dev.method() {
+                        Ok(AtaMethod::Pt16) | Err(_) => String::new(),
+                        Ok(m) => format!(", {}", m.name()),
+                    };
+                    if dev.node() != dev_path {
+                        method.push_str(&format!(", via {}", dev.node()));
+                    }
                     println!(
-                        "{}  {}  sn:{}  fw:{}",
-                        dev_path, info.model, info.serial, info.firmware
+                        "{}  {}  sn:{}  fw:{}{}  [{}, {}{}]",
+                        dev_path,
+                        info.model,
+                        info.serial,
+                        info.firmware,
+                        info.wwn.map(|w| format!("  wwn:{}", w)).unwrap_or_default(),
+                        class.class.name(),
+                        class.transport.name(),
+                        method
+                    );
+                }
+                Err(e) => {
+                    let class = classify::classify(dev_path, &dev, None);
+                    println!(
+                        "{}  (identify failed: {})  [{}, {}]",
+                        dev_path,
+                        e,
+                        class.class.name(),
+                        class.transport.name()
                     );
                 }
-                Err(e) => println!("{}  (identify failed: {})", dev_path, e),
             },
             Err(e) => println!("{}  (open failed: {})", dev_path, e),
         }
     }
+
+    for dev_path in &mmc_devices {
+        match mmc_info(dev_path) {
+            Ok(info) => println!(
+                "{}  {}  sn:{}  fw:{}  [emmc {}, {}]",
+                dev_path,
+                info.model,
+                info.serial,
+                info.firmware,
+                mmc::spec_version(info.ext_csd_rev),
+                mmc::manufacturer_name(info.manfid)
+            ),
+            Err(e) => println!("{}  (identify failed: {})  [emmc]", dev_path, e),
+        }
+    }
+
+    for dev_path in &ufs_devices {
+        match UfsDevice::open(dev_path).and_then(|d| ufs::identify(&d)) {
+            Ok(info) => println!(
+                "{}  {}  sn:{}  fw:{}  [ufs {}, {}{}]",
+                dev_path,
+                info.model,
+                info.serial,
+                info.firmware,
+                ufs::spec_version(info.spec_version),
+                ufs::manufacturer_name(info.manufacturer_id),
+                sysfs::ufs_lun_disk(dev_path).map(|d| format!(", lun {}", d)).unwrap_or_default()
+            ),
+            Err(e) => println!("{}  (identify failed: {})  [ufs]", dev_path, e),
+        }
+    }
+}
+
+/// Names accepted by --controller for one transport, for error messages.
+fn valid_types(builtin: &[&str], defs: &[Definition], transport: DefTransport) -> String {
+    let loaded = defs.iter().filter(|d| d.transport == transport).map(|d| d.name.as_str());
+    builtin.iter().copied().chain(loaded).collect::<Vec<_>>().join(", ")
 }
 
-fn resolve_nvme_controller_type(name: &str) -> Option<ControllerType> {
+const NVME_TYPES: &[&str] = &["smi", "rtl", "phison", "maxio", "marvell", "innogrit", "tenafe"];
+
+fn resolve_nvme_controller_type(name: &str, defs: &[Definition]) -> Option<ControllerType> {
+    if let Some(def) = defs.iter().find(|d| d.transport == DefTransport::Nvme && d.name == name) {
+        return Some(ControllerType::Defined(Box::new(def.clone())));
+    }
     match name {
         "smi" => Some(ControllerType::Smi("SMI (forced)".into())),
         "rtl" => Some(ControllerType::Realtek(
@@ -252,86 +889,411 @@ fn controller_family_display(ct: &ControllerType) -> &str {
         ControllerType::Marvell(_) => "Marvell",
         ControllerType::Innogrit(_) => "Innogrit",
         ControllerType::Tenafe(_) => "Tenafe",
+        ControllerType::Defined(def) => &def.display,
     }
 }
 
-fn nvme_read_flash_id(dev: &NvmeDevice, ct: &ControllerType) -> Result<FlashIdResult, String> {
+fn nvme_read_flash_id(dev: &NvmeDevice, ct: &ControllerType, args: &Args) -> Result<FlashIdResult, String> {
     match ct {
         ControllerType::Smi(_) => controllers::smi::read_flash_id(dev),
-        ControllerType::Realtek(_, variant) => controllers::rtl::read_flash_id(dev, variant),
+        ControllerType::Realtek(_, variant) => {
+            controllers::rtl::read_flash_id(dev, variant, timeout_for(args, ct.family()))
+        }
         ControllerType::Phison(_) => controllers::phison::read_flash_id(dev),
         ControllerType::Maxio(_) => controllers::maxio::read_flash_id(dev),
         ControllerType::Marvell(_) => controllers::marvell::read_flash_id(dev),
         ControllerType::Innogrit(_) => controllers::innogrit::read_flash_id(dev),
         ControllerType::Tenafe(_) => controllers::tenafe::read_flash_id(dev),
+        ControllerType::Defined(def) => controllers::defined::read_flash_id_nvme(dev, def),
     }
 }
 
-fn print_banks(result: &FlashIdResult, raw: bool) {
-    if result.banks.is_empty() {
-        println!("no flash banks detected");
-        return;
-    }
+const RECOVERY_SETTLE: std::time::Duration = std::time::Duration::from_secs(2);
 
-    for bank in &result.banks {
-        let hex = format_flash_id_hex(&bank.flash_id);
-        if raw {
-            println!("Bank{:02}: {}", bank.bank_num, hex);
-        } else {
-            let desc = describe_flash(&bank.flash_id);
-            println!("Bank{:02}: {} - {}", bank.bank_num, hex, desc);
-        }
+/// Families whose reader leaves the controller in a vendor mode declare a teardown here.
+fn nvme_has_teardown(ct: &ControllerType) -> bool {
+    match ct {
+        ControllerType::Defined(def) => !def.teardown.is_empty(),
+        _ => false,
     }
 }
 
-fn run_nvme(dev_path: &str, args: &Args) {
-    let dev = match NvmeDevice::open(dev_path) {
-        Ok(d) => d,
-        Err(e) => {
-            eprintln!("error: {}", e);
-            std::process::exit(1);
-        }
-    };
-
-    let id_data = match dev.identify_controller() {
-        Ok(d) => d,
-        Err(e) => {
-            eprintln!("error: failed to identify controller: {}", e);
-            std::process::exit(1);
-        }
-    };
-    let info = parse_identify(&id_data);
+fn nvme_run_teardown(dev: &NvmeDevice, ct: &ControllerType) -> Result<(), String> {
+    match ct {
+        ControllerType::Defined(def) => controllers::defined::teardown_nvme(dev, def),
+        _ => Ok(()),
+    }
+}
 
-    let mut ct = if let Some(ref forced) = args.controller {
-        match resolve_nvme_controller_type(forced) {
-            Some(ct) => ct,
-            None => {
-                eprintln!(
-                    "error: unknown controller type '{}'\n\nvalid nvme types: smi, rtl, phison, maxio, marvell, innogrit, tenafe",
-                    forced
-                );
-                std::process::exit(1);
-            }
+fn nvme_teardown(dev: &NvmeDevice, ct: &ControllerType) {
+    if !nvme_has_teardown(ct) {
+        return;
+    }
+    interrupt::run_teardown(|| {
+        let result = nvme_run_teardown(dev, ct);
+        if let Err(e) = &result {
+            eprintln!("warning: {} teardown failed: {}", controller_family_display(ct), e);
         }
-    } else {
-        match detect::detect(&dev, &info) {
-            Some(ct) => ct,
-            None => {
-                eprintln!(
-                    "error: could not auto-detect controller type for {}\n\
-                     model: {}\n\
-                     firmware: {}\n\
-                     vid: 0x{:04x}, ssvid: 0x{:04x}\n\n\
-                     try: ssd-flash-id --controller <type> {}\n\
-                     valid types: smi, rtl, phison, maxio, marvell, innogrit, tenafe",
-                    dev_path, info.model, info.firmware, info.vid, info.ssvid, dev_path
-                );
-                std::process::exit(1);
-            }
+        interrupt::record_teardown(result.is_ok());
+        if let Err(e) = dev.identify_controller() {
+            eprintln!("warning: controller did not respond after teardown: {}", e);
         }
-    };
+    });
+}
 
-    // Override Realtek variant if user specified one
+/// After a timeout the controller may be stuck on the aborted command. Report what
+/// happened and, with --recover, reset the controller through sysfs.
+fn nvme_check_health(dev: &NvmeDevice, dev_path: &str, recover: bool) {
+    if dev.timeouts() == 0 {
+        return;
+    }
+    eprintln!("warning: {} vendor command(s) timed out on {}", dev.timeouts(), dev_path);
+    if dev.identify_controller().is_ok() {
+        eprintln!("controller still responds to Identify; no recovery needed");
+        return;
+    }
+    if !recover {
+        eprintln!("controller is not responding; rerun with --recover to reset it");
+        return;
+    }
+    eprintln!("controller is not responding, resetting it");
+    if let Err(e) = dev.reset_controller() {
+        eprintln!("error: controller reset failed: {}", e);
+        return;
+    }
+    std::thread::sleep(RECOVERY_SETTLE);
+    match dev.identify_controller() {
+        Ok(_) => eprintln!("controller reset succeeded, Identify responds again"),
+        Err(e) => eprintln!("error: controller still not responding after reset: {}", e),
+    }
+}
+
+/// SATA counterpart of `nvme_check_health`, using a SCSI device reset.
+fn sata_check_health(dev: &AtaDevice, dev_path: &str, recover: bool) {
+    if dev.timeouts() == 0 {
+        return;
+    }
+    eprintln!("warning: {} vendor command(s) timed out on {}", dev.timeouts(), dev_path);
+    if dev.ata_identify().is_ok() {
+        eprintln!("device still responds to IDENTIFY; no recovery needed");
+        return;
+    }
+    if !recover {
+        eprintln!("device is not responding; rerun with --recover to reset it");
+        return;
+    }
+    eprintln!("device is not responding, sending SCSI device reset");
+    if let Err(e) = dev.reset_device() {
+        eprintln!("error: {}", e);
+        return;
+    }
+    std::thread::sleep(RECOVERY_SETTLE);
+    match dev.ata_identify() {
+        Ok(_) => eprintln!("device reset succeeded, IDENTIFY responds again"),
+        Err(e) => eprintln!("error: device still not responding after reset: {}", e),
+    }
+}
+
+fn nvme_reader_risk(ct: &ControllerType) -> Risk {
+    match ct {
+        ControllerType::Smi(_) => controllers::smi::RISK,
+        ControllerType::Realtek(_, _) => controllers::rtl::RISK,
+        ControllerType::Phison(_) => controllers::phison::RISK,
+        ControllerType::Maxio(_) => controllers::maxio::RISK,
+        ControllerType::Marvell(_) => controllers::marvell::RISK,
+        ControllerType::Innogrit(_) => controllers::innogrit::RISK,
+        ControllerType::Tenafe(_) => controllers::tenafe::RISK,
+        ControllerType::Defined(def) => def.risk,
+    }
+}
+
+fn print_evidence(evidence: &[Evidence], result: Option<(&ControllerType, Confidence)>) {
+    println!("Detection evidence:");
+    for e in evidence {
+        println!("  [{}] {}: {}", if e.matched { '+' } else { '-' }, e.heuristic, e.found);
+    }
+    match result {
+        Some((ct, confidence)) => println!("  => {}, {} confidence\n", ct.name(), confidence.name()),
+        None => println!("  => no match\n"),
+    }
+}
+
+fn print_link(info: &nvme::ControllerInfo, dev: &NvmeDevice) {
+    if let Some(ref pci) = info.pci {
+        println!("PCI        : {}", pci.describe());
+    }
+    if let Some(bridge) = dev.bridge() {
+        println!("USB bridge : {}", bridge.display());
+    }
+}
+
+/// DRAM or DRAM-less with the Host Memory Buffer state, judged from the first controller
+/// name that tells (reader's, then detection's) and the Identify HMB sizes.
+fn print_dram(info: &nvme::ControllerInfo, controllers: &[&str], dev: &NvmeDevice) {
+    let hmb = if info.hmpre > 0 { dev.host_memory_buffer().ok() } else { None };
+    if let Some(status) = controllers
+        .iter()
+        .find_map(|name| detect::dram_status(name, info.hmpre, hmb.as_ref()))
+    {
+        println!("DRAM       : {}", status);
+    }
+}
+
+/// Decoded Identify Controller fields for --details.
+fn print_nvme_details(info: &nvme::ControllerInfo) {
+    let list = |names: Vec<&str>| if names.is_empty() { "none".to_string() } else { names.join(", ") };
+    let kelvin = |k: u16| if k == 0 { "not reported".to_string() } else { format!("{} °C", k as i32 - 273) };
+    let hmb = |units: u32| format!("{} MiB", units as u64 * 4096 / (1024 * 1024));
+    println!("IEEE OUI   : {:06x}", info.ieee_oui);
+    println!("Ctrl ID    : 0x{:04x}", info.cntlid);
+    println!("NVMe       : {}", info.version_string().unwrap_or_else(|| "1.0/1.1 (VER not reported)".to_string()));
+    println!("CTRATT     : 0x{:08x} ({})", info.ctratt, list(nvme::ctratt_names(info.ctratt)));
+    println!("OACS       : 0x{:04x} ({})", info.oacs, list(nvme::oacs_names(info.oacs)));
+    println!(
+        "FW slots   : {}{}",
+        info.firmware_slots(),
+        if info.frmw & 0x01 != 0 { " (slot 1 read-only)" } else { "" }
+    );
+    if info.hmpre == 0 {
+        println!("HMB        : not supported");
+    } else {
+        println!("HMB        : preferred {}, minimum {}", hmb(info.hmpre), hmb(info.hmmin));
+    }
+    println!("Temp limit : warning {}, critical {}", kelvin(info.wctemp), kelvin(info.cctemp));
+    if info.tnvmcap != 0 {
+        println!(
+            "Capacity   : {} GB total, {} GB unallocated",
+            info.tnvmcap / 1_000_000_000,
+            info.unvmcap / 1_000_000_000
+        );
+    }
+    println!("Namespaces : {}", info.nn);
+    for (i, ps) in info.power_states.iter().enumerate() {
+        println!(
+            "Power ps{:<2}: {:.2} W{}, entry {} us, exit {} us",
+            i,
+            ps.watts(),
+            if ps.non_operational { " non-operational" } else { "" },
+            ps.entry_latency_us,
+            ps.exit_latency_us
+        );
+    }
+}
+
+/// Shown only when commands don't go through the usual ATA PASS-THROUGH(16) on the
+/// given node.
+fn print_ata_method(dev_path: &str, dev: &AtaDevice) {
+    if dev.node() != dev_path {
+        println!("SCSI node  : {}", dev.node());
+    }
+    if let Ok(method) = dev.method()
+        && method != AtaMethod::Pt16
+    {
+        println!("ATA access : {}", method.display());
+    }
+}
+
+/// Flags picks made on weak evidence next to the controller name.
+fn confidence_note(confidence: Option<Confidence>) -> &'static str {
+    match confidence {
+        Some(Confidence::Low) => "  [low confidence: matched by vendor ID or IEEE OUI only, see --explain-detect]",
+        _ => "",
+    }
+}
+
+fn print_dry_run_header(dev_path: &str, model: &str, firmware: &str) {
+    println!("Dry run for {} ({}, firmware {})", dev_path, model, firmware);
+    println!("vendor commands are printed, not sent; responses are treated as all zeros\n");
+}
+
+/// Why a reader may not run at the current --probe-level, or None when it may.
+fn risk_skip_reason(display: &str, risk: Risk, args: &Args) -> Option<String> {
+    (risk > args.probe_level).then(|| {
+        format!(
+            "the {} reader uses {} commands (needs --probe-level {}, current: {})",
+            display,
+            risk.description(),
+            risk.level_name(),
+            args.probe_level.level_name()
+        )
+    })
+}
+
+/// What `try_readers` needs from a family, whichever transport it belongs to.
+trait FamilyReader: Copy {
+    fn name(&self) -> &str;
+    fn display(&self) -> &str;
+    fn risk(&self) -> Risk;
+}
+
+struct ReaderRun<R> {
+    /// Readers whose commands were sent, for the teardowns
+    attempted: Vec<R>,
+    /// Readers above --probe-level
+    skipped: Vec<R>,
+    result: Result<(FlashIdResult, R), String>,
+}
+
+impl<R: FamilyReader> ReaderRun<R> {
+    fn skipped_note(&self, args: &Args) -> Option<String> {
+        if self.skipped.is_empty() {
+            return None;
+        }
+        let names: Vec<&str> = self.skipped.iter().map(|r| r.name()).collect();
+        Some(format!("skipped at --probe-level {}: {}", args.probe_level.level_name(), names.join(", ")))
+    }
+}
+
+/// Tries the candidate readers in order until one returns a result. Readers above
+/// --probe-level are skipped, and an interrupt ends the loop. `read` sets the reader's
+/// timeout and sends its commands.
+fn try_readers<R: FamilyReader>(
+    candidates: impl IntoIterator<Item = R>,
+    args: &Args,
+    mut read: impl FnMut(R) -> Result<FlashIdResult, String>,
+) -> ReaderRun<R> {
+    let mut run = ReaderRun {
+        attempted: Vec::new(),
+        skipped: Vec::new(),
+        result: Err("no controller family selected".to_string()),
+    };
+    for reader in candidates {
+        if let Some(reason) = risk_skip_reason(reader.display(), reader.risk(), args) {
+            run.skipped.push(reader);
+            run.result = Err(reason);
+            continue;
+        }
+        run.attempted.push(reader);
+        if args.dry_run {
+            println!("-- {} --", reader.display());
+        }
+        dump::set_label(reader.name());
+        match read(reader) {
+            Ok(r) => {
+                run.result = Ok((r, reader));
+                break;
+            }
+            Err(e) => run.result = Err(e),
+        }
+        if interrupt::check().is_err() {
+            break;
+        }
+    }
+    run
+}
+
+fn print_dry_run_summary(planned: u32, family: &str, result: &Result<FlashIdResult, String>) {
+    println!("\n{} vendor command(s) planned for {}", planned, family);
+    if let Err(e) = result {
+        // Readers branch on response contents, so zeroed responses can end the plan early
+        println!("with all-zero responses the reader stops here: {}", e);
+    }
+}
+
+fn print_banks(result: &FlashIdResult, raw: bool) {
+    if result.banks.is_empty() {
+        println!("no flash banks detected");
+        return;
+    }
+
+    for bank in &result.banks {
+        let hex = format_flash_id_hex(&bank.flash_id);
+        if raw {
+            println!("Bank{:02}: {}", bank.bank_num, hex);
+        } else {
+            let desc = describe_flash(&bank.flash_id);
+            println!("Bank{:02}: {} - {}", bank.bank_num, hex, desc);
+        }
+    }
+}
+
+fn run_nvme(
+    dev_path: &str,
+    bridge: Option<NvmeBridge>,
+    args: &Args,
+    defs: &[Definition],
+    db: &[DriveEntry],
+) {
+    let dev = open_nvme(dev_path, bridge);
+
+    if let Some(ms) = default_timeout(args) {
+        dev.set_timeout(ms);
+    }
+
+    let id_data = match dev.identify_controller() {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("error: failed to identify controller: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut info = parse_identify(&id_data);
+    info.pci = sysfs::nvme_pci_info(dev_path);
+    trace::set_device(dev_path, &info.serial);
+
+    if args.dry_run {
+        dev.set_dry_run(true);
+        print_dry_run_header(dev_path, &info.model, &info.firmware);
+    }
+
+    let mut confidence = None;
+    let mut ct = if let Some(ref forced) = args.controller {
+        if args.explain_detect {
+            println!("Detection skipped: controller forced with --controller {}\n", forced);
+        }
+        match resolve_nvme_controller_type(forced, defs) {
+            Some(ct) => ct,
+            None => {
+                eprintln!(
+                    "error: unknown controller type '{}'\n\nvalid nvme types: {}",
+                    forced,
+                    valid_types(NVME_TYPES, defs, DefTransport::Nvme)
+                );
+                std::process::exit(1);
+            }
+        }
+    } else {
+        dump::set_label("detect");
+        let mut evidence = Vec::new();
+        let detected = detect::detect(&dev, &info, args.probe_level, defs, db, &mut evidence);
+        interrupt::exit_if_interrupted();
+        if args.explain_detect {
+            print_evidence(&evidence, detected.as_ref().map(|(ct, c)| (ct, *c)));
+        }
+        match detected {
+            Some((ct, c)) => {
+                confidence = Some(c);
+                ct
+            }
+            None if args.dry_run => {
+                println!("\ndetection would depend on the probe responses above;");
+                println!("use --controller <type> to see a family's read plan");
+                return;
+            }
+            None => {
+                eprintln!(
+                    "error: could not auto-detect controller type for {}\n\
+                     model: {}\n\
+                     firmware: {}\n\
+                     vid: 0x{:04x}, ssvid: 0x{:04x}\n\
+                     pci: {}\n\n\
+                     try: ssd-flash-id --controller <type> {}\n\
+                     valid types: {}",
+                    dev_path,
+                    info.model,
+                    info.firmware,
+                    info.vid,
+                    info.ssvid,
+                    info.pci.as_ref().map(|p| p.describe()).unwrap_or_else(|| "unknown".into()),
+                    dev_path,
+                    valid_types(NVME_TYPES, defs, DefTransport::Nvme)
+                );
+                std::process::exit(1);
+            }
+        }
+    };
+
+    // Override Realtek variant if user specified one
     if let Some(variant) = args.rtl_variant {
         if let ControllerType::Realtek(ref name, _) = ct {
             ct = ControllerType::Realtek(name.clone(), variant);
@@ -340,15 +1302,56 @@ fn run_nvme(dev_path: &str, args: &Args) {
         }
     }
 
-    match nvme_read_flash_id(&dev, &ct) {
+    if let Some(reason) = risk_skip_reason(controller_family_display(&ct), nvme_reader_risk(&ct), args) {
+        println!("Model      : {}", info.model);
+        println!("Firmware   : {}", info.firmware);
+        print_link(&info, &dev);
+        if args.details {
+            print_nvme_details(&info);
+        }
+        println!(
+            "Controller : {} ({}){}",
+            ct.name(),
+            controller_family_display(&ct),
+            confidence_note(confidence)
+        );
+        print_dram(&info, &[ct.name()], &dev);
+        println!();
+        println!("flash ID read skipped: {}", reason);
+        return;
+    }
+
+    if let Some(ms) = timeout_for(args, ct.family()) {
+        dev.set_timeout(ms);
+    }
+
+    dump::set_label(ct.family());
+    let read_result = nvme_read_flash_id(&dev, &ct, args);
+    dump::set_label("teardown");
+    nvme_teardown(&dev, &ct);
+    interrupt::run_teardown(|| nvme_check_health(&dev, dev_path, args.recover));
+    interrupt::exit_if_interrupted();
+
+    if args.dry_run {
+        print_dry_run_summary(dev.planned(), ct.name(), &read_result);
+        return;
+    }
+
+    match read_result {
         Ok(result) => {
             println!("Model      : {}", info.model);
             println!("Firmware   : {}", info.firmware);
+            print_link(&info, &dev);
+            if args.details {
+                print_nvme_details(&info);
+            }
             println!(
-                "Controller : {} ({})",
+                "Controller : {} ({}){}",
                 result.controller_name,
-                controller_family_display(&ct)
+                controller_family_display(&ct),
+                confidence_note(confidence)
             );
+            print_dram(&info, &[&result.controller_name, ct.name()], &dev);
             println!();
             print_banks(&result, args.raw);
         }
@@ -364,35 +1367,169 @@ fn run_nvme(dev_path: &str, args: &Args) {
                 ct.family()
             );
             eprintln!("this may mean the controller is a different type than detected.\n");
+            if confidence == Some(Confidence::Low) {
+                eprintln!("detection matched only the vendor ID or IEEE OUI (see --explain-detect).\n");
+            }
             eprintln!("try a different controller type:");
             eprintln!("  ssd-flash-id --controller <type> {}", dev_path);
-            eprintln!("  valid types: smi, rtl, phison, maxio, marvell, innogrit, tenafe");
+            eprintln!("  valid types: {}", valid_types(NVME_TYPES, defs, DefTransport::Nvme));
             std::process::exit(1);
         }
     }
 }
 
-fn run_sata(dev_path: &str, args: &Args) {
+struct SataFamily {
+    name: &'static str,
+    display: &'static str,
+    read: fn(&AtaDevice) -> Result<FlashIdResult, String>,
+    risk: Risk,
+}
+
+/// In auto-detect probing order, least invasive first. None of the built-in readers
+/// has a teardown: the JMicron and Realtek unlocks have no documented relock.
+const SATA_FAMILIES: &[SataFamily] = &[
+    SataFamily {
+        name: "yeestor",
+        display: "Yeestor/SiliconGo",
+        read: controllers::yeestor::read_flash_id,
+        risk: controllers::yeestor::RISK,
+    },
+    SataFamily {
+        name: "smi-sata",
+        display: "Silicon Motion",
+        read: controllers::smi_sata::read_flash_id,
+        risk: controllers::smi_sata::RISK,
+    },
+    SataFamily {
+        name: "sandforce",
+        display: "SandForce",
+        read: controllers::sandforce::read_flash_id,
+        risk: controllers::sandforce::RISK,
+    },
+    SataFamily {
+        name: "jm",
+        display: "JMicron/Maxio",
+        read: read_jm_sata,
+        risk: controllers::jm_sata::RISK,
+    },
+    SataFamily {
+        name: "rtl-sata",
+        display: "Realtek",
+        read: controllers::rtl_sata::read_flash_id,
+        risk: controllers::rtl_sata::RISK,
+    },
+];
+
+fn sata_family(name: &str) -> Option<&'static SataFamily> {
+    SATA_FAMILIES.iter().find(|f| f.name == name)
+}
+
+/// A SATA reader: a built-in family or one loaded from a definition file.
+#[derive(Clone, Copy)]
+enum SataReader<'a> {
+    Builtin(&'static SataFamily),
+    Defined(&'a Definition),
+}
+
+impl<'a> SataReader<'a> {
+    fn name(&self) -> &'a str {
+        match self {
+            SataReader::Builtin(f) => f.name,
+            SataReader::Defined(def) => &def.name,
+        }
+    }
+
+    fn display(&self) -> &'a str {
+        match self {
+            SataReader::Builtin(f) => f.display,
+            SataReader::Defined(def) => &def.display,
+        }
+    }
+
+    fn risk(&self) -> Risk {
+        match self {
+            SataReader::Builtin(f) => f.risk,
+            SataReader::Defined(def) => def.risk,
+        }
+    }
+
+    fn read(&self, dev: &AtaDevice) -> Result<FlashIdResult, String> {
+        match self {
+            SataReader::Builtin(f) => (f.read)(dev),
+            SataReader::Defined(def) => controllers::defined::read_flash_id_ata(dev, def),
+        }
+    }
+
+    /// None when the reader needs no teardown; only definitions can declare one.
+    fn teardown(&self, dev: &AtaDevice) -> Option<Result<(), String>> {
+        match self {
+            SataReader::Defined(def) if !def.teardown.is_empty() => {
+                Some(controllers::defined::teardown_ata(dev, def))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl FamilyReader for SataReader<'_> {
+    fn name(&self) -> &str {
+        SataReader::name(self)
+    }
+
+    fn display(&self) -> &str {
+        SataReader::display(self)
+    }
+
+    fn risk(&self) -> Risk {
+        SataReader::risk(self)
+    }
+}
+
+fn read_jm_sata(dev: &AtaDevice) -> Result<FlashIdResult, String> {
+    let fw_response = controllers::jm_sata::read_firmware_id(dev)?;
+    controllers::jm_sata::read_flash_id(dev, &fw_response)
+}
+
+fn sata_teardown(dev: &AtaDevice, attempted: &[SataReader]) {
+    interrupt::run_teardown(|| {
+        let mut ran = false;
+        for reader in attempted.iter().rev() {
+            if let Some(result) = reader.teardown(dev) {
+                if let Err(e) = &result {
+                    eprintln!("warning: {} teardown failed: {}", reader.display(), e);
+                }
+                interrupt::record_teardown(result.is_ok());
+                ran = true;
+            }
+        }
+        if ran && let Err(e) = dev.ata_identify() {
+            eprintln!("warning: device did not respond after teardown: {}", e);
+        }
+    });
+}
+
+fn run_sata(dev_path: &str, args: &Args, defs: &[Definition], db: &[DriveEntry]) {
     let forced = args.controller.as_deref();
     const SATA_TYPES: &[&str] = &["jm", "smi-sata", "yeestor", "sandforce", "rtl-sata"];
+    let ata_defs: Vec<&Definition> = defs.iter().filter(|d| d.transport == DefTransport::Ata).collect();
+    let forced_def = forced.and_then(|f| ata_defs.iter().copied().find(|d| d.name == f));
     if let Some(f) = forced
+        && forced_def.is_none()
         && !SATA_TYPES.contains(&f)
     {
         eprintln!(
             "error: controller type '{}' is not supported for SATA devices\n\nsupported sata types: {}",
             f,
-            SATA_TYPES.join(", ")
+            valid_types(SATA_TYPES, defs, DefTransport::Ata)
         );
         std::process::exit(1);
     }
 
-    let dev = match AtaDevice::open(dev_path) {
-        Ok(d) => d,
-        Err(e) => {
-            eprintln!("error: {}", e);
-            std::process::exit(1);
-        }
-    };
+    let dev = open_ata(dev_path, args);
+
+    if let Some(ms) = default_timeout(args) {
+        dev.set_timeout(ms);
+    }
 
     let id_data = match dev.ata_identify() {
         Ok(d) => d,
@@ -402,54 +1539,80 @@ fn run_sata(dev_path: &str, args: &Args) {
         }
     };
     let info = parse_ata_identify(&id_data);
+    trace::set_device(dev_path, &info.serial);
+    sata_guard(dev_path, &dev, &id_data, args);
+
+    if args.dry_run {
+        dev.set_dry_run(true);
+        print_dry_run_header(dev_path, &info.model, &info.firmware);
+    }
 
     // Check ATA IDENTIFY data for embedded flash IDs (some controllers store them in vendor words)
     let identify_fid = controllers::ata_identify_fid::extract_from_identify(&id_data);
 
-    // Try controllers in order: firmware detection first, then probing
-    let result = if forced == Some("jm") {
-        try_jm_sata(&dev)
-    } else if forced == Some("smi-sata") {
-        try_smi_sata(&dev)
-    } else if forced == Some("yeestor") {
-        try_yeestor(&dev)
-    } else if forced == Some("sandforce") {
-        try_sandforce(&dev)
-    } else if forced == Some("rtl-sata") {
-        try_rtl_sata(&dev)
-    } else {
-        // Auto-detect: check firmware strings first
-        if controllers::smi_sata::detect_from_firmware(&info.firmware).is_some() {
-            try_smi_sata(&dev)
-        } else if controllers::rtl_sata::detect_from_firmware(&info.firmware).is_some() {
-            try_rtl_sata(&dev)
-        } else {
-            // Try each controller family in order of least-invasive
-            try_yeestor(&dev)
-                .or_else(|_| try_smi_sata(&dev))
-                .or_else(|_| try_sandforce(&dev))
-                .or_else(|_| try_jm_sata(&dev))
-                .or_else(|_| try_rtl_sata(&dev))
-                .or_else(|_| {
-                    // Last resort: check if flash ID was embedded in ATA IDENTIFY data
-                    identify_fid
-                        .clone()
-                        .map(|r| (r, "SATA"))
-                        .ok_or_else(|| "no vendor commands succeeded and no flash ID in IDENTIFY data".to_string())
-                })
+    // Try controllers in order: definitions and firmware detection first, then probing
+    let matching_def = ata_defs.iter().copied().find(|d| d.matches(&info.firmware, &info.model, 0, 0));
+    let db_reader = db.iter().filter(|e| e.matches(&info.model, &info.firmware)).find_map(|e| {
+        match ata_defs.iter().copied().find(|d| d.name == e.family) {
+            Some(def) => Some(SataReader::Defined(def)),
+            None => sata_family(&e.family).map(SataReader::Builtin),
         }
+    });
+    let builtin = |name| sata_family(name).map(SataReader::Builtin).into_iter().collect();
+    let (candidates, probing): (Vec<SataReader>, bool) = if let Some(def) = forced_def {
+        (vec![SataReader::Defined(def)], false)
+    } else if let Some(f) = forced {
+        (builtin(f), false)
+    } else if let Some(def) = matching_def {
+        (vec![SataReader::Defined(def)], false)
+    } else if let Some(reader) = db_reader {
+        (vec![reader], false)
+    } else if controllers::smi_sata::detect_from_firmware(&info.firmware).is_some() {
+        (builtin("smi-sata"), false)
+    } else if controllers::rtl_sata::detect_from_firmware(&info.firmware).is_some() {
+        (builtin("rtl-sata"), false)
+    } else {
+        (SATA_FAMILIES.iter().map(SataReader::Builtin).collect(), true)
     };
 
+    let run = try_readers(candidates, args, |reader| {
+        dev.set_timeout(timeout_for(args, reader.name()).unwrap_or(ata::DEFAULT_TIMEOUT_MS));
+        reader.read(&dev)
+    });
+    let skipped_note = run.skipped_note(args);
+    let mut result = run.result.map(|(r, reader)| (r, reader.display()));
+    if probing && result.is_err() {
+        // Last resort: check if flash ID was embedded in ATA IDENTIFY data
+        result = identify_fid
+            .map(|r| (r, "SATA"))
+            .ok_or_else(|| "no vendor commands succeeded and no flash ID in IDENTIFY data".to_string());
+    }
+
+    dev.set_timeout(default_timeout(args).unwrap_or(ata::DEFAULT_TIMEOUT_MS));
+    dump::set_label("teardown");
+    sata_teardown(&dev, &run.attempted);
+    interrupt::run_teardown(|| sata_check_health(&dev, dev_path, args.recover));
+    interrupt::exit_if_interrupted();
+
+    if args.dry_run {
+        let names: Vec<&str> = run.attempted.iter().map(|r| r.display()).collect();
+        print_dry_run_summary(dev.planned(), &names.join(", "), &result.map(|(r, _)| r));
+        return;
+    }
+
     let (result, family) = match result {
         Ok(r) => r,
         Err(e) => {
             eprintln!("error: {}", e);
             eprintln!("\nmodel: {}", info.model);
             eprintln!("firmware: {}", info.firmware);
+            if let Some(note) = skipped_note {
+                eprintln!("\n{}", note);
+            }
             eprintln!("\nthis SATA device may not have a supported controller.");
             eprintln!(
                 "supported sata types: {}",
-                SATA_TYPES.join(", ")
+                valid_types(SATA_TYPES, defs, DefTransport::Ata)
             );
             std::process::exit(1);
         }
@@ -457,35 +1620,646 @@ fn run_sata(dev_path: &str, args: &Args) {
 
     println!("Model      : {}", info.model);
     println!("Firmware   : {}", info.firmware);
+    print_ata_method(dev_path, &dev);
     println!("Controller : {} ({})", result.controller_name, family);
     println!();
     print_banks(&result, args.raw);
 }
 
-fn try_jm_sata(dev: &AtaDevice) -> Result<(FlashIdResult, &'static str), String> {
-    let fw_response = controllers::jm_sata::read_firmware_id(dev)?;
-    let result = controllers::jm_sata::read_flash_id(dev, &fw_response)?;
-    Ok((result, "JMicron/Maxio"))
+type MmcTeardown = fn(&MmcDevice) -> Result<(), String>;
+
+struct MmcFamily {
+    name: &'static str,
+    display: &'static str,
+    read: fn(&MmcDevice) -> Result<FlashIdResult, String>,
+    risk: Risk,
+    /// CID manufacturer IDs that select the family without probing
+    manfids: &'static [u8],
+    /// Also tried on eMMC of other manufacturers. Only single GEN_CMD reads are; readers
+    /// that write a GEN_CMD request or enter a CMD62 vendor mode need a manfid match or
+    /// --controller.
+    probe: bool,
+    /// Restores normal operation after the family's vendor commands (run even on failure)
+    teardown: Option<MmcTeardown>,
+}
+
+/// In auto-detect probing order; only the `probe` families are tried without a match.
+impl FamilyReader for &MmcFamily {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn display(&self) -> &str {
+        self.display
+    }
+
+    fn risk(&self) -> Risk {
+        self.risk
+    }
+}
+
+const MMC_FAMILIES: &[MmcFamily] = &[
+    MmcFamily {
+        name: "hynix-emmc",
+        display: "SK hynix",
+        read: controllers::hynix_emmc::read_flash_id,
+        risk: controllers::hynix_emmc::RISK,
+        manfids: &[0x90],
+        probe: true,
+        teardown: None,
+    },
+    MmcFamily {
+        name: "sandisk-emmc",
+        display: "SanDisk/WD",
+        read: controllers::sandisk_emmc::read_flash_id,
+        risk: controllers::sandisk_emmc::RISK,
+        manfids: &[0x45],
+        probe: true,
+        teardown: None,
+    },
+    MmcFamily {
+        name: "kioxia-emmc",
+        display: "Kioxia/Toshiba",
+        read: controllers::kioxia_emmc::read_flash_id,
+        risk: controllers::kioxia_emmc::RISK,
+        manfids: &[],
+        probe: false,
+        teardown: None,
+    },
+    MmcFamily {
+        name: "phison-emmc",
+        display: "Phison",
+        read: controllers::phison_emmc::read_flash_id,
+        risk: controllers::phison_emmc::RISK,
+        manfids: &[],
+        probe: false,
+        teardown: None,
+    },
+    MmcFamily {
+        name: "smi-emmc",
+        display: "Silicon Motion",
+        read: controllers::smi_emmc::read_flash_id,
+        risk: controllers::smi_emmc::RISK,
+        manfids: &[],
+        probe: false,
+        teardown: None,
+    },
+    MmcFamily {
+        name: "samsung-emmc",
+        display: "Samsung",
+        read: controllers::samsung_emmc::read_flash_id,
+        risk: controllers::samsung_emmc::RISK,
+        manfids: &[0x15],
+        probe: false,
+        teardown: Some(controllers::samsung_emmc::teardown),
+    },
+];
+
+impl MmcFamily {
+    /// Neither selected by a manfid nor probed: the reader only runs with --controller.
+    fn controller_only(&self) -> bool {
+        self.manfids.is_empty() && !self.probe
+    }
+}
+
+fn mmc_family(name: &str) -> Option<&'static MmcFamily> {
+    MMC_FAMILIES.iter().find(|f| f.name == name)
+}
+
+/// Model and firmware from the CID (sysfs) and EXT_CSD (CMD8).
+fn mmc_identify(dev: &MmcDevice, dev_path: &str) -> Result<mmc::MmcInfo, String> {
+    let cid = sysfs::mmc_cid(dev_path)
+        .ok_or_else(|| format!("cannot read the CID of '{}' from sysfs", dev_path))?;
+    let ext_csd = dev.ext_csd().map_err(|e| format!("failed to read EXT_CSD: {}", e))?;
+    Ok(mmc::parse_ext_csd(&mmc::parse_cid(&cid), &ext_csd))
+}
+
+fn mmc_info(dev_path: &str) -> Result<mmc::MmcInfo, String> {
+    mmc_identify(&MmcDevice::open(dev_path)?, dev_path)
+}
+
+/// Opens an eMMC; SD cards given by path are refused, vendor commands are meant for
+/// soldered eMMC only.
+fn open_mmc(dev_path: &str) -> MmcDevice {
+    if !sysfs::is_emmc(dev_path) {
+        eprintln!("error: {} is not an eMMC (SD cards are not supported)", dev_path);
+        std::process::exit(1);
+    }
+    match MmcDevice::open(dev_path) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn mmc_teardown(dev: &MmcDevice, attempted: &[&MmcFamily]) {
+    interrupt::run_teardown(|| {
+        for family in attempted.iter().rev() {
+            if let Some(teardown) = family.teardown {
+                let result = teardown(dev);
+                if let Err(e) = &result {
+                    eprintln!("warning: {} teardown failed: {}", family.display, e);
+                }
+                interrupt::record_teardown(result.is_ok());
+            }
+        }
+    });
+}
+
+/// eMMC counterpart of `nvme_check_health`. There is no reset the host can request
+/// through the block driver, so a card that stopped answering needs a power cycle.
+fn mmc_check_health(dev: &MmcDevice, dev_path: &str) {
+    if dev.timeouts() == 0 {
+        return;
+    }
+    eprintln!("warning: {} vendor command(s) timed out on {}", dev.timeouts(), dev_path);
+    match dev.ext_csd() {
+        Ok(_) => eprintln!("card still responds to SEND_EXT_CSD; no recovery needed"),
+        Err(e) => eprintln!("error: card is not responding ({}); power-cycle the board", e),
+    }
+}
+
+fn run_mmc(dev_path: &str, args: &Args) {
+    const MMC_TYPES: &[&str] =
+        &["samsung-emmc", "hynix-emmc", "kioxia-emmc", "sandisk-emmc", "phison-emmc", "smi-emmc"];
+    let forced = args.controller.as_deref();
+    if let Some(f) = forced
+        && !MMC_TYPES.contains(&f)
+    {
+        eprintln!(
+            "error: controller type '{}' is not supported for eMMC devices\n\nsupported emmc types: {}",
+            f,
+            MMC_TYPES.join(", ")
+        );
+        std::process::exit(1);
+    }
+
+    let dev = open_mmc(dev_path);
+    if let Some(ms) = default_timeout(args) {
+        dev.set_timeout(ms);
+    }
+    let info = match mmc_identify(&dev, dev_path) {
+        Ok(i) => i,
+        Err(e) => {
+            eprintln!("error: failed to identify device: {}", e);
+            std::process::exit(1);
+        }
+    };
+    trace::set_device(dev_path, &info.serial);
+
+    if args.dry_run {
+        dev.set_dry_run(true);
+        print_dry_run_header(dev_path, &info.model, &info.firmware);
+    }
+
+    let by_manfid = MMC_FAMILIES.iter().find(|f| f.manfids.contains(&info.manfid));
+    let candidates: Vec<&MmcFamily> = if let Some(f) = forced {
+        mmc_family(f).into_iter().collect()
+    } else if let Some(family) = by_manfid {
+        vec![family]
+    } else {
+        MMC_FAMILIES.iter().filter(|f| f.probe).collect()
+    };
+
+    let run = try_readers(candidates, args, |family| {
+        dev.set_timeout(timeout_for(args, family.name).unwrap_or(mmc::DEFAULT_TIMEOUT_MS));
+        (family.read)(&dev)
+    });
+    let skipped_note = run.skipped_note(args);
+
+    dev.set_timeout(default_timeout(args).unwrap_or(mmc::DEFAULT_TIMEOUT_MS));
+    dump::set_label("teardown");
+    mmc_teardown(&dev, &run.attempted);
+    interrupt::run_teardown(|| mmc_check_health(&dev, dev_path));
+    interrupt::exit_if_interrupted();
+
+    if args.dry_run {
+        let names: Vec<&str> = run.attempted.iter().map(|f| f.display).collect();
+        print_dry_run_summary(dev.planned(), &names.join(", "), &run.result.map(|(r, _)| r));
+        return;
+    }
+
+    let (result, family) = match run.result {
+        Ok((r, family)) => (r, family.display),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("\nmodel: {}", info.model);
+            eprintln!("firmware: {}", info.firmware);
+            if let Some(note) = skipped_note {
+                eprintln!("\n{}", note);
+            }
+            eprintln!("\nthis eMMC device may not have a supported controller.");
+            eprintln!("supported emmc types: {}", MMC_TYPES.join(", "));
+            std::process::exit(1);
+        }
+    };
+
+    println!("Model      : {}", info.model);
+    println!("Firmware   : {}", info.firmware);
+    print_mmc_card(&info);
+    println!("Controller : {} ({})", result.controller_name, family);
+    println!();
+    print_banks(&result, args.raw);
+}
+
+fn print_mmc_card(info: &mmc::MmcInfo) {
+    println!(
+        "eMMC       : {} (0x{:02x}), eMMC {}, made {}, {} GB",
+        mmc::manufacturer_name(info.manfid),
+        info.manfid,
+        mmc::spec_version(info.ext_csd_rev),
+        info.date,
+        info.capacity / 1_000_000_000
+    );
+    if info.life_time != (0, 0) {
+        println!(
+            "Life used  : type A {}, type B {}",
+            life_time_text(info.life_time.0),
+            life_time_text(info.life_time.1)
+        );
+    }
+}
+
+/// DEVICE_LIFE_TIME_EST value: 1 = 0-10% of the rated life used, ... 0x0B = exceeded.
+fn life_time_text(value: u8) -> String {
+    match value {
+        0 => "not reported".to_string(),
+        1..=0x0A => format!("{}-{}%", (value - 1) * 10, value * 10),
+        0x0B => "exceeded".to_string(),
+        _ => format!("reserved (0x{:02x})", value),
+    }
+}
+
+struct UfsFamily {
+    name: &'static str,
+    display: &'static str,
+    read: fn(&ScsiDevice) -> Result<FlashIdResult, String>,
+    risk: Risk,
+    /// Device descriptor wManufacturerID values that select the family. UFS devices
+    /// are never probed with another vendor's commands.
+    manufacturer_ids: &'static [u16],
 }
 
-fn try_smi_sata(dev: &AtaDevice) -> Result<(FlashIdResult, &'static str), String> {
-    let result = controllers::smi_sata::read_flash_id(dev)?;
-    Ok((result, "Silicon Motion"))
+impl FamilyReader for &UfsFamily {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn display(&self) -> &str {
+        self.display
+    }
+
+    fn risk(&self) -> Risk {
+        self.risk
+    }
 }
 
-fn try_yeestor(dev: &AtaDevice) -> Result<(FlashIdResult, &'static str), String> {
-    let result = controllers::yeestor::read_flash_id(dev)?;
-    Ok((result, "Yeestor/SiliconGo"))
+const UFS_FAMILIES: &[UfsFamily] = &[
+    UfsFamily {
+        name: "samsung-ufs",
+        display: "Samsung",
+        read: controllers::samsung_ufs::read_flash_id,
+        risk: controllers::samsung_ufs::RISK,
+        manufacturer_ids: &[0x01CE],
+    },
+    UfsFamily {
+        name: "hynix-ufs",
+        display: "SK hynix",
+        read: controllers::hynix_ufs::read_flash_id,
+        risk: controllers::hynix_ufs::RISK,
+        manufacturer_ids: &[0x01AD],
+    },
+    UfsFamily {
+        name: "kioxia-ufs",
+        display: "Kioxia/Toshiba",
+        read: controllers::kioxia_ufs::read_flash_id,
+        risk: controllers::kioxia_ufs::RISK,
+        manufacturer_ids: &[0x0198],
+    },
+];
+
+fn open_ufs(bsg_path: &str) -> (UfsDevice, ufs::UfsInfo) {
+    let dev = match UfsDevice::open(bsg_path) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    match ufs::identify(&dev) {
+        Ok(info) => (dev, info),
+        Err(e) => {
+            eprintln!("error: failed to read the device descriptor: {}", e);
+            std::process::exit(1);
+        }
+    }
 }
 
-fn try_sandforce(dev: &AtaDevice) -> Result<(FlashIdResult, &'static str), String> {
-    let result = controllers::sandforce::read_flash_id(dev)?;
-    Ok((result, "SandForce"))
+/// The logical unit vendor SCSI commands go to.
+fn open_ufs_lun(bsg_path: &str) -> Result<(String, ScsiDevice), String> {
+    let disk = sysfs::ufs_lun_disk(bsg_path)
+        .ok_or_else(|| format!("no logical unit (/dev/sdX) found behind {}", bsg_path))?;
+    let lun = ScsiDevice::open(&disk)?;
+    Ok((disk, lun))
 }
 
-fn try_rtl_sata(dev: &AtaDevice) -> Result<(FlashIdResult, &'static str), String> {
-    let result = controllers::rtl_sata::read_flash_id(dev)?;
-    Ok((result, "Realtek"))
+/// SCSI counterpart of `sata_check_health` for UFS logical units and USB drives.
+fn scsi_check_health(dev: &ScsiDevice, dev_path: &str, recover: bool) {
+    if dev.timeouts() == 0 {
+        return;
+    }
+    eprintln!("warning: {} vendor command(s) timed out on {}", dev.timeouts(), dev_path);
+    if dev.inquiry().is_ok() {
+        eprintln!("device still responds to INQUIRY; no recovery needed");
+        return;
+    }
+    if !recover {
+        eprintln!("device is not responding; rerun with --recover to reset it");
+        return;
+    }
+    eprintln!("device is not responding, sending SCSI device reset");
+    if let Err(e) = dev.reset_device() {
+        eprintln!("error: {}", e);
+        return;
+    }
+    std::thread::sleep(RECOVERY_SETTLE);
+    match dev.inquiry() {
+        Ok(_) => eprintln!("device reset succeeded, INQUIRY responds again"),
+        Err(e) => eprintln!("error: device still not responding after reset: {}", e),
+    }
+}
+
+fn run_ufs(bsg_path: &str, args: &Args) {
+    const UFS_TYPES: &[&str] = &["samsung-ufs", "hynix-ufs", "kioxia-ufs"];
+    let forced = args.controller.as_deref();
+    if let Some(f) = forced
+        && !UFS_TYPES.contains(&f)
+    {
+        eprintln!(
+            "error: controller type '{}' is not supported for UFS devices\n\nsupported ufs types: {}",
+            f,
+            UFS_TYPES.join(", ")
+        );
+        std::process::exit(1);
+    }
+
+    let (ufs_dev, info) = open_ufs(bsg_path);
+    trace::set_device(bsg_path, &info.serial);
+
+    let candidates: Vec<&UfsFamily> = match forced {
+        Some(f) => UFS_FAMILIES.iter().filter(|fam| fam.name == f).collect(),
+        None => UFS_FAMILIES
+            .iter()
+            .filter(|fam| fam.manufacturer_ids.contains(&info.manufacturer_id))
+            .collect(),
+    };
+    let lun = if candidates.is_empty() { None } else { Some(open_ufs_lun(bsg_path)) };
+
+    if args.dry_run {
+        print_dry_run_header(bsg_path, &info.model, &info.firmware);
+    }
+
+    let mut skipped_note = None;
+    let mut result = Err(format!(
+        "no vendor reader for {} UFS devices (manufacturer ID 0x{:04x})",
+        ufs::manufacturer_name(info.manufacturer_id),
+        info.manufacturer_id
+    ));
+    let mut planned = 0;
+    match lun {
+        Some(Err(e)) => result = Err(e),
+        Some(Ok((ref disk, ref dev))) => {
+            if let Some(ms) = default_timeout(args) {
+                dev.set_timeout(ms);
+            }
+            dev.set_dry_run(args.dry_run);
+            let run = try_readers(candidates, args, |family| {
+                dev.set_timeout(timeout_for(args, family.name).unwrap_or(scsi::DEFAULT_TIMEOUT_MS));
+                (family.read)(dev)
+            });
+            skipped_note = run.skipped_note(args);
+            result = run.result.map(|(r, family)| (r, family.display));
+            interrupt::run_teardown(|| scsi_check_health(dev, disk, args.recover));
+            planned = dev.planned();
+        }
+        None => {}
+    }
+    interrupt::exit_if_interrupted();
+
+    if args.dry_run {
+        print_dry_run_summary(planned, "UFS", &result.map(|(r, _)| r));
+        return;
+    }
+
+    println!("Model      : {}", info.model);
+    println!("Firmware   : {}", info.firmware);
+    print_ufs_device(&info);
+    let (result, family) = match result {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            if let Some(note) = skipped_note {
+                eprintln!("{}", note);
+            }
+            eprintln!("supported ufs types: {}", UFS_TYPES.join(", "));
+            println!("\n{}", ufs::dump_descriptors(&ufs_dev));
+            std::process::exit(1);
+        }
+    };
+    println!("Controller : {} ({})", result.controller_name, family);
+    println!();
+    print_banks(&result, args.raw);
+}
+
+fn print_ufs_device(info: &ufs::UfsInfo) {
+    let capacity = info.capacity.map(|c| format!(", {} GB raw", c / 1_000_000_000)).unwrap_or_default();
+    println!(
+        "UFS        : {} (0x{:04x}), UFS {}{}",
+        if info.vendor.is_empty() { ufs::manufacturer_name(info.manufacturer_id) } else { &info.vendor },
+        info.manufacturer_id,
+        ufs::spec_version(info.spec_version),
+        capacity
+    );
+    if let Some((a, b)) = info.life_time.filter(|&l| l != (0, 0)) {
+        println!("Life used  : type A {}, type B {}", life_time_text(a), life_time_text(b));
+    }
+}
+
+struct UsbFamily {
+    name: &'static str,
+    display: &'static str,
+    read: fn(&ScsiDevice) -> Result<FlashIdResult, String>,
+    risk: Risk,
+    /// Only run when named with --controller: never detected and left out of --research
+    controller_only: bool,
+}
+
+/// USB flash drive controllers, picked by `usb_flash::detect`; sticks are never probed
+/// with another controller's CDBs.
+const USB_FAMILIES: &[UsbFamily] = &[
+    UsbFamily {
+        name: "phison-usb",
+        display: "Phison PS2251",
+        read: controllers::phison_usb::read_flash_id,
+        risk: controllers::phison_usb::RISK,
+        controller_only: false,
+    },
+    UsbFamily {
+        name: "smi-usb",
+        display: "SMI SM32xx",
+        read: controllers::smi_usb::read_flash_id,
+        risk: controllers::smi_usb::RISK,
+        controller_only: false,
+    },
+    UsbFamily {
+        name: "alcor-usb",
+        display: "Alcor Micro",
+        read: controllers::alcor_usb::read_flash_id,
+        risk: controllers::alcor_usb::RISK,
+        controller_only: false,
+    },
+    UsbFamily {
+        name: "innostor-usb",
+        display: "Innostor",
+        read: controllers::innostor_usb::read_flash_id,
+        risk: controllers::innostor_usb::RISK,
+        controller_only: true,
+    },
+    UsbFamily {
+        name: "chipsbank-usb",
+        display: "ChipsBank",
+        read: controllers::chipsbank_usb::read_flash_id,
+        risk: controllers::chipsbank_usb::RISK,
+        controller_only: false,
+    },
+];
+
+/// The USB flash drive family of a /dev/sdX: the --controller choice if it names one,
+/// else detected from the USB ID and SCSI INQUIRY. Only USB-attached disks are
+/// considered; a forced family on any other disk needs --force.
+fn usb_flash_for(dev_path: &str, forced: Option<&str>, force: bool) -> Option<&'static UsbFamily> {
+    let transport = sysfs::block_transport(dev_path);
+    if let Some(f) = forced {
+        let family = USB_FAMILIES.iter().find(|fam| fam.name == f)?;
+        if transport != BlockTransport::Usb {
+            usb_guard(dev_path, family, transport, force);
+        }
+        return Some(family);
+    }
+    if transport != BlockTransport::Usb {
+        return None;
+    }
+    let inquiry = ScsiDevice::open(dev_path).ok().and_then(|d| d.inquiry().ok());
+    let name = usb_flash::detect(sysfs::usb_id(dev_path), inquiry.as_ref())?;
+    USB_FAMILIES.iter().find(|fam| fam.name == name)
+}
+
+/// USB counterpart of `sata_guard`: refuses a USB flash reader on a disk that is not
+/// USB-attached unless --force is given.
+fn usb_guard(dev_path: &str, family: &UsbFamily, transport: BlockTransport, force: bool) {
+    if force {
+        return;
+    }
+    eprintln!(
+        "error: {} is not a USB flash drive ({} transport), the {} reader needs one",
+        dev_path,
+        transport.name(),
+        family.display
+    );
+    eprintln!("vendor commands were not sent; use --force to probe it anyway");
+    std::process::exit(1);
+}
+
+fn run_usb(dev_path: &str, family: &UsbFamily, args: &Args) {
+    let dev = match ScsiDevice::open(dev_path) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let (vendor, product, revision) = match dev.inquiry() {
+        Ok(inq) => usb_flash::inquiry_strings(&inq),
+        Err(e) => {
+            eprintln!("error: failed to identify device: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let model = format!("{} {}", vendor, product);
+    let serial = sysfs::usb_serial(dev_path).unwrap_or_default();
+    trace::set_device(dev_path, &serial);
+
+    if let Some(reason) = risk_skip_reason(family.display, family.risk, args) {
+        eprintln!("error: {}", reason);
+        std::process::exit(1);
+    }
+
+    if args.dry_run {
+        dev.set_dry_run(true);
+        print_dry_run_header(dev_path, &model, &revision);
+        println!("-- {} --", family.display);
+    }
+    dev.set_timeout(
+        timeout_for(args, family.name)
+            .or(default_timeout(args))
+            .unwrap_or(scsi::DEFAULT_TIMEOUT_MS),
+    );
+    dump::set_label(family.name);
+    let result = (family.read)(&dev);
+    interrupt::run_teardown(|| scsi_check_health(&dev, dev_path, args.recover));
+    interrupt::exit_if_interrupted();
+
+    if args.dry_run {
+        print_dry_run_summary(dev.planned(), family.display, &result);
+        return;
+    }
+
+    let result = match result {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("Model      : {}", model);
+    println!("Firmware   : {}", revision);
+    if let Some((vid, pid)) = sysfs::usb_id(dev_path) {
+        println!("USB ID     : {:04x}:{:04x}", vid, pid);
+    }
+    println!("Controller : {}", result.controller_name);
+    println!();
+    print_banks(&result, args.raw);
+}
+
+fn run_console(dev_path: &str, kind: DeviceKind, bridge: Option<NvmeBridge>, args: &Args) {
+    match (kind, bridge) {
+        (DeviceKind::Mmc | DeviceKind::Ufs, _) => {
+            eprintln!("error: the console supports NVMe and SATA devices, not eMMC or UFS");
+            std::process::exit(1);
+        }
+        (DeviceKind::Sata, None) => {
+            let dev = open_ata(dev_path, args);
+            if let Ok(id_data) = dev.ata_identify() {
+                let info = parse_ata_identify(&id_data);
+                trace::set_device(dev_path, &info.serial);
+                println!("{}: {} (fw {}, sn {})", dev_path, info.model, info.firmware, info.serial);
+            }
+            dev.set_dry_run(args.dry_run);
+            console::run(console::ConsoleDevice::Ata(&dev));
+        }
+        _ => {
+            let dev = open_nvme(dev_path, bridge);
+            if let Ok(id_data) = dev.identify_controller() {
+                let info = parse_identify(&id_data);
+                trace::set_device(dev_path, &info.serial);
+                println!("{}: {} (fw {}, sn {})", dev_path, info.model, info.firmware, info.serial);
+            }
+            dev.set_dry_run(args.dry_run);
+            console::run(console::ConsoleDevice::Nvme(&dev));
+        }
+    }
 }
 
 fn main() {
@@ -495,8 +2269,63 @@ fn main() {
         print_usage();
         return;
     }
+    let has_target = args.device.is_some() || args.serial.is_some() || args.wwn.is_some();
+    if args.all && (has_target || args.console) {
+        eprintln!("error: --all selects the devices itself; it cannot be combined with a device");
+        std::process::exit(1);
+    }
+    // Raw commands go exactly where the user says, never to an auto-picked drive
+    if args.console && !has_target {
+        eprintln!("error: console requires a device, e.g.: ssd-flash-id console /dev/nvme0");
+        std::process::exit(1);
+    }
+    if args.research && args.controller.is_some() {
+        eprintln!("error: --research runs every family; it cannot be combined with --controller");
+        std::process::exit(1);
+    }
 
     check_root();
+    interrupt::install_handlers();
+    trace::set_verbosity(args.verbose);
+    if let Some(ref dest) = args.audit_log
+        && let Err(e) = trace::open_audit_log(dest)
+    {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+    if let Some(ref dir) = args.dump_dir
+        && let Err(e) = dump::open(dir)
+    {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+
+    let def_paths: Vec<String> =
+        std::iter::once(defs::SYSTEM_DEFS_DIR.to_string()).chain(args.defs.iter().cloned()).collect();
+    let defs = match defs::load(&def_paths) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let families = timeout_families(&defs);
+    if let Some(family) = args.timeouts.iter().filter_map(|(f, _)| f.as_deref()).find(|f| !families.contains(f)) {
+        eprintln!("error: unknown family '{}' in --timeout\n\nvalid families: {}", family, families.join(", "));
+        std::process::exit(1);
+    }
+
+    let db_paths: Vec<String> = std::iter::once(drive_db::SYSTEM_DRIVE_DB.to_string())
+        .chain(args.drive_db.iter().cloned())
+        .collect();
+    let db = match drive_db::load(&db_paths) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
 
     if args.list {
         list_devices();
@@ -505,29 +2334,96 @@ fn main() {
 
     let dev_path = match &args.device {
         Some(p) => p.clone(),
+        None if args.serial.is_some() || args.wwn.is_some() => {
+            match find_device_by_identity(args.serial.as_deref(), args.wwn.as_deref()) {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
         None => {
-            // Auto-detect: NVMe only. SATA requires explicit device path.
-            let devices = find_nvme_devices();
+            // Auto-detect: NVMe controllers and ATA SSDs; HDDs and other SCSI disks need a path
+            let devices = find_default_targets();
             if devices.is_empty() {
-                eprintln!("error: no NVMe devices found");
-                eprintln!("\nfor SATA devices, specify the path: ssd-flash-id /dev/sdX");
+                eprintln!("error: no NVMe devices or ATA SSDs found");
+                eprintln!("\nfor other SATA devices, specify the path: ssd-flash-id /dev/sdX");
                 std::process::exit(1);
             }
+            if args.all {
+                run_all(&devices);
+            }
             if devices.len() > 1 {
-                eprintln!("multiple NVMe devices found:");
+                eprintln!("multiple devices found:");
                 for d in &devices {
                     eprintln!("  {}", d);
                 }
                 eprintln!("\nspecify a device, e.g.: ssd-flash-id {}", devices[0]);
+                eprintln!("or run on all of them: ssd-flash-id --all");
                 std::process::exit(1);
             }
             devices.into_iter().next().unwrap()
         }
     };
 
-    if is_sata_path(&dev_path) {
-        run_sata(&dev_path, &args);
-    } else {
-        run_nvme(&dev_path, &args);
+    let resolved = match resolve_device(&dev_path) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Held until exit; the kernel also drops it if the process is killed
+    let _lock = match DeviceLock::acquire(
+        &resolved.path,
+        std::time::Duration::from_secs(args.lock_timeout),
+    ) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let bridge = match resolved.kind {
+        DeviceKind::Sata => nvme_bridge_for(&resolved.path, args.bridge),
+        DeviceKind::Nvme | DeviceKind::Mmc | DeviceKind::Ufs if args.bridge.is_some_and(|b| b.is_some()) => {
+            eprintln!("error: --bridge applies to USB enclosures (/dev/sdX), not {}", resolved.path);
+            std::process::exit(1);
+        }
+        DeviceKind::Nvme | DeviceKind::Mmc | DeviceKind::Ufs => None,
+    };
+
+    let usb_flash = match (resolved.kind, bridge) {
+        (DeviceKind::Sata, None) => usb_flash_for(&resolved.path, args.controller.as_deref(), args.force),
+        _ => None,
+    };
+
+    if args.console {
+        run_console(&resolved.path, resolved.kind, bridge, &args);
+        return;
+    }
+
+    if args.research {
+        match (resolved.kind, bridge) {
+            (DeviceKind::Mmc, _) => research::run_mmc(&resolved.path, &args),
+            (DeviceKind::Ufs, _) => research::run_ufs(&resolved.path, &args),
+            (DeviceKind::Sata, None) if usb_flash.is_some() => research::run_usb(&resolved.path, &args),
+            (DeviceKind::Sata, None) => research::run_sata(&resolved.path, &args, &defs),
+            _ => research::run_nvme(&resolved.path, bridge, &args, &defs),
+        }
+        return;
+    }
+
+    match (resolved.kind, bridge) {
+        (DeviceKind::Mmc, _) => run_mmc(&resolved.path, &args),
+        (DeviceKind::Ufs, _) => run_ufs(&resolved.path, &args),
+        (DeviceKind::Sata, None) => match usb_flash {
+            Some(family) => run_usb(&resolved.path, family, &args),
+            None => run_sata(&resolved.path, &args, &defs, &db),
+        },
+        _ => run_nvme(&resolved.path, bridge, &args, &defs, &db),
     }
 }
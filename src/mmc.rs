@@ -0,0 +1,450 @@
+use std::cell::Cell;
+use std::ffi::CString;
+use std::time::Instant;
+
+use crate::errno::errno_to_str;
+use crate::hexdump::hexdump;
+
+/// _IOWR(MMC_BLOCK_MAJOR, 0, struct mmc_ioc_cmd)
+const MMC_IOC_CMD: u64 = 0xC048B300;
+/// _IOWR(MMC_BLOCK_MAJOR, 1, struct mmc_ioc_multi_cmd)
+const MMC_IOC_MULTI_CMD: u64 = 0xC008B301;
+const MMC_IOC_MAX_CMDS: usize = 255;
+pub const DEFAULT_TIMEOUT_MS: u32 = 10_000;
+
+pub const MMC_SEND_EXT_CSD: u32 = 8;
+pub const MMC_READ_SINGLE_BLOCK: u32 = 17;
+pub const MMC_GEN_CMD: u32 = 56;
+pub const BLOCK_SIZE: usize = 512;
+
+// Response and command type flags from <linux/mmc/core.h>
+const MMC_RSP_PRESENT: u32 = 1 << 0;
+const MMC_RSP_CRC: u32 = 1 << 2;
+const MMC_RSP_BUSY: u32 = 1 << 3;
+const MMC_RSP_OPCODE: u32 = 1 << 4;
+const MMC_CMD_AC: u32 = 0 << 5;
+const MMC_CMD_ADTC: u32 = 1 << 5;
+const MMC_RSP_R1: u32 = MMC_RSP_PRESENT | MMC_RSP_CRC | MMC_RSP_OPCODE;
+const MMC_RSP_R1B: u32 = MMC_RSP_R1 | MMC_RSP_BUSY;
+
+/// R1 card status bits that report a failed command (JESD84 6.13)
+const R1_ERRORS: u32 = 0xFDF9_8080;
+const R1_ILLEGAL_COMMAND: u32 = 1 << 22;
+
+#[repr(C)]
+struct MmcIocCmd {
+    write_flag: i32,
+    is_acmd: i32,
+    opcode: u32,
+    arg: u32,
+    response: [u32; 4],
+    flags: u32,
+    blksz: u32,
+    blocks: u32,
+    postsleep_min_us: u32,
+    postsleep_max_us: u32,
+    data_timeout_ns: u32,
+    cmd_timeout_ms: u32,
+    pad: u32,
+    data_ptr: u64,
+}
+
+/// Data phase of one MMC command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmcData {
+    None,
+    /// R1b: the card signals busy until the command is done
+    NoneBusy,
+    Read,
+    Write,
+}
+
+/// One MMC command with its data buffer, for `MmcDevice::run`.
+pub struct MmcCmd<'a> {
+    pub opcode: u32,
+    pub arg: u32,
+    pub data: MmcData,
+    /// Whole blocks of BLOCK_SIZE bytes; empty for commands without data
+    pub buf: &'a mut [u8],
+}
+
+impl<'a> MmcCmd<'a> {
+    pub fn no_data(opcode: u32, arg: u32, busy: bool) -> Self {
+        let data = if busy { MmcData::NoneBusy } else { MmcData::None };
+        MmcCmd { opcode, arg, data, buf: &mut [] }
+    }
+
+    pub fn read(opcode: u32, arg: u32, buf: &'a mut [u8]) -> Self {
+        MmcCmd { opcode, arg, data: MmcData::Read, buf }
+    }
+
+    pub fn write(opcode: u32, arg: u32, buf: &'a mut [u8]) -> Self {
+        MmcCmd { opcode, arg, data: MmcData::Write, buf }
+    }
+
+    /// "mmc cmd56 arg=0x00000001 read len=512"
+    pub fn describe(&self) -> String {
+        let dir = match self.data {
+            MmcData::None => "none",
+            MmcData::NoneBusy => "none (busy)",
+            MmcData::Read => "read",
+            MmcData::Write => "write",
+        };
+        format!(
+            "mmc cmd{} arg=0x{:08x} {} len={}",
+            self.opcode,
+            self.arg,
+            dir,
+            self.buf.len()
+        )
+    }
+
+    fn ioc(&mut self, timeout_ms: u32) -> MmcIocCmd {
+        let (flags, blocks) = match self.data {
+            MmcData::None => (MMC_RSP_R1 | MMC_CMD_AC, 0),
+            MmcData::NoneBusy => (MMC_RSP_R1B | MMC_CMD_AC, 0),
+            MmcData::Read | MmcData::Write => (MMC_RSP_R1 | MMC_CMD_ADTC, self.buf.len() / BLOCK_SIZE),
+        };
+        MmcIocCmd {
+            write_flag: (self.data == MmcData::Write) as i32,
+            is_acmd: 0,
+            opcode: self.opcode,
+            arg: self.arg,
+            response: [0; 4],
+            flags,
+            blksz: if blocks > 0 { BLOCK_SIZE as u32 } else { 0 },
+            blocks: blocks as u32,
+            postsleep_min_us: 0,
+            postsleep_max_us: 0,
+            data_timeout_ns: 0,
+            cmd_timeout_ms: if self.data == MmcData::NoneBusy { timeout_ms } else { 0 },
+            pad: 0,
+            data_ptr: self.buf.as_mut_ptr() as u64,
+        }
+    }
+}
+
+pub struct MmcDevice {
+    fd: i32,
+    timeout_ms: Cell<u32>,
+    timeouts: Cell<u32>,
+    dry_run: Cell<bool>,
+    planned: Cell<u32>,
+}
+
+impl MmcDevice {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let c_path =
+            CString::new(path).map_err(|e| format!("invalid device path '{}': {}", path, e))?;
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_RDWR) };
+        if fd < 0 {
+            let errno = unsafe { *libc::__errno_location() };
+            return Err(format!(
+                "failed to open '{}': {} (errno {})",
+                path,
+                errno_to_str(errno),
+                errno
+            ));
+        }
+        Ok(MmcDevice {
+            fd,
+            timeout_ms: Cell::new(DEFAULT_TIMEOUT_MS),
+            timeouts: Cell::new(0),
+            dry_run: Cell::new(false),
+            planned: Cell::new(0),
+        })
+    }
+
+    /// Timeout for busy (R1b) commands. Data commands use the host's own data timeout.
+    pub fn set_timeout(&self, timeout_ms: u32) {
+        self.timeout_ms.set(timeout_ms);
+    }
+
+    /// In dry-run mode every command except SEND_EXT_CSD is printed instead of sent,
+    /// and read commands "return" a zeroed buffer.
+    pub fn set_dry_run(&self, dry_run: bool) {
+        self.dry_run.set(dry_run);
+    }
+
+    /// Number of commands printed (not sent) in dry-run mode.
+    pub fn planned(&self) -> u32 {
+        self.planned.get()
+    }
+
+    /// Number of commands that timed out since the device was opened.
+    pub fn timeouts(&self) -> u32 {
+        self.timeouts.get()
+    }
+
+    /// CMD8 SEND_EXT_CSD, the eMMC counterpart of Identify.
+    pub fn ext_csd(&self) -> Result<[u8; BLOCK_SIZE], String> {
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.run(&mut [MmcCmd::read(MMC_SEND_EXT_CSD, 0, &mut buf)])?;
+        Ok(buf)
+    }
+
+    /// CMD56 GEN_CMD read of one block. Bit 0 of the argument selects the read direction.
+    pub fn gen_cmd_read(&self, arg: u32) -> Result<[u8; BLOCK_SIZE], String> {
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.run(&mut [MmcCmd::read(MMC_GEN_CMD, arg | 1, &mut buf)])?;
+        Ok(buf)
+    }
+
+    /// CMD56 GEN_CMD write of one block.
+    pub fn gen_cmd_write(&self, arg: u32, data: &[u8; BLOCK_SIZE]) -> Result<(), String> {
+        let mut buf = *data;
+        self.run(&mut [MmcCmd::write(MMC_GEN_CMD, arg & !1, &mut buf)])
+    }
+
+    /// Sends the commands with MMC_IOC_MULTI_CMD, so the block layer can't slip regular
+    /// I/O in between. Vendor modes redirect ordinary reads while they are active, so a
+    /// sequence that enters one must be sent as a unit.
+    pub fn run(&self, cmds: &mut [MmcCmd]) -> Result<(), String> {
+        crate::interrupt::check()?;
+        if cmds.is_empty() || cmds.len() > MMC_IOC_MAX_CMDS {
+            return Err(format!("invalid mmc command sequence length {}", cmds.len()));
+        }
+        let vendor = cmds.iter().any(|c| c.opcode != MMC_SEND_EXT_CSD);
+        if self.dry_run.get() && vendor {
+            for cmd in cmds.iter_mut() {
+                self.planned.set(self.planned.get() + 1);
+                println!("[dry-run] {}", cmd.describe());
+                match cmd.data {
+                    MmcData::Write => print!("{}", hexdump(cmd.buf, "    ")),
+                    MmcData::Read => cmd.buf.fill(0),
+                    _ => {}
+                }
+            }
+            return Ok(());
+        }
+
+        let timeout_ms = self.timeout_ms.get();
+        let mut iocs: Vec<MmcIocCmd> = cmds.iter_mut().map(|c| c.ioc(timeout_ms)).collect();
+        let start = Instant::now();
+        let ret = if iocs.len() == 1 {
+            unsafe { libc::ioctl(self.fd, MMC_IOC_CMD, iocs.as_mut_ptr()) }
+        } else {
+            // struct mmc_ioc_multi_cmd: a u64 count followed by the commands
+            let mut multi: Vec<u64> = vec![0; 1 + iocs.len() * std::mem::size_of::<MmcIocCmd>() / 8];
+            multi[0] = iocs.len() as u64;
+            unsafe {
+                std::ptr::copy_nonoverlapping(iocs.as_ptr(), multi.as_mut_ptr().add(1) as *mut MmcIocCmd, iocs.len());
+                let ret = libc::ioctl(self.fd, MMC_IOC_MULTI_CMD, multi.as_mut_ptr());
+                std::ptr::copy_nonoverlapping(multi.as_ptr().add(1) as *const MmcIocCmd, iocs.as_mut_ptr(), iocs.len());
+                ret
+            }
+        };
+        let errno = if ret < 0 { unsafe { *libc::__errno_location() } } else { 0 };
+        let elapsed = start.elapsed();
+
+        // The ioctl reports one errno for the whole sequence. The failed command is the
+        // first one whose card status shows an error, or that got no status at all.
+        let failed = iocs
+            .iter()
+            .position(|i| i.response[0] & R1_ERRORS != 0 || (ret < 0 && i.response[0] == 0))
+            .or((ret < 0).then(|| iocs.len() - 1));
+        let mut result = Ok(());
+        for (n, (cmd, ioc)) in cmds.iter().zip(&iocs).enumerate() {
+            let status = ioc.response[0];
+            let outcome = match failed {
+                Some(f) if n == f && status & R1_ERRORS != 0 => Err(describe_card_status(cmd.opcode, status)),
+                Some(f) if n == f => Err(self.ioctl_error(cmd.opcode, errno)),
+                Some(f) if n > f => Err("not sent, an earlier command of the sequence failed".to_string()),
+                _ => Ok(()),
+            };
+            crate::trace::record(&crate::trace::CommandRecord {
+                tag: format!("mmc-cmd{}", cmd.opcode),
+                desc: cmd.describe(),
+                vendor: cmd.opcode != MMC_SEND_EXT_CSD,
+                duration: elapsed,
+                status: if ret < 0 {
+                    format!("errno {}, card status 0x{:08x}", errno, status)
+                } else {
+                    format!("card status 0x{:08x}", status)
+                },
+                error: outcome.as_ref().err().map(|e| e.as_str()),
+                data_out: Some(&*cmd.buf).filter(|b| cmd.data == MmcData::Write && !b.is_empty()),
+                data_in: Some(&*cmd.buf).filter(|b| cmd.data == MmcData::Read && !b.is_empty() && outcome.is_ok()),
+                sense: None,
+            });
+            if failed == Some(n) {
+                result = outcome;
+            }
+        }
+        result
+    }
+
+    fn ioctl_error(&self, opcode: u32, errno: i32) -> String {
+        if errno == libc::ETIMEDOUT {
+            self.timeouts.set(self.timeouts.get() + 1);
+            return format!("mmc command timed out (cmd{})", opcode);
+        }
+        format!("mmc ioctl failed: {} (errno {}, cmd{})", errno_to_str(errno), errno, opcode)
+    }
+}
+
+impl Drop for MmcDevice {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+fn describe_card_status(opcode: u32, status: u32) -> String {
+    if status & R1_ILLEGAL_COMMAND != 0 {
+        format!("mmc cmd{} rejected: illegal command (card status 0x{:08x})", opcode, status)
+    } else {
+        format!("mmc cmd{} failed: card status 0x{:08x}", opcode, status)
+    }
+}
+
+/// Card identification register (JESD84 7.2), as exposed in sysfs.
+pub struct Cid {
+    pub manfid: u8,
+    /// Product name, 6 ASCII characters
+    pub name: String,
+    /// Product revision, BCD major.minor
+    pub prv: u8,
+    pub serial: u32,
+    /// Manufacturing month and year field, decoded in `date`
+    pub mdt: u8,
+}
+
+impl Cid {
+    /// "2019-06". `ext_csd_rev` decides the year base: eMMC 4.41 and later count
+    /// from 2013 for the values 0-12.
+    pub fn date(&self, ext_csd_rev: u8) -> String {
+        let mut year = 1997 + (self.mdt & 0x0F) as u32;
+        if ext_csd_rev > 4 && year < 2010 {
+            year += 16;
+        }
+        format!("{}-{:02}", year, self.mdt >> 4)
+    }
+}
+
+pub fn parse_cid(cid: &[u8; 16]) -> Cid {
+    Cid {
+        manfid: cid[0],
+        name: ascii_trim(&cid[3..9]),
+        prv: cid[9],
+        serial: u32::from_be_bytes([cid[10], cid[11], cid[12], cid[13]]),
+        mdt: cid[14],
+    }
+}
+
+pub struct MmcInfo {
+    pub model: String,
+    pub firmware: String,
+    pub serial: String,
+    pub manfid: u8,
+    pub ext_csd_rev: u8,
+    pub date: String,
+    /// User area size in bytes
+    pub capacity: u64,
+    /// DEVICE_LIFE_TIME_EST_TYP_A/B, in 10% steps of the rated life used (0 = not reported)
+    pub life_time: (u8, u8),
+}
+
+/// Model from the CID product name, firmware from EXT_CSD FIRMWARE_VERSION (eMMC 5.0
+/// and later) or else the CID product revision.
+pub fn parse_ext_csd(cid: &Cid, ext_csd: &[u8; BLOCK_SIZE]) -> MmcInfo {
+    let rev = ext_csd[192];
+    let fw = &ext_csd[254..262];
+    let firmware = if rev >= 7 && fw.iter().any(|&b| b != 0) {
+        if fw.iter().all(|&b| b == 0 || b.is_ascii_graphic() || b == b' ') {
+            ascii_trim(fw)
+        } else {
+            fw.iter().map(|b| format!("{:02X}", b)).collect()
+        }
+    } else {
+        format!("{}.{}", cid.prv >> 4, cid.prv & 0x0F)
+    };
+    let sectors = u32::from_le_bytes([ext_csd[212], ext_csd[213], ext_csd[214], ext_csd[215]]);
+    MmcInfo {
+        model: cid.name.clone(),
+        firmware,
+        serial: format!("0x{:08x}", cid.serial),
+        manfid: cid.manfid,
+        ext_csd_rev: rev,
+        date: cid.date(rev),
+        capacity: sectors as u64 * 512,
+        life_time: (ext_csd[268], ext_csd[269]),
+    }
+}
+
+/// eMMC version for an EXT_CSD_REV value.
+pub fn spec_version(ext_csd_rev: u8) -> &'static str {
+    match ext_csd_rev {
+        0 => "4.0",
+        1 => "4.1",
+        2 => "4.2",
+        3 => "4.3",
+        5 => "4.41",
+        6 => "4.5",
+        7 => "5.0",
+        8 => "5.1",
+        _ => "unknown",
+    }
+}
+
+/// JEDEC manufacturer IDs seen in eMMC CIDs.
+pub fn manufacturer_name(manfid: u8) -> &'static str {
+    match manfid {
+        0x11 => "Kioxia/Toshiba",
+        0x13 | 0xFE => "Micron",
+        0x15 => "Samsung",
+        0x45 => "SanDisk",
+        0x70 => "Kingston",
+        0x90 => "SK hynix",
+        _ => "unknown",
+    }
+}
+
+fn ascii_trim(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches('\0')
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ioc_layout() {
+        // <linux/mmc/ioctl.h>: 72 bytes, matching the size encoded in MMC_IOC_CMD
+        assert_eq!(std::mem::size_of::<MmcIocCmd>(), 72);
+        assert_eq!((MMC_IOC_CMD >> 16) & 0x3FFF, 72);
+    }
+
+    #[test]
+    fn test_parse_cid_and_ext_csd() {
+        // Samsung 8GTF4R, PRV 0x07, serial 0x1a2b3c4d, June of year field 6
+        let cid = [
+            0x15, 0x01, 0x00, b'8', b'G', b'T', b'F', b'4', b'R', 0x07, 0x1A, 0x2B, 0x3C, 0x4D, 0x66, 0x00,
+        ];
+        let cid = parse_cid(&cid);
+        assert_eq!(cid.name, "8GTF4R");
+        assert_eq!(cid.serial, 0x1A2B3C4D);
+
+        let mut ext_csd = [0u8; BLOCK_SIZE];
+        ext_csd[192] = 8;
+        ext_csd[212..216].copy_from_slice(&0x00E9_0000u32.to_le_bytes());
+        ext_csd[254] = 0x05;
+        ext_csd[268] = 0x02;
+        let info = parse_ext_csd(&cid, &ext_csd);
+        assert_eq!(info.firmware, "0500000000000000");
+        assert_eq!(info.date, "2019-06");
+        assert_eq!(info.capacity, 0x00E9_0000 * 512);
+        assert_eq!(info.life_time, (2, 0));
+        assert_eq!(spec_version(info.ext_csd_rev), "5.1");
+
+        // Before eMMC 5.0 the firmware is the CID product revision
+        ext_csd[192] = 6;
+        let info = parse_ext_csd(&cid, &ext_csd);
+        assert_eq!(info.firmware, "0.7");
+        assert_eq!(info.date, "2019-06");
+    }
+}
@@ -1,7 +1,20 @@
+use std::cell::Cell;
 use std::ffi::CString;
+use std::time::Instant;
+
+use crate::errno::errno_to_str;
+use crate::hexdump::hexdump;
+use crate::usb_bridge::{BridgeError, NvmeBridge};
 
 const NVME_IOCTL_ADMIN_CMD: u64 = 0xC0484E41;
-const TIMEOUT_MS: u32 = 10_000;
+const NVME_ADMIN_IDENTIFY: u8 = 0x06;
+const NVME_ADMIN_GET_FEATURES: u8 = 0x0A;
+const NVME_FEAT_HOST_MEM_BUF: u32 = 0x0D;
+/// Memory page size HSIZE is counted in; Linux always programs CC.MPS for 4 KiB
+const NVME_MEM_PAGE_SIZE: u64 = 4096;
+pub const DEFAULT_TIMEOUT_MS: u32 = 10_000;
+/// Completion status the kernel reports for commands it aborted after a timeout
+const NVME_SC_HOST_ABORTED_CMD: i32 = 0x371;
 
 #[repr(C)]
 pub struct NvmeAdminCmd {
@@ -26,7 +39,7 @@ pub struct NvmeAdminCmd {
 }
 
 impl NvmeAdminCmd {
-    fn zeroed() -> Self {
+    pub fn zeroed() -> Self {
         NvmeAdminCmd {
             opcode: 0,
             flags: 0,
@@ -50,16 +63,51 @@ impl NvmeAdminCmd {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataDir {
+    None,
+    ToDevice,
+    FromDevice,
+}
+
+impl DataDir {
+    pub fn name(self) -> &'static str {
+        match self {
+            DataDir::None => "none",
+            DataDir::ToDevice => "to-device",
+            DataDir::FromDevice => "from-device",
+        }
+    }
+}
+
 pub struct NvmeDevice {
     fd: i32,
+    path: String,
+    /// Set for a /dev/sdX behind a USB bridge; commands go through its SCSI tunnel
+    bridge: Option<NvmeBridge>,
+    timeout_ms: Cell<u32>,
+    timeouts: Cell<u32>,
+    /// Status field and cdw0 of the last completed command
+    completion: Cell<Option<(u16, u32)>>,
+    dry_run: Cell<bool>,
+    planned: Cell<u32>,
 }
 
 #[allow(clippy::too_many_arguments)]
 impl NvmeDevice {
     pub fn open(path: &str) -> Result<Self, String> {
+        Self::open_with(path, libc::O_RDONLY, None)
+    }
+
+    /// Opens the SCSI disk of a USB enclosure and tunnels admin commands through `bridge`.
+    pub fn open_bridged(path: &str, bridge: NvmeBridge) -> Result<Self, String> {
+        Self::open_with(path, libc::O_RDWR, Some(bridge))
+    }
+
+    fn open_with(path: &str, flags: i32, bridge: Option<NvmeBridge>) -> Result<Self, String> {
         let c_path =
             CString::new(path).map_err(|e| format!("invalid device path '{}': {}", path, e))?;
-        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_RDONLY) };
+        let fd = unsafe { libc::open(c_path.as_ptr(), flags) };
         if fd < 0 {
             let errno = unsafe { *libc::__errno_location() };
             return Err(format!(
@@ -69,7 +117,69 @@ impl NvmeDevice {
                 errno
             ));
         }
-        Ok(NvmeDevice { fd })
+        Ok(NvmeDevice {
+            fd,
+            path: path.to_string(),
+            bridge,
+            timeout_ms: Cell::new(DEFAULT_TIMEOUT_MS),
+            timeouts: Cell::new(0),
+            completion: Cell::new(None),
+            dry_run: Cell::new(false),
+            planned: Cell::new(0),
+        })
+    }
+
+    pub fn bridge(&self) -> Option<NvmeBridge> {
+        self.bridge
+    }
+
+    pub fn set_timeout(&self, timeout_ms: u32) {
+        self.timeout_ms.set(timeout_ms);
+    }
+
+    /// Runs `f` with a different command timeout, e.g. for commands known to hang some firmware.
+    pub fn with_timeout<T>(&self, timeout_ms: u32, f: impl FnOnce() -> T) -> T {
+        let prev = self.timeout_ms.replace(timeout_ms);
+        let ret = f();
+        self.timeout_ms.set(prev);
+        ret
+    }
+
+    /// In dry-run mode every command except Identify is printed instead of sent, and
+    /// data-in commands "return" a zeroed buffer.
+    pub fn set_dry_run(&self, dry_run: bool) {
+        self.dry_run.set(dry_run);
+    }
+
+    /// Number of commands printed (not sent) in dry-run mode.
+    pub fn planned(&self) -> u32 {
+        self.planned.get()
+    }
+
+    /// Status field and result (cdw0) of the last command, None when it did not complete
+    /// (ioctl error, timeout, bridge failure).
+    pub fn completion(&self) -> Option<(u16, u32)> {
+        self.completion.get()
+    }
+
+    /// Number of commands that timed out since the device was opened.
+    pub fn timeouts(&self) -> u32 {
+        self.timeouts.get()
+    }
+
+    /// Resets the controller through sysfs, which also aborts any command it is stuck on.
+    /// Behind a USB bridge only the bridge can be reset, with a SCSI device reset.
+    pub fn reset_controller(&self) -> Result<(), String> {
+        if self.bridge.is_some() {
+            return crate::scsi::reset_device(self.fd);
+        }
+        let name = std::path::Path::new(&self.path)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let reset_path = format!("/sys/class/nvme/{}/reset_controller", name);
+        std::fs::write(&reset_path, "1")
+            .map_err(|e| format!("failed to write '{}': {}", reset_path, e))
     }
 
     pub fn admin_read(
@@ -95,9 +205,9 @@ impl NvmeDevice {
         cmd.cdw15 = cdw15;
         cmd.addr = buf.as_mut_ptr() as u64;
         cmd.data_len = buf.len() as u32;
-        cmd.timeout_ms = TIMEOUT_MS;
+        cmd.timeout_ms = self.timeout_ms.get();
 
-        self.submit_admin_cmd(&mut cmd)
+        self.submit_admin_cmd(&mut cmd, DataDir::FromDevice)
     }
 
     pub fn admin_write(
@@ -123,9 +233,9 @@ impl NvmeDevice {
         cmd.cdw15 = cdw15;
         cmd.addr = buf.as_ptr() as u64;
         cmd.data_len = buf.len() as u32;
-        cmd.timeout_ms = TIMEOUT_MS;
+        cmd.timeout_ms = self.timeout_ms.get();
 
-        self.submit_admin_cmd(&mut cmd)
+        self.submit_admin_cmd(&mut cmd, DataDir::ToDevice)
     }
 
     pub fn admin_no_data(
@@ -148,9 +258,9 @@ impl NvmeDevice {
         cmd.cdw13 = cdw13;
         cmd.cdw14 = cdw14;
         cmd.cdw15 = cdw15;
-        cmd.timeout_ms = TIMEOUT_MS;
+        cmd.timeout_ms = self.timeout_ms.get();
 
-        self.submit_admin_cmd(&mut cmd)
+        self.submit_admin_cmd(&mut cmd, DataDir::None)
     }
 
     /// Opcode 0x06, CNS=1 (cdw10=1)
@@ -160,10 +270,154 @@ impl NvmeDevice {
         Ok(buf)
     }
 
-    fn submit_admin_cmd(&self, cmd: &mut NvmeAdminCmd) -> Result<u32, String> {
+    /// Opcode 0x06, CNS=0 (cdw10=0)
+    pub fn identify_namespace(&self, nsid: u32) -> Result<[u8; 4096], String> {
+        let mut buf = [0u8; 4096];
+        self.admin_read(0x06, nsid, 0, 0, 0, 0, 0, 0, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Opcode 0x06, CNS=2 (cdw10=2): active namespace IDs, the first 1024. NVMe 1.1+.
+    pub fn active_namespaces(&self) -> Result<Vec<u32>, String> {
+        let mut buf = [0u8; 4096];
+        self.admin_read(0x06, 0, 2, 0, 0, 0, 0, 0, &mut buf)?;
+        Ok(buf
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+            .take_while(|&nsid| nsid != 0)
+            .collect())
+    }
+
+    /// Get Features, Host Memory Buffer (FID 0x0D), current value.
+    pub fn host_memory_buffer(&self) -> Result<HmbState, String> {
+        let mut buf = [0u8; 4096];
+        let dw0 = self.admin_read(NVME_ADMIN_GET_FEATURES, 0, NVME_FEAT_HOST_MEM_BUF, 0, 0, 0, 0, 0, &mut buf)?;
+        let hsize = u32::from_le_bytes(buf[0..4].try_into().unwrap());
+        Ok(HmbState {
+            enabled: dw0 & 0x1 != 0,
+            size: hsize as u64 * NVME_MEM_PAGE_SIZE,
+        })
+    }
+
+    fn submit_admin_cmd(&self, cmd: &mut NvmeAdminCmd, dir: DataDir) -> Result<u32, String> {
+        crate::interrupt::check()?;
+        if let Some(bridge) = self.bridge {
+            bridge.check(cmd, dir)?;
+        }
+        if self.dry_run.get() && !is_standard_read(cmd.opcode) {
+            self.planned.set(self.planned.get() + 1);
+            println!("[dry-run] {}", self.describe(cmd, dir));
+            if cmd.data_len > 0 {
+                let data = unsafe {
+                    std::slice::from_raw_parts_mut(cmd.addr as *mut u8, cmd.data_len as usize)
+                };
+                if dir == DataDir::ToDevice {
+                    print!("{}", hexdump(data, "    "));
+                } else {
+                    data.fill(0);
+                }
+            }
+            return Ok(0);
+        }
+        self.completion.set(None);
+        let start = Instant::now();
+        let (outcome, status) = match self.bridge {
+            None => self.ioctl_admin(cmd, start),
+            Some(bridge) => self.bridged_admin(bridge, cmd, dir, start),
+        };
+        let elapsed = start.elapsed();
+
+        let data = if cmd.data_len > 0 {
+            Some(unsafe { std::slice::from_raw_parts(cmd.addr as *const u8, cmd.data_len as usize) })
+        } else {
+            None
+        };
+        crate::trace::record(&crate::trace::CommandRecord {
+            tag: format!("nvme-{:02x}", cmd.opcode),
+            desc: self.describe(cmd, dir),
+            vendor: !is_standard_read(cmd.opcode),
+            duration: elapsed,
+            status,
+            error: outcome.as_ref().err().map(|e| e.as_str()),
+            data_out: data.filter(|_| dir == DataDir::ToDevice),
+            data_in: data.filter(|_| dir == DataDir::FromDevice && outcome.is_ok()),
+            sense: None,
+        });
+        outcome
+    }
+
+    /// Native NVMe admin ioctl. Returns the outcome and the status text for the trace.
+    fn ioctl_admin(&self, cmd: &mut NvmeAdminCmd, start: Instant) -> (Result<u32, String>, String) {
         let ret = unsafe { libc::ioctl(self.fd, NVME_IOCTL_ADMIN_CMD, cmd as *mut NvmeAdminCmd) };
+        let errno = if ret < 0 { unsafe { *libc::__errno_location() } } else { 0 };
+        let outcome = self.check_completion(cmd, ret, errno, start.elapsed().as_millis());
+        if ret >= 0 && ret != NVME_SC_HOST_ABORTED_CMD {
+            self.completion.set(Some((ret as u16, cmd.result)));
+        }
+        let status = if ret < 0 {
+            format!("errno {}", errno)
+        } else {
+            format!("status 0x{:03x}, result 0x{:08x}", ret, cmd.result)
+        };
+        (outcome, status)
+    }
+
+    fn bridged_admin(
+        &self,
+        bridge: NvmeBridge,
+        cmd: &mut NvmeAdminCmd,
+        dir: DataDir,
+        start: Instant,
+    ) -> (Result<u32, String>, String) {
+        match bridge.submit(self.fd, cmd, dir) {
+            Ok(c) => {
+                cmd.result = c.result;
+                self.completion.set(Some((c.status, c.result)));
+                let status = format!("status 0x{:03x}, result 0x{:08x}", c.status, c.result);
+                if c.status != 0 {
+                    return (Err(status_error(c.status, cmd.opcode)), status);
+                }
+                (Ok(c.result), status)
+            }
+            Err(BridgeError::TimedOut) => {
+                self.timeouts.set(self.timeouts.get() + 1);
+                let e = format!(
+                    "nvme command timed out after {} ms (opcode 0x{:02x}, via {})",
+                    start.elapsed().as_millis(),
+                    cmd.opcode,
+                    bridge.name()
+                );
+                (Err(e), "timed out".to_string())
+            }
+            Err(BridgeError::Failed(e)) => (Err(e), format!("rejected by {}", bridge.name())),
+        }
+    }
+
+    fn describe(&self, cmd: &NvmeAdminCmd, dir: DataDir) -> String {
+        match self.bridge {
+            Some(bridge) => format!("{} via {}", describe_cmd(cmd, dir), bridge.name()),
+            None => describe_cmd(cmd, dir),
+        }
+    }
+
+    fn check_completion(
+        &self,
+        cmd: &NvmeAdminCmd,
+        ret: i32,
+        errno: i32,
+        elapsed_ms: u128,
+    ) -> Result<u32, String> {
+        if ret == NVME_SC_HOST_ABORTED_CMD
+            || errno == libc::ETIMEDOUT
+            || (ret < 0 && elapsed_ms >= cmd.timeout_ms as u128)
+        {
+            self.timeouts.set(self.timeouts.get() + 1);
+            return Err(format!(
+                "nvme command timed out after {} ms (opcode 0x{:02x})",
+                elapsed_ms, cmd.opcode
+            ));
+        }
         if ret < 0 {
-            let errno = unsafe { *libc::__errno_location() };
             return Err(format!(
                 "nvme ioctl failed: {} (errno {}, opcode 0x{:02x})",
                 errno_to_str(errno),
@@ -171,10 +425,58 @@ impl NvmeDevice {
                 cmd.opcode
             ));
         }
+        if ret > 0 {
+            return Err(status_error(ret as u16, cmd.opcode));
+        }
         Ok(cmd.result)
     }
 }
 
+/// Error for a completion with a nonzero status field (SCT in bits 10:8, SC in 7:0).
+fn status_error(status: u16, opcode: u8) -> String {
+    format!("nvme command failed: {} (opcode 0x{:02x})", describe_status(status), opcode)
+}
+
+/// "status 0x002 (Invalid Field in Command, DNR)" for a completion status field.
+pub fn describe_status(status: u16) -> String {
+    let sct = (status >> 8) & 0x7;
+    let sc = status & 0xFF;
+    let name = match (sct, sc) {
+        (0, 0x01) => "Invalid Command Opcode".to_string(),
+        (0, 0x02) => "Invalid Field in Command".to_string(),
+        (0, 0x04) => "Data Transfer Error".to_string(),
+        (0, 0x06) => "Internal Error".to_string(),
+        (0, 0x0B) => "Invalid Namespace or Format".to_string(),
+        (0, 0x0D) => "Invalid SGL Segment Descriptor".to_string(),
+        (1, 0x0D) => "Feature Identifier Not Saveable".to_string(),
+        (1, 0x0E) => "Feature Not Changeable".to_string(),
+        (0, _) => format!("generic SC 0x{:02x}", sc),
+        (1, _) => format!("command specific SC 0x{:02x}", sc),
+        (2, _) => format!("media error SC 0x{:02x}", sc),
+        (7, _) => format!("vendor specific SC 0x{:02x}", sc),
+        _ => format!("SCT {} SC 0x{:02x}", sct, sc),
+    };
+    let dnr = if status & 0x4000 != 0 { ", DNR" } else { "" };
+    format!("status 0x{:03x} ({}{})", status & 0x7FF, name, dnr)
+}
+
+pub fn describe_cmd(cmd: &NvmeAdminCmd, dir: DataDir) -> String {
+    format!(
+        "nvme admin opcode=0x{:02x} nsid=0x{:x} cdw10=0x{:08x} cdw11=0x{:08x} cdw12=0x{:08x} \
+         cdw13=0x{:08x} cdw14=0x{:08x} cdw15=0x{:08x} dir={} len={}",
+        cmd.opcode,
+        cmd.nsid,
+        cmd.cdw10,
+        cmd.cdw11,
+        cmd.cdw12,
+        cmd.cdw13,
+        cmd.cdw14,
+        cmd.cdw15,
+        dir.name(),
+        cmd.data_len
+    )
+}
+
 impl Drop for NvmeDevice {
     fn drop(&mut self) {
         unsafe {
@@ -183,29 +485,184 @@ impl Drop for NvmeDevice {
     }
 }
 
+/// Identify and Get Features only read controller state; they are not audited as vendor
+/// commands, and --dry-run still sends them.
+fn is_standard_read(opcode: u8) -> bool {
+    opcode == NVME_ADMIN_IDENTIFY || opcode == NVME_ADMIN_GET_FEATURES
+}
+
+/// Current Host Memory Buffer feature state.
+pub struct HmbState {
+    /// EHM: the host has enabled the buffer
+    pub enabled: bool,
+    /// HSIZE in bytes: what the host allocated
+    pub size: u64,
+}
+
+/// One power state descriptor (Identify Controller bytes 2048+, 32 bytes each).
+pub struct PowerState {
+    /// Maximum power in units of 0.0001 W (MXPS set) or 0.01 W
+    pub max_power: u16,
+    /// Max Power Scale: `max_power` is in 0.0001 W units
+    pub mxps: bool,
+    /// Non-Operational State: no I/O is processed in this state
+    pub non_operational: bool,
+    pub entry_latency_us: u32,
+    pub exit_latency_us: u32,
+}
+
+impl PowerState {
+    pub fn watts(&self) -> f64 {
+        self.max_power as f64 * if self.mxps { 0.0001 } else { 0.01 }
+    }
+}
+
 pub struct ControllerInfo {
     pub vid: u16,
     pub ssvid: u16,
     pub serial: String,
     pub model: String,
     pub firmware: String,
+    /// IEEE OUI as nvme-cli prints it (bytes 75, 74, 73)
+    pub ieee_oui: u32,
+    /// Controller ID
+    pub cntlid: u16,
+    /// VER: major in bits 31:16, minor in 15:8, tertiary in 7:0; 0 before NVMe 1.2
+    pub version: u32,
+    /// Controller Attributes
+    pub ctratt: u32,
+    /// Optional Admin Command Support
+    pub oacs: u16,
+    /// Firmware Updates: slot 1 read-only in bit 0, slot count in bits 3:1
+    pub frmw: u8,
+    /// Host Memory Buffer preferred and minimum size, in 4 KiB units
+    pub hmpre: u32,
+    pub hmmin: u32,
+    /// Warning and critical composite temperature thresholds, in kelvin
+    pub wctemp: u16,
+    pub cctemp: u16,
+    /// Total and unallocated NVM capacity in bytes
+    pub tnvmcap: u128,
+    pub unvmcap: u128,
+    /// Number of namespaces
+    pub nn: u32,
+    pub power_states: Vec<PowerState>,
+    /// Filled in from sysfs by the caller; Identify data doesn't carry it
+    pub pci: Option<crate::sysfs::PciInfo>,
+}
+
+impl ControllerInfo {
+    /// "1.4" or "1.3.1"; None when VER isn't reported
+    pub fn version_string(&self) -> Option<String> {
+        if self.version == 0 {
+            return None;
+        }
+        let (major, minor, tertiary) = (self.version >> 16, (self.version >> 8) & 0xFF, self.version & 0xFF);
+        Some(if tertiary == 0 {
+            format!("{}.{}", major, minor)
+        } else {
+            format!("{}.{}.{}", major, minor, tertiary)
+        })
+    }
+
+    pub fn firmware_slots(&self) -> u8 {
+        (self.frmw >> 1) & 0x7
+    }
 }
 
 pub fn parse_identify(data: &[u8; 4096]) -> ControllerInfo {
-    let vid = u16::from_le_bytes([data[0], data[1]]);
-    let ssvid = u16::from_le_bytes([data[2], data[3]]);
+    let le16 = |off: usize| u16::from_le_bytes([data[off], data[off + 1]]);
+    let le32 = |off: usize| u32::from_le_bytes(data[off..off + 4].try_into().unwrap());
+    let le128 = |off: usize| u128::from_le_bytes(data[off..off + 16].try_into().unwrap());
+
+    let vid = le16(0);
+    let ssvid = le16(2);
     let serial = ascii_trim(&data[4..24]);
     let model = ascii_trim(&data[24..64]);
     let firmware = ascii_trim(&data[64..72]);
+    // NPSS is zero-based: one descriptor more than its value
+    let npss = data[263] as usize;
+    let power_states = (0..=npss.min(31))
+        .map(|i| {
+            let off = 2048 + i * 32;
+            PowerState {
+                max_power: le16(off),
+                mxps: data[off + 3] & 0x01 != 0,
+                non_operational: data[off + 3] & 0x02 != 0,
+                entry_latency_us: le32(off + 4),
+                exit_latency_us: le32(off + 8),
+            }
+        })
+        .collect();
     ControllerInfo {
         vid,
         ssvid,
         serial,
         model,
         firmware,
+        ieee_oui: u32::from_be_bytes([0, data[75], data[74], data[73]]),
+        cntlid: le16(78),
+        version: le32(80),
+        ctratt: le32(96),
+        oacs: le16(256),
+        frmw: data[260],
+        hmpre: le32(272),
+        hmmin: le32(276),
+        wctemp: le16(266),
+        cctemp: le16(268),
+        tnvmcap: le128(280),
+        unvmcap: le128(296),
+        nn: le32(516),
+        power_states,
+        pci: None,
     }
 }
 
+/// Names of the OACS bits set, for --details.
+pub fn oacs_names(oacs: u16) -> Vec<&'static str> {
+    const NAMES: &[(u16, &str)] = &[
+        (1 << 0, "security"),
+        (1 << 1, "format"),
+        (1 << 2, "fw-download"),
+        (1 << 3, "ns-mgmt"),
+        (1 << 4, "self-test"),
+        (1 << 5, "directives"),
+        (1 << 6, "nvme-mi"),
+        (1 << 7, "virt-mgmt"),
+        (1 << 8, "doorbell-buffer"),
+        (1 << 9, "lba-status"),
+    ];
+    NAMES.iter().filter(|&&(bit, _)| oacs & bit != 0).map(|&(_, n)| n).collect()
+}
+
+/// Names of the CTRATT bits set, for --details.
+pub fn ctratt_names(ctratt: u32) -> Vec<&'static str> {
+    const NAMES: &[(u32, &str)] = &[
+        (1 << 0, "128-bit-host-id"),
+        (1 << 1, "non-op-power-state-permissive"),
+        (1 << 2, "nvm-sets"),
+        (1 << 3, "read-recovery-levels"),
+        (1 << 4, "endurance-groups"),
+        (1 << 5, "predictable-latency"),
+        (1 << 6, "tbkas"),
+        (1 << 7, "namespace-granularity"),
+        (1 << 8, "sq-associations"),
+        (1 << 9, "uuid-list"),
+    ];
+    NAMES.iter().filter(|&&(bit, _)| ctratt & bit != 0).map(|&(_, n)| n).collect()
+}
+
+/// Namespace identifiers from Identify Namespace: NGUID (bytes 104-119) and EUI64
+/// (120-127), each when set. A namespace may report both, and tools differ in which
+/// one they show as its WWN.
+pub fn namespace_wwns(data: &[u8; 4096]) -> Vec<String> {
+    [&data[104..120], &data[120..128]]
+        .into_iter()
+        .filter(|id| id.iter().any(|&b| b != 0))
+        .map(|id| id.iter().map(|b| format!("{:02x}", b)).collect())
+        .collect()
+}
+
 fn ascii_trim(bytes: &[u8]) -> String {
     let s: String = bytes
         .iter()
@@ -214,18 +671,61 @@ fn ascii_trim(bytes: &[u8]) -> String {
     s.trim().to_string()
 }
 
-fn errno_to_str(errno: i32) -> &'static str {
-    match errno {
-        libc::EACCES => "permission denied",
-        libc::ENOENT => "no such file or directory",
-        libc::EBUSY => "device busy",
-        libc::EIO => "i/o error",
-        libc::EINVAL => "invalid argument",
-        libc::ENOTTY => "inappropriate ioctl for device",
-        libc::ENOMEM => "out of memory",
-        libc::EPERM => "operation not permitted",
-        libc::ENODEV => "no such device",
-        libc::EAGAIN => "device busy (try again)",
-        _ => "unknown error",
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespace_wwns() {
+        let mut data = [0u8; 4096];
+        assert!(namespace_wwns(&data).is_empty());
+        data[120..128].copy_from_slice(&[0x00, 0x25, 0x38, 0x57, 0x11, 0xb2, 0xaa, 0x01]);
+        assert_eq!(namespace_wwns(&data), ["0025385711b2aa01"]);
+        data[104] = 0xe8;
+        data[119] = 0x3d;
+        assert_eq!(namespace_wwns(&data), ["e800000000000000000000000000003d", "0025385711b2aa01"]);
+    }
+
+    #[test]
+    fn test_describe_status() {
+        assert_eq!(describe_status(0x4001), "status 0x001 (Invalid Command Opcode, DNR)");
+        assert_eq!(describe_status(0x002), "status 0x002 (Invalid Field in Command)");
+        assert_eq!(describe_status(0x7C0), "status 0x7c0 (vendor specific SC 0xc0)");
+        assert!(status_error(0x002, 0xC2).ends_with("(opcode 0xc2)"));
+    }
+
+    #[test]
+    fn test_parse_identify() {
+        let mut data = [0u8; 4096];
+        data[0..2].copy_from_slice(&0x126Fu16.to_le_bytes());
+        data[24..32].copy_from_slice(b"TEST SSD");
+        data[73..76].copy_from_slice(&[0xA7, 0x79, 0x64]);
+        data[78..80].copy_from_slice(&1u16.to_le_bytes());
+        data[80..84].copy_from_slice(&0x0001_0400u32.to_le_bytes());
+        data[256] = 0x17;
+        data[260] = 0x06;
+        data[263] = 1;
+        data[266..268].copy_from_slice(&358u16.to_le_bytes());
+        data[272..276].copy_from_slice(&10240u32.to_le_bytes());
+        data[280..296].copy_from_slice(&512_110_190_592u128.to_le_bytes());
+        data[516..520].copy_from_slice(&1u32.to_le_bytes());
+        data[2048..2050].copy_from_slice(&650u16.to_le_bytes());
+        data[2080..2082].copy_from_slice(&30u16.to_le_bytes());
+        data[2083] = 0x03;
+        data[2084..2088].copy_from_slice(&5000u32.to_le_bytes());
+
+        let info = parse_identify(&data);
+        assert_eq!((info.vid, info.model.as_str()), (0x126F, "TEST SSD"));
+        assert_eq!((info.ieee_oui, info.cntlid), (0x6479A7, 1));
+        assert_eq!(info.version_string().as_deref(), Some("1.4"));
+        assert_eq!(oacs_names(info.oacs), ["security", "format", "fw-download", "self-test"]);
+        assert_eq!(info.firmware_slots(), 3);
+        assert_eq!((info.wctemp, info.hmpre, info.nn), (358, 10240, 1));
+        assert_eq!(info.tnvmcap, 512_110_190_592);
+        assert_eq!(info.power_states.len(), 2);
+        assert!((info.power_states[0].watts() - 6.5).abs() < 1e-9);
+        let ps1 = &info.power_states[1];
+        assert!(ps1.mxps && ps1.non_operational);
+        assert_eq!(ps1.entry_latency_us, 5000);
     }
 }
@@ -0,0 +1,386 @@
+use crate::ata::{self, parse_ata_identify};
+use crate::controllers::{self, FlashIdResult};
+use crate::defs::{DefTransport, Definition};
+use crate::detect::ControllerType;
+use crate::mmc;
+use crate::nvme::{self, parse_identify};
+use crate::risk::Risk;
+use crate::scsi;
+use crate::trace::Outcome;
+use crate::ufs;
+use crate::usb_bridge::NvmeBridge;
+use crate::usb_flash;
+use crate::{dump, interrupt, trace, Args};
+
+/// Runs every NVMe family reader allowed by --probe-level, one after another, and
+/// reports what each command did. For drives no detection rule recognizes.
+pub fn run_nvme(dev_path: &str, bridge: Option<NvmeBridge>, args: &Args, defs: &[Definition]) {
+    let dev = crate::open_nvme(dev_path, bridge);
+    let default_ms = crate::default_timeout(args).unwrap_or(nvme::DEFAULT_TIMEOUT_MS);
+    dev.set_timeout(default_ms);
+    let mut info = match dev.identify_controller() {
+        Ok(d) => parse_identify(&d),
+        Err(e) => {
+            eprintln!("error: failed to identify controller: {}", e);
+            std::process::exit(1);
+        }
+    };
+    info.pci = crate::sysfs::nvme_pci_info(dev_path);
+    trace::set_device(dev_path, &info.serial);
+    dev.set_dry_run(args.dry_run);
+    print_header(dev_path, &info.model, &info.firmware, args);
+    if let Some(ref pci) = info.pci {
+        println!("PCI {}\n", pci.describe());
+    }
+    if let Some(bridge) = dev.bridge() {
+        println!("USB bridge {}\n", bridge.display());
+    }
+    if args.details {
+        crate::print_nvme_details(&info);
+        println!();
+    }
+
+    let mut families: Vec<ControllerType> = crate::NVME_TYPES
+        .iter()
+        .filter_map(|t| crate::resolve_nvme_controller_type(t, &[]))
+        .collect();
+    families.extend(
+        defs.iter()
+            .filter(|d| d.transport == DefTransport::Nvme)
+            .map(|d| ControllerType::Defined(Box::new(d.clone()))),
+    );
+    if let Some(variant) = args.rtl_variant {
+        for ct in families.iter_mut() {
+            if let ControllerType::Realtek(name, _) = ct {
+                *ct = ControllerType::Realtek(name.clone(), variant);
+            }
+        }
+    }
+
+    let mut summary = Vec::new();
+    for ct in &families {
+        if interrupt::check().is_err() {
+            break;
+        }
+        let line = research_family(
+            ct.family(),
+            crate::controller_family_display(ct),
+            crate::nvme_reader_risk(ct),
+            args,
+            || {
+                let timeouts = dev.timeouts();
+                dev.set_timeout(crate::timeout_for(args, ct.family()).unwrap_or(default_ms));
+                dump::set_label(ct.family());
+                let result = crate::nvme_read_flash_id(&dev, ct, args);
+                dump::set_label("teardown");
+                crate::nvme_teardown(&dev, ct);
+                dev.set_timeout(default_ms);
+                // A hung controller would fail every following family for the wrong reason
+                if dev.timeouts() > timeouts {
+                    interrupt::run_teardown(|| crate::nvme_check_health(&dev, dev_path, args.recover));
+                }
+                result
+            },
+        );
+        summary.push((ct.family().to_string(), line));
+    }
+    interrupt::exit_if_interrupted();
+    print_summary(&summary, args.dry_run.then(|| dev.planned()));
+}
+
+/// SATA counterpart of `run_nvme`, over the built-in SATA families and ATA definitions.
+pub fn run_sata(dev_path: &str, args: &Args, defs: &[Definition]) {
+    let dev = crate::open_ata(dev_path, args);
+    let default_ms = crate::default_timeout(args).unwrap_or(ata::DEFAULT_TIMEOUT_MS);
+    dev.set_timeout(default_ms);
+    let id_data = match dev.ata_identify() {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("error: failed to identify device: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let info = parse_ata_identify(&id_data);
+    trace::set_device(dev_path, &info.serial);
+    crate::sata_guard(dev_path, &dev, &id_data, args);
+    dev.set_dry_run(args.dry_run);
+    print_header(dev_path, &info.model, &info.firmware, args);
+    if dev.node() != dev_path {
+        println!("SCSI node {}\n", dev.node());
+    }
+    if let Ok(method) = dev.method()
+        && method != ata::AtaMethod::Pt16
+    {
+        println!("ATA access {}\n", method.display());
+    }
+
+    let readers: Vec<crate::SataReader> = crate::SATA_FAMILIES
+        .iter()
+        .map(crate::SataReader::Builtin)
+        .chain(
+            defs.iter()
+                .filter(|d| d.transport == DefTransport::Ata)
+                .map(crate::SataReader::Defined),
+        )
+        .collect();
+
+    let mut summary = Vec::new();
+    for reader in readers {
+        if interrupt::check().is_err() {
+            break;
+        }
+        let line = research_family(reader.name(), reader.display(), reader.risk(), args, || {
+            let timeouts = dev.timeouts();
+            dev.set_timeout(crate::timeout_for(args, reader.name()).unwrap_or(default_ms));
+            dump::set_label(reader.name());
+            let result = reader.read(&dev);
+            dev.set_timeout(default_ms);
+            dump::set_label("teardown");
+            crate::sata_teardown(&dev, &[reader]);
+            if dev.timeouts() > timeouts {
+                interrupt::run_teardown(|| crate::sata_check_health(&dev, dev_path, args.recover));
+            }
+            result
+        });
+        summary.push((reader.name().to_string(), line));
+    }
+    interrupt::exit_if_interrupted();
+
+    println!("== identify-fid (flash IDs in IDENTIFY vendor words) ==");
+    match controllers::ata_identify_fid::extract_from_identify(&id_data) {
+        Some(r) => {
+            crate::print_banks(&r, args.raw);
+            summary.push(("identify-fid".to_string(), format!("{} bank(s)", r.banks.len())));
+        }
+        None => {
+            println!("  none");
+            summary.push(("identify-fid".to_string(), "none".to_string()));
+        }
+    }
+    println!();
+    print_summary(&summary, args.dry_run.then(|| dev.planned()));
+}
+
+/// eMMC counterpart of `run_nvme`, over every eMMC family including the ones that are
+/// never probed automatically.
+pub fn run_mmc(dev_path: &str, args: &Args) {
+    let dev = crate::open_mmc(dev_path);
+    let default_ms = crate::default_timeout(args).unwrap_or(mmc::DEFAULT_TIMEOUT_MS);
+    dev.set_timeout(default_ms);
+    let info = match crate::mmc_identify(&dev, dev_path) {
+        Ok(i) => i,
+        Err(e) => {
+            eprintln!("error: failed to identify device: {}", e);
+            std::process::exit(1);
+        }
+    };
+    trace::set_device(dev_path, &info.serial);
+    dev.set_dry_run(args.dry_run);
+    print_header(dev_path, &info.model, &info.firmware, args);
+    println!(
+        "eMMC {} (0x{:02x}), eMMC {}\n",
+        mmc::manufacturer_name(info.manfid),
+        info.manfid,
+        mmc::spec_version(info.ext_csd_rev)
+    );
+
+    let mut summary = Vec::new();
+    for family in crate::MMC_FAMILIES.iter().filter(|f| !f.controller_only()) {
+        if interrupt::check().is_err() {
+            break;
+        }
+        let line = research_family(family.name, family.display, family.risk, args, || {
+            let timeouts = dev.timeouts();
+            dev.set_timeout(crate::timeout_for(args, family.name).unwrap_or(default_ms));
+            dump::set_label(family.name);
+            let result = (family.read)(&dev);
+            dev.set_timeout(default_ms);
+            dump::set_label("teardown");
+            crate::mmc_teardown(&dev, &[family]);
+            if dev.timeouts() > timeouts {
+                interrupt::run_teardown(|| crate::mmc_check_health(&dev, dev_path));
+            }
+            result
+        });
+        summary.push((family.name.to_string(), line));
+    }
+    interrupt::exit_if_interrupted();
+    print_summary(&summary, args.dry_run.then(|| dev.planned()));
+}
+
+pub fn run_ufs(bsg_path: &str, args: &Args) {
+    let (_, info) = crate::open_ufs(bsg_path);
+    trace::set_device(bsg_path, &info.serial);
+    let (disk, dev) = match crate::open_ufs_lun(bsg_path) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let default_ms = crate::default_timeout(args).unwrap_or(scsi::DEFAULT_TIMEOUT_MS);
+    dev.set_timeout(default_ms);
+    dev.set_dry_run(args.dry_run);
+    print_header(bsg_path, &info.model, &info.firmware, args);
+    println!(
+        "UFS {} (0x{:04x}), UFS {}, vendor commands go to {}\n",
+        ufs::manufacturer_name(info.manufacturer_id),
+        info.manufacturer_id,
+        ufs::spec_version(info.spec_version),
+        disk
+    );
+
+    let mut summary = Vec::new();
+    for family in crate::UFS_FAMILIES {
+        if interrupt::check().is_err() {
+            break;
+        }
+        let line = research_family(family.name, family.display, family.risk, args, || {
+            let timeouts = dev.timeouts();
+            dev.set_timeout(crate::timeout_for(args, family.name).unwrap_or(default_ms));
+            dump::set_label(family.name);
+            let result = (family.read)(&dev);
+            dev.set_timeout(default_ms);
+            if dev.timeouts() > timeouts {
+                interrupt::run_teardown(|| crate::scsi_check_health(&dev, &disk, args.recover));
+            }
+            result
+        });
+        summary.push((family.name.to_string(), line));
+    }
+    interrupt::exit_if_interrupted();
+    print_summary(&summary, args.dry_run.then(|| dev.planned()));
+}
+
+pub fn run_usb(dev_path: &str, args: &Args) {
+    let dev = match scsi::ScsiDevice::open(dev_path) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let (vendor, product, revision) = match dev.inquiry() {
+        Ok(inq) => usb_flash::inquiry_strings(&inq),
+        Err(e) => {
+            eprintln!("error: failed to identify device: {}", e);
+            std::process::exit(1);
+        }
+    };
+    trace::set_device(dev_path, &crate::sysfs::usb_serial(dev_path).unwrap_or_default());
+    let default_ms = crate::default_timeout(args).unwrap_or(scsi::DEFAULT_TIMEOUT_MS);
+    dev.set_timeout(default_ms);
+    dev.set_dry_run(args.dry_run);
+    print_header(dev_path, &format!("{} {}", vendor, product), &revision, args);
+    if let Some((vid, pid)) = crate::sysfs::usb_id(dev_path) {
+        println!("USB flash drive {:04x}:{:04x}\n", vid, pid);
+    }
+
+    let mut summary = Vec::new();
+    for family in crate::USB_FAMILIES.iter().filter(|f| !f.controller_only) {
+        if interrupt::check().is_err() {
+            break;
+        }
+        let line = research_family(family.name, family.display, family.risk, args, || {
+            let timeouts = dev.timeouts();
+            dev.set_timeout(crate::timeout_for(args, family.name).unwrap_or(default_ms));
+            dump::set_label(family.name);
+            let result = (family.read)(&dev);
+            dev.set_timeout(default_ms);
+            if dev.timeouts() > timeouts {
+                interrupt::run_teardown(|| crate::scsi_check_health(&dev, dev_path, args.recover));
+            }
+            result
+        });
+        summary.push((family.name.to_string(), line));
+    }
+    interrupt::exit_if_interrupted();
+    print_summary(&summary, args.dry_run.then(|| dev.planned()));
+}
+
+fn print_header(dev_path: &str, model: &str, firmware: &str, args: &Args) {
+    println!("Research run on {} ({}, firmware {})", dev_path, model, firmware);
+    println!(
+        "running every family reader up to --probe-level {}{}\n",
+        args.probe_level.level_name(),
+        if args.dry_run { " (dry run, nothing is sent)" } else { "" }
+    );
+}
+
+/// Runs one family with outcome capture and prints its commands and banks. Returns the
+/// one-line summary for the final table.
+fn research_family(
+    family: &str,
+    display: &str,
+    risk: Risk,
+    args: &Args,
+    run: impl FnOnce() -> Result<FlashIdResult, String>,
+) -> String {
+    println!("== {} ({}) ==", family, display);
+    if risk > args.probe_level {
+        println!(
+            "  skipped: uses {} commands (needs --probe-level {})\n",
+            risk.description(),
+            risk.level_name()
+        );
+        return format!("skipped ({})", risk.description());
+    }
+
+    trace::start_capture();
+    let result = run();
+    let outcomes = trace::take_capture();
+
+    for o in &outcomes {
+        println!("  {} -> {}", o.desc, outcome_text(o));
+    }
+    let rejected = outcomes.iter().filter(|o| o.error.is_some()).count();
+    let with_data = outcomes
+        .iter()
+        .filter(|o| o.error.is_none() && o.data_in.is_some_and(|(_, set)| set > 0))
+        .count();
+    // Dry-run commands are only printed, so there are no outcomes to count
+    let counts = if args.dry_run {
+        "planned only".to_string()
+    } else {
+        format!(
+            "{} sent, {} rejected, {} returned data",
+            outcomes.len(),
+            rejected,
+            with_data
+        )
+    };
+
+    let line = match result {
+        Ok(r) => {
+            println!("  reader succeeded, controller name: {}", r.controller_name);
+            crate::print_banks(&r, args.raw);
+            format!("{}; {} bank(s), {}", counts, r.banks.len(), r.controller_name)
+        }
+        Err(e) => {
+            println!("  reader failed: {}", e);
+            format!("{}; failed", counts)
+        }
+    };
+    println!();
+    line
+}
+
+fn outcome_text(o: &Outcome) -> String {
+    match (&o.error, o.data_in) {
+        (Some(e), _) => format!("rejected: {}", e),
+        (None, Some((len, 0))) => format!("accepted, empty response ({} bytes)", len),
+        (None, Some((len, set))) => format!("data, {} of {} bytes set", set, len),
+        (None, None) => "accepted".to_string(),
+    }
+}
+
+fn print_summary(summary: &[(String, String)], planned: Option<u32>) {
+    println!("summary:");
+    let width = summary.iter().map(|(f, _)| f.len()).max().unwrap_or(0);
+    for (family, line) in summary {
+        println!("  {:width$}  {}", family, line, width = width);
+    }
+    if let Some(planned) = planned {
+        println!("\n{} vendor command(s) planned", planned);
+    }
+}
@@ -0,0 +1,45 @@
+/// How invasive a probe or reader is, in increasing order. `--probe-level` sets the
+/// highest risk the tool may use on a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Risk {
+    /// Standard Identify only (plus sysfs and the data already returned by it)
+    Identify,
+    /// Vendor-specific commands that only transfer data from the device
+    VendorRead,
+    /// Vendor-specific commands that send data or change controller state (unlocks, requests)
+    VendorWrite,
+    /// Reads of user-addressable LBAs that some firmware intercepts as magic commands
+    UserLbaRead,
+}
+
+pub const LEVEL_NAMES: &str = "passive, read, write, all";
+
+impl Risk {
+    pub fn from_level(level: &str) -> Option<Risk> {
+        match level {
+            "passive" => Some(Risk::Identify),
+            "read" => Some(Risk::VendorRead),
+            "write" => Some(Risk::VendorWrite),
+            "all" => Some(Risk::UserLbaRead),
+            _ => None,
+        }
+    }
+
+    pub fn level_name(self) -> &'static str {
+        match self {
+            Risk::Identify => "passive",
+            Risk::VendorRead => "read",
+            Risk::VendorWrite => "write",
+            Risk::UserLbaRead => "all",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Risk::Identify => "identify only",
+            Risk::VendorRead => "vendor read",
+            Risk::VendorWrite => "vendor write",
+            Risk::UserLbaRead => "user-LBA read",
+        }
+    }
+}
@@ -0,0 +1,540 @@
+//! SG_IO plumbing shared by the ATA pass-through and the USB bridge tunnels. Block
+//! (/dev/sdX) and SCSI generic (/dev/sgN) nodes take the v3 header, bsg nodes the v4 one.
+
+use std::cell::Cell;
+use std::ffi::CString;
+use std::time::Instant;
+
+use crate::errno::errno_to_str;
+use crate::hexdump::hexdump;
+
+pub const SG_IO: u64 = 0x2285;
+const SG_SCSI_RESET: u64 = 0x2284;
+const SG_SCSI_RESET_DEVICE: i32 = 1;
+const DID_TIME_OUT: u16 = 0x03;
+const DRIVER_TIMEOUT: u16 = 0x06;
+pub const SG_DXFER_NONE: i32 = -1;
+pub const SG_DXFER_TO_DEV: i32 = -2;
+pub const SG_DXFER_FROM_DEV: i32 = -3;
+pub const SENSE_BUF_LEN: u8 = 32;
+pub const BSG_SUB_PROTOCOL_SCSI_TRANSPORT: u32 = 2;
+
+#[repr(C)]
+pub struct SgIoHdr {
+    pub interface_id: i32,
+    pub dxfer_direction: i32,
+    pub cmd_len: u8,
+    pub mx_sb_len: u8,
+    pub iovec_count: u16,
+    pub dxfer_len: u32,
+    pub dxferp: *mut u8,
+    pub cmdp: *const u8,
+    pub sbp: *mut u8,
+    pub timeout: u32,
+    pub flags: u32,
+    pub pack_id: i32,
+    pub usr_ptr: *mut u8,
+    pub status: u8,
+    pub masked_status: u8,
+    pub msg_status: u8,
+    pub sb_len_wr: u8,
+    pub host_status: u16,
+    pub driver_status: u16,
+    pub resid: i32,
+    pub duration: u32,
+    pub info: u32,
+}
+
+impl SgIoHdr {
+    fn zeroed() -> Self {
+        SgIoHdr {
+            interface_id: 0,
+            dxfer_direction: 0,
+            cmd_len: 0,
+            mx_sb_len: 0,
+            iovec_count: 0,
+            dxfer_len: 0,
+            dxferp: std::ptr::null_mut(),
+            cmdp: std::ptr::null(),
+            sbp: std::ptr::null_mut(),
+            timeout: 0,
+            flags: 0,
+            pack_id: 0,
+            usr_ptr: std::ptr::null_mut(),
+            status: 0,
+            masked_status: 0,
+            msg_status: 0,
+            sb_len_wr: 0,
+            host_status: 0,
+            driver_status: 0,
+            resid: 0,
+            duration: 0,
+            info: 0,
+        }
+    }
+
+    /// The command timed out in the SCSI midlayer or the low-level driver.
+    pub fn timed_out(&self) -> bool {
+        self.host_status == DID_TIME_OUT || self.driver_status & 0x0F == DRIVER_TIMEOUT
+    }
+
+    /// "scsi status 0x02, host 0x0000, driver 0x0008"
+    pub fn describe_status(&self) -> String {
+        format!(
+            "scsi status 0x{:02x}, host 0x{:04x}, driver 0x{:04x}",
+            self.status, self.host_status, self.driver_status
+        )
+    }
+}
+
+/// Sends one CDB through SG_IO. Returns the header (SCSI, host and driver status) and
+/// the errno of a failed ioctl, 0 on success.
+pub fn send(
+    fd: i32,
+    cdb: &[u8],
+    direction: i32,
+    dxferp: *mut u8,
+    dxfer_len: usize,
+    timeout_ms: u32,
+    sense: &mut [u8; SENSE_BUF_LEN as usize],
+) -> (SgIoHdr, i32) {
+    let mut hdr = SgIoHdr::zeroed();
+    hdr.interface_id = b'S' as i32;
+    hdr.dxfer_direction = direction;
+    hdr.cmd_len = cdb.len() as u8;
+    hdr.mx_sb_len = SENSE_BUF_LEN;
+    hdr.dxfer_len = dxfer_len as u32;
+    hdr.dxferp = dxferp;
+    hdr.cmdp = cdb.as_ptr();
+    hdr.sbp = sense.as_mut_ptr();
+    hdr.timeout = timeout_ms;
+    let ret = unsafe { libc::ioctl(fd, SG_IO, &mut hdr as *mut SgIoHdr) };
+    let errno = if ret < 0 { unsafe { *libc::__errno_location() } } else { 0 };
+    (hdr, errno)
+}
+
+/// struct sg_io_v4 from <linux/bsg.h>, the header /dev/bsg/* nodes expect.
+/// `protocol` 0 is SCSI; `subprotocol` 0 a SCSI command, 2 a transport message
+/// (e.g. a ufs-bsg query).
+#[repr(C)]
+#[derive(Default)]
+pub struct SgIoV4 {
+    pub guard: i32,
+    pub protocol: u32,
+    pub subprotocol: u32,
+    pub request_len: u32,
+    pub request: u64,
+    pub request_tag: u64,
+    pub request_attr: u32,
+    pub request_priority: u32,
+    pub request_extra: u32,
+    pub max_response_len: u32,
+    pub response: u64,
+    pub dout_iovec_count: u32,
+    pub dout_xfer_len: u32,
+    pub din_iovec_count: u32,
+    pub din_xfer_len: u32,
+    pub dout_xferp: u64,
+    pub din_xferp: u64,
+    pub timeout: u32,
+    pub flags: u32,
+    pub usr_ptr: u64,
+    pub spare_in: u32,
+    pub driver_status: u32,
+    pub transport_status: u32,
+    pub device_status: u32,
+    pub retry_delay: u32,
+    pub info: u32,
+    pub duration: u32,
+    pub response_len: u32,
+    pub din_resid: i32,
+    pub dout_resid: i32,
+    pub generated_tag: u64,
+    pub spare_out: u32,
+    pub padding: u32,
+}
+
+/// `send` for bsg nodes. The v4 completion fields are copied into a v3 header so
+/// callers check one set of fields for both.
+pub fn send_bsg(
+    fd: i32,
+    cdb: &[u8],
+    direction: i32,
+    dxferp: *mut u8,
+    dxfer_len: usize,
+    timeout_ms: u32,
+    sense: &mut [u8; SENSE_BUF_LEN as usize],
+) -> (SgIoHdr, i32) {
+    // protocol and subprotocol 0: SCSI command
+    let mut v4 = SgIoV4 {
+        guard: b'Q' as i32,
+        request_len: cdb.len() as u32,
+        request: cdb.as_ptr() as u64,
+        max_response_len: SENSE_BUF_LEN as u32,
+        response: sense.as_mut_ptr() as u64,
+        timeout: timeout_ms,
+        ..Default::default()
+    };
+    match direction {
+        SG_DXFER_TO_DEV => {
+            v4.dout_xfer_len = dxfer_len as u32;
+            v4.dout_xferp = dxferp as u64;
+        }
+        SG_DXFER_FROM_DEV => {
+            v4.din_xfer_len = dxfer_len as u32;
+            v4.din_xferp = dxferp as u64;
+        }
+        _ => {}
+    }
+    let ret = unsafe { libc::ioctl(fd, SG_IO, &mut v4 as *mut SgIoV4) };
+    let errno = if ret < 0 { unsafe { *libc::__errno_location() } } else { 0 };
+
+    let mut hdr = SgIoHdr::zeroed();
+    hdr.interface_id = b'Q' as i32;
+    hdr.dxfer_direction = direction;
+    hdr.status = v4.device_status as u8;
+    hdr.masked_status = (v4.device_status as u8 >> 1) & 0x7F;
+    hdr.sb_len_wr = v4.response_len.min(SENSE_BUF_LEN as u32) as u8;
+    hdr.host_status = v4.transport_status as u16;
+    hdr.driver_status = v4.driver_status as u16;
+    hdr.resid = if direction == SG_DXFER_TO_DEV { v4.dout_resid } else { v4.din_resid };
+    hdr.duration = v4.duration;
+    hdr.info = v4.info;
+    (hdr, errno)
+}
+
+pub const SENSE_ILLEGAL_REQUEST: u8 = 0x05;
+
+/// Decoded sense data, fixed (0x70/0x71) or descriptor (0x72/0x73) format.
+pub struct Sense<'a> {
+    pub key: u8,
+    pub asc: u8,
+    pub ascq: u8,
+    data: &'a [u8],
+}
+
+impl<'a> Sense<'a> {
+    pub fn is_descriptor(&self) -> bool {
+        self.data[0] & 0x7F >= 0x72
+    }
+
+    /// The first descriptor of type `kind`, including its two header bytes.
+    pub fn descriptor(&self, kind: u8) -> Option<&'a [u8]> {
+        if !self.is_descriptor() {
+            return None;
+        }
+        let end = (8 + *self.data.get(7)? as usize).min(self.data.len());
+        let mut off = 8;
+        while off + 2 <= end {
+            let len = 2 + self.data[off + 1] as usize;
+            if self.data[off] == kind {
+                return self.data.get(off..off + len);
+            }
+            off += len;
+        }
+        None
+    }
+
+    /// INFORMATION field (bytes 3-6) of fixed-format sense.
+    pub fn information(&self) -> Option<[u8; 4]> {
+        self.fixed_field(3)
+    }
+
+    /// COMMAND-SPECIFIC INFORMATION field (bytes 8-11) of fixed-format sense.
+    pub fn command_specific(&self) -> Option<[u8; 4]> {
+        self.fixed_field(8)
+    }
+
+    fn fixed_field(&self, off: usize) -> Option<[u8; 4]> {
+        if self.is_descriptor() {
+            return None;
+        }
+        self.data.get(off..off + 4)?.try_into().ok()
+    }
+
+    /// "illegal request: invalid field in CDB (24/00)"
+    pub fn describe(&self) -> String {
+        describe_sense(self.key, self.asc, self.ascq)
+    }
+}
+
+/// None for empty or unrecognized sense data.
+pub fn decode_sense(data: &[u8]) -> Option<Sense<'_>> {
+    let (key, asc, ascq) = match data.first()? & 0x7F {
+        0x70 | 0x71 if data.len() >= 14 => (data[2] & 0x0F, data[12], data[13]),
+        0x72 | 0x73 if data.len() >= 4 => (data[1] & 0x0F, data[2], data[3]),
+        _ => return None,
+    };
+    Some(Sense { key, asc, ascq, data })
+}
+
+pub fn describe_sense(key: u8, asc: u8, ascq: u8) -> String {
+    match asc_name(asc, ascq) {
+        Some(name) => format!("{}: {} ({:02x}/{:02x})", key_name(key), name, asc, ascq),
+        None => format!("{} ({:02x}/{:02x})", key_name(key), asc, ascq),
+    }
+}
+
+fn key_name(key: u8) -> &'static str {
+    match key {
+        0x0 => "no sense",
+        0x1 => "recovered error",
+        0x2 => "not ready",
+        0x3 => "medium error",
+        0x4 => "hardware error",
+        0x5 => "illegal request",
+        0x6 => "unit attention",
+        0x7 => "data protect",
+        0x8 => "blank check",
+        0x9 => "vendor specific",
+        0xA => "copy aborted",
+        0xB => "aborted command",
+        0xD => "volume overflow",
+        0xE => "miscompare",
+        _ => "reserved sense key",
+    }
+}
+
+/// The additional sense codes ATA pass-through and USB bridges commonly report.
+fn asc_name(asc: u8, ascq: u8) -> Option<&'static str> {
+    Some(match (asc, ascq) {
+        (0x00, 0x00) => "no additional sense information",
+        (0x00, 0x1D) => "ATA pass through information available",
+        (0x04, _) => "logical unit not ready",
+        (0x11, _) => "unrecovered read error",
+        (0x20, 0x00) => "invalid command operation code",
+        (0x21, 0x00) => "logical block address out of range",
+        (0x24, 0x00) => "invalid field in CDB",
+        (0x26, 0x00) => "invalid field in parameter list",
+        (0x29, _) => "power on, reset, or bus device reset occurred",
+        (0x3A, _) => "medium not present",
+        (0x44, 0x00) => "internal target failure",
+        (0x47, _) => "SCSI parity error",
+        (0x4B, _) => "data phase error",
+        _ => return None,
+    })
+}
+
+/// SCSI device reset (SG_SCSI_RESET). libata resets the link and the drive; USB storage
+/// resets the bridge.
+pub fn reset_device(fd: i32) -> Result<(), String> {
+    let mut op = SG_SCSI_RESET_DEVICE;
+    let ret = unsafe { libc::ioctl(fd, SG_SCSI_RESET, &mut op as *mut i32) };
+    if ret < 0 {
+        let errno = unsafe { *libc::__errno_location() };
+        return Err(format!(
+            "scsi device reset failed: {} (errno {})",
+            errno_to_str(errno),
+            errno
+        ));
+    }
+    Ok(())
+}
+
+pub const DEFAULT_TIMEOUT_MS: u32 = 10_000;
+const SCSI_INQUIRY: u8 = 0x12;
+
+/// A SCSI device that vendor CDBs are sent to as they are, e.g. a UFS logical unit or
+/// a USB flash drive. ATA devices use `AtaDevice`, which wraps its commands in ATA
+/// PASS-THROUGH.
+pub struct ScsiDevice {
+    fd: i32,
+    timeout_ms: Cell<u32>,
+    timeouts: Cell<u32>,
+    dry_run: Cell<bool>,
+    planned: Cell<u32>,
+}
+
+impl ScsiDevice {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let c_path =
+            CString::new(path).map_err(|e| format!("invalid device path '{}': {}", path, e))?;
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_RDWR) };
+        if fd < 0 {
+            let errno = unsafe { *libc::__errno_location() };
+            return Err(format!(
+                "failed to open '{}': {} (errno {})",
+                path,
+                errno_to_str(errno),
+                errno
+            ));
+        }
+        Ok(ScsiDevice {
+            fd,
+            timeout_ms: Cell::new(DEFAULT_TIMEOUT_MS),
+            timeouts: Cell::new(0),
+            dry_run: Cell::new(false),
+            planned: Cell::new(0),
+        })
+    }
+
+    pub fn set_timeout(&self, timeout_ms: u32) {
+        self.timeout_ms.set(timeout_ms);
+    }
+
+    /// In dry-run mode every CDB except INQUIRY is printed instead of sent, and data-in
+    /// commands "return" a zeroed buffer.
+    pub fn set_dry_run(&self, dry_run: bool) {
+        self.dry_run.set(dry_run);
+    }
+
+    /// Number of commands printed (not sent) in dry-run mode.
+    pub fn planned(&self) -> u32 {
+        self.planned.get()
+    }
+
+    /// Number of commands that timed out since the device was opened.
+    pub fn timeouts(&self) -> u32 {
+        self.timeouts.get()
+    }
+
+    pub fn reset_device(&self) -> Result<(), String> {
+        reset_device(self.fd)
+    }
+
+    /// Standard INQUIRY, 96 bytes: vendor at 8..16, product at 16..32, revision at 32..36.
+    pub fn inquiry(&self) -> Result<[u8; 96], String> {
+        let mut buf = [0u8; 96];
+        self.cdb_read(&[SCSI_INQUIRY, 0, 0, 0, buf.len() as u8, 0], &mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn cdb_read(&self, cdb: &[u8], buf: &mut [u8]) -> Result<(), String> {
+        self.submit(cdb, SG_DXFER_FROM_DEV, buf.as_mut_ptr(), buf.len())
+    }
+
+    pub fn cdb_write(&self, cdb: &[u8], buf: &[u8]) -> Result<(), String> {
+        self.submit(cdb, SG_DXFER_TO_DEV, buf.as_ptr() as *mut u8, buf.len())
+    }
+
+    pub fn cdb_no_data(&self, cdb: &[u8]) -> Result<(), String> {
+        self.submit(cdb, SG_DXFER_NONE, std::ptr::null_mut(), 0)
+    }
+
+    fn submit(&self, cdb: &[u8], direction: i32, dxferp: *mut u8, dxfer_len: usize) -> Result<(), String> {
+        crate::interrupt::check()?;
+        let opcode = cdb[0];
+        let desc = describe_cdb(cdb, direction, dxfer_len);
+        if self.dry_run.get() && opcode != SCSI_INQUIRY {
+            self.planned.set(self.planned.get() + 1);
+            println!("[dry-run] {}", desc);
+            if dxfer_len > 0 {
+                let data = unsafe { std::slice::from_raw_parts_mut(dxferp, dxfer_len) };
+                if direction == SG_DXFER_TO_DEV {
+                    print!("{}", hexdump(data, "    "));
+                } else {
+                    data.fill(0);
+                }
+            }
+            return Ok(());
+        }
+
+        let mut sense = [0u8; SENSE_BUF_LEN as usize];
+        let start = Instant::now();
+        let (hdr, errno) = send(self.fd, cdb, direction, dxferp, dxfer_len, self.timeout_ms.get(), &mut sense);
+        let elapsed = start.elapsed();
+        let sense_data = &sense[..(hdr.sb_len_wr as usize).min(sense.len())];
+
+        let outcome = if errno != 0 {
+            Err(format!(
+                "scsi ioctl failed: {} (errno {}, opcode 0x{:02x})",
+                errno_to_str(errno),
+                errno,
+                opcode
+            ))
+        } else if hdr.timed_out() {
+            self.timeouts.set(self.timeouts.get() + 1);
+            Err(format!(
+                "scsi command timed out after {} ms (opcode 0x{:02x})",
+                elapsed.as_millis(),
+                opcode
+            ))
+        } else if let Some(s) = decode_sense(sense_data).filter(|s| s.key > 0x01) {
+            Err(format!("scsi command 0x{:02x} failed: {}", opcode, s.describe()))
+        } else if hdr.status != 0 || hdr.host_status != 0 || hdr.driver_status & 0x0F != 0 {
+            Err(format!("scsi command 0x{:02x} failed: {}", opcode, hdr.describe_status()))
+        } else {
+            Ok(())
+        };
+
+        let data = if dxfer_len > 0 {
+            Some(unsafe { std::slice::from_raw_parts(dxferp as *const u8, dxfer_len) })
+        } else {
+            None
+        };
+        crate::trace::record(&crate::trace::CommandRecord {
+            tag: format!("scsi-{:02x}", opcode),
+            desc,
+            vendor: opcode != SCSI_INQUIRY,
+            duration: elapsed,
+            status: if errno != 0 { format!("errno {}", errno) } else { hdr.describe_status() },
+            error: outcome.as_ref().err().map(|e| e.as_str()),
+            data_out: data.filter(|_| direction == SG_DXFER_TO_DEV),
+            data_in: data.filter(|_| direction == SG_DXFER_FROM_DEV && outcome.is_ok()),
+            sense: Some(sense_data),
+        });
+        outcome
+    }
+}
+
+impl Drop for ScsiDevice {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// "scsi cdb=c0 00 00 00 00 00 00 00 00 00 dir=in len=512"
+pub fn describe_cdb(cdb: &[u8], direction: i32, len: usize) -> String {
+    let bytes: Vec<String> = cdb.iter().map(|b| format!("{:02x}", b)).collect();
+    let dir = match direction {
+        SG_DXFER_TO_DEV => "out",
+        SG_DXFER_FROM_DEV => "in",
+        _ => "none",
+    };
+    format!("scsi cdb={} dir={} len={}", bytes.join(" "), dir, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sg_io_v4_layout() {
+        // <linux/bsg.h>: 160 bytes, din_xferp at 80, timeout at 88, response_len at 132
+        assert_eq!(std::mem::size_of::<SgIoV4>(), 160);
+        let v4 = SgIoV4::default();
+        let base = &v4 as *const SgIoV4 as usize;
+        assert_eq!(&v4.din_xferp as *const u64 as usize - base, 80);
+        assert_eq!(&v4.timeout as *const u32 as usize - base, 88);
+        assert_eq!(&v4.response_len as *const u32 as usize - base, 132);
+    }
+
+    #[test]
+    fn test_decode_sense() {
+        let mut fixed = [0u8; 18];
+        fixed[0] = 0x70;
+        fixed[2] = 0x05;
+        fixed[3..7].copy_from_slice(&[0x04, 0x51, 0xE0, 0x01]);
+        fixed[12] = 0x24;
+        let sense = decode_sense(&fixed).unwrap();
+        assert_eq!((sense.key, sense.asc, sense.ascq), (0x05, 0x24, 0x00));
+        assert_eq!(sense.describe(), "illegal request: invalid field in CDB (24/00)");
+        assert_eq!(sense.information(), Some([0x04, 0x51, 0xE0, 0x01]));
+        assert!(sense.descriptor(0x09).is_none());
+
+        // Descriptor format with an information descriptor (0x00) before the ATA one
+        let mut desc = vec![0x72, 0x01, 0x00, 0x1D, 0, 0, 0, 26];
+        desc.extend_from_slice(&[0x00, 0x0A, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        desc.extend_from_slice(&[0x09, 0x0C, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0xE0, 0x50]);
+        let sense = decode_sense(&desc).unwrap();
+        assert_eq!(sense.descriptor(0x09).map(|d| d[13]), Some(0x50));
+        assert!(sense.information().is_none());
+        assert_eq!(
+            describe_sense(0x06, 0x29, 0x00),
+            "unit attention: power on, reset, or bus device reset occurred (29/00)"
+        );
+        assert!(decode_sense(&[]).is_none());
+    }
+}
@@ -0,0 +1,527 @@
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    Nvme,
+    Sata,
+    /// eMMC (/dev/mmcblkN)
+    Mmc,
+    /// UFS host, addressed through its ufs-bsg node (/dev/bsg/ufs-bsgN)
+    Ufs,
+}
+
+pub struct ResolvedDevice {
+    pub path: String,
+    pub kind: DeviceKind,
+}
+
+/// Resolves a user-supplied device path to the node the vendor commands must be sent to.
+/// Symlinks (/dev/disk/by-id/...) are followed, NVMe namespaces and partitions map to their
+/// controller character device, and SATA partitions map to the whole disk.
+pub fn resolve_device(path: &str) -> Result<ResolvedDevice, String> {
+    let real = std::fs::canonicalize(path)
+        .map_err(|e| format!("failed to resolve '{}': {}", path, e))?;
+    let name = file_name(&real);
+
+    if Path::new("/sys/class/nvme").join(&name).exists() {
+        return Ok(ResolvedDevice {
+            path: format!("/dev/{}", name),
+            kind: DeviceKind::Nvme,
+        });
+    }
+
+    let class_block = Path::new("/sys/class/block").join(&name);
+    if class_block.exists() {
+        let disk = if class_block.join("partition").exists() {
+            std::fs::canonicalize(class_block.join(".."))
+                .map(|p| file_name(&p))
+                .unwrap_or_else(|_| name.clone())
+        } else {
+            name.clone()
+        };
+
+        if let Some(bsg) = ufs_bsg_for(&disk) {
+            return Ok(ResolvedDevice {
+                path: bsg,
+                kind: DeviceKind::Ufs,
+            });
+        }
+        if let Some(card) = mmc_card_from_name(&disk) {
+            return Ok(ResolvedDevice {
+                path: format!("/dev/{}", card),
+                kind: DeviceKind::Mmc,
+            });
+        }
+        if disk.starts_with("nvme") {
+            // The name only tells the controller when sysfs has no device link to go by: with
+            // native multipath, nvme0n1 is subsystem 0's namespace, not controller nvme0's
+            let ctrl = if Path::new("/sys/block").join(&disk).join("device").exists() {
+                nvme_controller_from_sysfs(&disk)
+            } else {
+                nvme_controller_from_namespace(&disk)
+            }
+            .ok_or_else(|| format!("cannot find NVMe controller for '{}'", path))?;
+            return Ok(ResolvedDevice {
+                path: format!("/dev/{}", ctrl),
+                kind: DeviceKind::Nvme,
+            });
+        }
+        return Ok(ResolvedDevice {
+            path: format!("/dev/{}", disk),
+            kind: DeviceKind::Sata,
+        });
+    }
+
+    if name.starts_with("ufs-bsg") {
+        return Ok(ResolvedDevice {
+            path: format!("/dev/bsg/{}", name),
+            kind: DeviceKind::Ufs,
+        });
+    }
+
+    // SCSI generic nodes are used as given: they exist for disks without a block device
+    if is_scsi_generic(&real.to_string_lossy()) {
+        return Ok(ResolvedDevice {
+            path: real.to_string_lossy().into_owned(),
+            kind: DeviceKind::Sata,
+        });
+    }
+
+    // No sysfs entry (e.g. containers without /sys): fall back to naming and file type
+    if let Some(ctrl) = nvme_controller_from_namespace(&name) {
+        return Ok(ResolvedDevice {
+            path: format!("/dev/{}", ctrl),
+            kind: DeviceKind::Nvme,
+        });
+    }
+    if let Some(card) = mmc_card_from_name(&name) {
+        return Ok(ResolvedDevice {
+            path: format!("/dev/{}", card),
+            kind: DeviceKind::Mmc,
+        });
+    }
+    let is_block = std::fs::metadata(&real)
+        .map(|m| m.file_type().is_block_device())
+        .unwrap_or(false);
+    Ok(ResolvedDevice {
+        path: real.to_string_lossy().into_owned(),
+        kind: if is_block { DeviceKind::Sata } else { DeviceKind::Nvme },
+    })
+}
+
+/// /sys/block/nvme0n1/device links to the owning controller (nvme0). With native NVMe
+/// multipath it links to the subsystem (nvme-subsys0) instead; its first live controller
+/// from /sys/class/nvme-subsystem/nvme-subsys0/nvme* is used then.
+fn nvme_controller_from_sysfs(disk: &str) -> Option<String> {
+    let dev = std::fs::canonicalize(Path::new("/sys/block").join(disk).join("device")).ok()?;
+    let name = file_name(&dev);
+    if Path::new("/sys/class/nvme").join(&name).exists() {
+        return Some(name);
+    }
+    let subsys = Path::new("/sys/class/nvme-subsystem").join(&name);
+    let mut ctrls: Vec<String> = std::fs::read_dir(subsys)
+        .ok()?
+        .flatten()
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .filter(|n| nvme_controller_from_namespace(n).as_deref() == Some(n.as_str()))
+        .collect();
+    ctrls.sort_by_key(|n| n[4..].parse::<u32>().unwrap_or(u32::MAX));
+    let live = |n: &String| {
+        std::fs::read_to_string(Path::new("/sys/class/nvme").join(n).join("state"))
+            .is_ok_and(|s| s.trim() == "live")
+    };
+    ctrls.iter().find(|n| live(n)).or(ctrls.first()).cloned()
+}
+
+/// Maps a namespace or partition name (nvme0n1, nvme0n1p2) to the controller name (nvme0).
+fn nvme_controller_from_namespace(name: &str) -> Option<String> {
+    let suffix = name.strip_prefix("nvme")?;
+    let digits: String = suffix.chars().take_while(|c| c.is_ascii_digit()).collect();
+    if digits.is_empty() {
+        return None;
+    }
+    let rest = &suffix[digits.len()..];
+    if rest.is_empty() || rest.starts_with('n') {
+        Some(format!("nvme{}", digits))
+    } else {
+        None
+    }
+}
+
+/// Maps an eMMC node (mmcblk0, mmcblk0p2, mmcblk0boot1, mmcblk0rpmb) to the user
+/// area disk (mmcblk0), which is the node MMC_IOC_CMD is issued on.
+fn mmc_card_from_name(name: &str) -> Option<String> {
+    let suffix = name.strip_prefix("mmcblk")?;
+    let digits: String = suffix.chars().take_while(|c| c.is_ascii_digit()).collect();
+    if digits.is_empty() {
+        return None;
+    }
+    let rest = &suffix[digits.len()..];
+    let known = rest.is_empty()
+        || rest == "rpmb"
+        || ["p", "boot"]
+            .iter()
+            .any(|p| rest.strip_prefix(p).is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit())));
+    known.then(|| format!("mmcblk{}", digits))
+}
+
+/// True for an eMMC device; SD cards share the mmcblk naming but have type "SD".
+pub fn is_emmc(dev_path: &str) -> bool {
+    let name = file_name(Path::new(dev_path));
+    std::fs::read_to_string(Path::new("/sys/class/block").join(name).join("device/type"))
+        .is_ok_and(|t| t.trim() == "MMC")
+}
+
+/// The card's CID register, from /sys/class/block/mmcblkN/device/cid (32 hex digits).
+pub fn mmc_cid(dev_path: &str) -> Option<[u8; 16]> {
+    let name = file_name(Path::new(dev_path));
+    let hex = std::fs::read_to_string(Path::new("/sys/class/block").join(name).join("device/cid")).ok()?;
+    let hex = hex.trim();
+    if hex.len() != 32 {
+        return None;
+    }
+    let mut cid = [0u8; 16];
+    for (i, b) in cid.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(cid)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// How a SCSI disk (/dev/sdX) is attached, from its sysfs device path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockTransport {
+    /// libata port (ataN in the path)
+    Ata,
+    Usb,
+    /// SAS HBA (end_device-*), which may still have a SATA drive behind it
+    Sas,
+    Iscsi,
+    Virtual,
+    Unknown,
+}
+
+impl BlockTransport {
+    pub fn name(self) -> &'static str {
+        match self {
+            BlockTransport::Ata => "ata",
+            BlockTransport::Usb => "usb",
+            BlockTransport::Sas => "sas",
+            BlockTransport::Iscsi => "iscsi",
+            BlockTransport::Virtual => "virtual",
+            BlockTransport::Unknown => "unknown",
+        }
+    }
+}
+
+/// Works for /dev/sdX and its /dev/sgN and /dev/bsg/H:C:T:L nodes alike.
+pub fn block_transport(dev_path: &str) -> BlockTransport {
+    let Some(real) = scsi_node_sysfs(dev_path) else {
+        return BlockTransport::Unknown;
+    };
+    let components: Vec<String> = real.iter().map(|c| c.to_string_lossy().into_owned()).collect();
+    let any = |pred: &dyn Fn(&str) -> bool| components.iter().any(|c| pred(c));
+    if any(&|c| c.starts_with("usb")) {
+        BlockTransport::Usb
+    } else if any(&|c| c.starts_with("session")) {
+        BlockTransport::Iscsi
+    } else if any(&|c| c.starts_with("end_device-")) {
+        BlockTransport::Sas
+    } else if any(&|c| c.strip_prefix("ata").is_some_and(|n| n.chars().all(|ch| ch.is_ascii_digit()) && !n.is_empty())) {
+        BlockTransport::Ata
+    } else if any(&|c| c.starts_with("virtio") || c == "virtual") {
+        BlockTransport::Virtual
+    } else {
+        BlockTransport::Unknown
+    }
+}
+
+/// idVendor/idProduct of the USB device a SCSI disk sits on, from the nearest USB
+/// device directory above it in sysfs.
+pub fn usb_id(dev_path: &str) -> Option<(u16, u16)> {
+    let real = scsi_node_sysfs(dev_path)?;
+    let id = |dir: &Path, attr: &str| {
+        let s = std::fs::read_to_string(dir.join(attr)).ok()?;
+        u16::from_str_radix(s.trim(), 16).ok()
+    };
+    real.ancestors()
+        .find_map(|dir| Some((id(dir, "idVendor")?, id(dir, "idProduct")?)))
+}
+
+/// iSerial string of the USB device a SCSI disk sits on.
+pub fn usb_serial(dev_path: &str) -> Option<String> {
+    let real = scsi_node_sysfs(dev_path)?;
+    real.ancestors()
+        .find(|dir| dir.join("idVendor").exists())
+        .and_then(|dir| std::fs::read_to_string(dir.join("serial")).ok())
+        .map(|s| s.trim().to_string())
+}
+
+/// queue/rotational of a block device (the kernel's view, which USB bridges often get wrong).
+/// SCSI generic nodes use their disk's value; sg-only devices have none.
+pub fn block_rotational(dev_path: &str) -> Option<bool> {
+    let disk = match disk_for_generic(dev_path) {
+        Some(disk) => disk,
+        None => dev_path.to_string(),
+    };
+    let name = file_name(Path::new(&disk));
+    let path = Path::new("/sys/class/block").join(name).join("queue/rotational");
+    match std::fs::read_to_string(path).ok()?.trim() {
+        "0" => Some(false),
+        "1" => Some(true),
+        _ => None,
+    }
+}
+
+/// Canonical sysfs directory of a SCSI disk, SCSI generic or bsg node.
+fn scsi_node_sysfs(dev_path: &str) -> Option<std::path::PathBuf> {
+    let name = file_name(Path::new(dev_path));
+    ["/sys/class/block", "/sys/class/scsi_generic", "/sys/class/bsg"]
+        .iter()
+        .find_map(|class| std::fs::canonicalize(Path::new(class).join(&name)).ok())
+}
+
+/// True for /dev/sgN and /dev/bsg/* nodes of SCSI devices (not SAS hosts or ufs-bsg).
+pub fn is_scsi_generic(dev_path: &str) -> bool {
+    let name = file_name(Path::new(dev_path));
+    Path::new("/sys/class/scsi_generic").join(&name).exists()
+        || Path::new("/sys/class/bsg").join(&name).join("device/type").exists()
+}
+
+/// The only entry of a sysfs directory such as device/scsi_generic or device/block.
+fn single_entry(dir: &Path) -> Option<String> {
+    let entry = std::fs::read_dir(dir).ok()?.flatten().next()?;
+    Some(entry.file_name().to_string_lossy().into_owned())
+}
+
+/// /dev/sgN of a disk, from /sys/class/block/sdX/device/scsi_generic.
+pub fn sg_node(dev_path: &str) -> Option<String> {
+    let name = file_name(Path::new(dev_path));
+    let dir = Path::new("/sys/class/block").join(name).join("device/scsi_generic");
+    single_entry(&dir).map(|sg| format!("/dev/{}", sg))
+}
+
+/// /dev/bsg/H:C:T:L of a disk, from /sys/class/block/sdX/device/bsg.
+pub fn bsg_node(dev_path: &str) -> Option<String> {
+    let name = file_name(Path::new(dev_path));
+    let dir = Path::new("/sys/class/block").join(name).join("device/bsg");
+    single_entry(&dir).map(|bsg| format!("/dev/bsg/{}", bsg))
+}
+
+/// /dev/sdX of a SCSI generic or bsg node, None for sg-only devices (no disk driver
+/// bound, e.g. locked drives or ones without a medium).
+pub fn disk_for_generic(dev_path: &str) -> Option<String> {
+    let name = file_name(Path::new(dev_path));
+    ["/sys/class/scsi_generic", "/sys/class/bsg"]
+        .iter()
+        .find_map(|class| single_entry(&Path::new(class).join(&name).join("device/block")))
+        .map(|disk| format!("/dev/{}", disk))
+}
+
+/// /dev/sgN nodes of direct-access devices (SCSI peripheral type 0) that have no disk.
+pub fn sg_only_devices() -> Vec<String> {
+    let Ok(dir) = std::fs::read_dir("/sys/class/scsi_generic") else {
+        return Vec::new();
+    };
+    let mut devices: Vec<String> = dir
+        .flatten()
+        .filter(|e| {
+            let dev = e.path().join("device");
+            std::fs::read_to_string(dev.join("type")).is_ok_and(|t| t.trim() == "0")
+                && !dev.join("block").exists()
+        })
+        .map(|e| format!("/dev/{}", e.file_name().to_string_lossy()))
+        .collect();
+    devices.sort();
+    devices
+}
+
+/// ufs-bsg nodes of every UFS host (/dev/bsg/ufs-bsg0, or /dev/bsg/ufs-bsg on older kernels).
+pub fn ufs_bsg_nodes() -> Vec<String> {
+    let Ok(dir) = std::fs::read_dir("/sys/class/bsg") else {
+        return Vec::new();
+    };
+    let mut nodes: Vec<String> = dir
+        .flatten()
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .filter(|n| n.starts_with("ufs-bsg"))
+        .map(|n| format!("/dev/bsg/{}", n))
+        .collect();
+    nodes.sort();
+    nodes
+}
+
+/// The SCSI host directory a ufs-bsg node hangs off.
+fn ufs_host_dir(bsg_path: &str) -> Option<std::path::PathBuf> {
+    let name = file_name(Path::new(bsg_path));
+    let real = std::fs::canonicalize(Path::new("/sys/class/bsg").join(name)).ok()?;
+    real.parent().map(Path::to_path_buf)
+}
+
+/// The ufs-bsg node of the UFS host a disk (a UFS logical unit) belongs to.
+pub fn ufs_bsg_for(dev_path: &str) -> Option<String> {
+    let disk = std::fs::canonicalize(Path::new("/sys/class/block").join(file_name(Path::new(dev_path)))).ok()?;
+    ufs_bsg_nodes()
+        .into_iter()
+        .find(|bsg| ufs_host_dir(bsg).is_some_and(|host| disk.starts_with(host)))
+}
+
+/// The disk of the lowest-numbered logical unit behind a ufs-bsg node, for vendor
+/// SCSI commands.
+pub fn ufs_lun_disk(bsg_path: &str) -> Option<String> {
+    let host = ufs_host_dir(bsg_path)?;
+    let lun = |disk: &Path| -> Option<u64> {
+        let dev = std::fs::canonicalize(disk.join("device")).ok()?;
+        file_name(&dev).rsplit(':').next()?.parse().ok()
+    };
+    std::fs::read_dir("/sys/class/block")
+        .ok()?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| !p.join("partition").exists())
+        .filter(|p| std::fs::canonicalize(p).is_ok_and(|real| real.starts_with(&host)))
+        .filter_map(|p| Some((lun(&p)?, file_name(&p))))
+        .min()
+        .map(|(_, disk)| format!("/dev/{}", disk))
+}
+
+/// PCI identity and link state of an NVMe controller's function.
+#[derive(Debug, Clone)]
+pub struct PciInfo {
+    pub vendor: u16,
+    pub device: u16,
+    pub subsystem_vendor: u16,
+    pub subsystem_device: u16,
+    /// e.g. "8.0 GT/s PCIe", as reported by the kernel
+    pub link_speed: Option<String>,
+    pub link_width: Option<String>,
+    pub max_link_speed: Option<String>,
+    pub max_link_width: Option<String>,
+}
+
+/// Reads /sys/class/nvme/<ctrl>/device/*. None for controllers that are not PCI
+/// functions (fabrics, some USB bridges) or without sysfs.
+pub fn nvme_pci_info(dev_path: &str) -> Option<PciInfo> {
+    let dir = Path::new("/sys/class/nvme").join(file_name(Path::new(dev_path))).join("device");
+    let attr = |name: &str| {
+        std::fs::read_to_string(dir.join(name))
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty() && s != "Unknown")
+    };
+    let id = |name: &str| {
+        let s = attr(name)?;
+        u16::from_str_radix(s.strip_prefix("0x").unwrap_or(&s), 16).ok()
+    };
+    Some(PciInfo {
+        vendor: id("vendor")?,
+        device: id("device")?,
+        subsystem_vendor: id("subsystem_vendor").unwrap_or(0),
+        subsystem_device: id("subsystem_device").unwrap_or(0),
+        link_speed: attr("current_link_speed"),
+        link_width: attr("current_link_width"),
+        max_link_speed: attr("max_link_speed"),
+        max_link_width: attr("max_link_width"),
+    })
+}
+
+impl PciInfo {
+    /// "1987:5012 (subsystem 1987:5012), PCIe 8.0 GT/s x4 (max 16.0 GT/s x4)"
+    pub fn describe(&self) -> String {
+        let mut s = format!(
+            "{:04x}:{:04x} (subsystem {:04x}:{:04x})",
+            self.vendor, self.device, self.subsystem_vendor, self.subsystem_device
+        );
+        let link = |speed: &Option<String>, width: &Option<String>| match (speed, width) {
+            (Some(speed), Some(width)) => {
+                Some(format!("{} x{}", speed.trim_end_matches(" PCIe"), width))
+            }
+            _ => None,
+        };
+        if let Some(cur) = link(&self.link_speed, &self.link_width) {
+            s.push_str(&format!(", PCIe {}", cur));
+            if let Some(max) = link(&self.max_link_speed, &self.max_link_width)
+                && max != cur
+            {
+                s.push_str(&format!(" (max {})", max));
+            }
+        }
+        s
+    }
+}
+
+/// Strips the common WWN/EUI prefixes and separators so identifiers from different
+/// sources (lsblk, /dev/disk/by-id, nvme-cli) compare equal. The by-id prefixes
+/// ("nvme-eui.", "wwn-0x") go first, in the order they appear in a link name.
+pub fn normalize_wwn(wwn: &str) -> String {
+    let lower = wwn.trim().to_ascii_lowercase();
+    let stripped = ["nvme-", "wwn-", "naa.", "eui.", "nvme.", "0x"]
+        .iter()
+        .fold(lower.as_str(), |s, p| s.strip_prefix(p).unwrap_or(s))
+        .to_string();
+    stripped.chars().filter(|c| c.is_ascii_hexdigit()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespace_to_controller() {
+        assert_eq!(nvme_controller_from_namespace("nvme0"), Some("nvme0".into()));
+        assert_eq!(nvme_controller_from_namespace("nvme0n1"), Some("nvme0".into()));
+        assert_eq!(nvme_controller_from_namespace("nvme12n1p3"), Some("nvme12".into()));
+        assert_eq!(nvme_controller_from_namespace("nvme-fabrics"), None);
+        assert_eq!(nvme_controller_from_namespace("sda"), None);
+    }
+
+    #[test]
+    fn test_mmc_card_from_name() {
+        assert_eq!(mmc_card_from_name("mmcblk0"), Some("mmcblk0".into()));
+        assert_eq!(mmc_card_from_name("mmcblk0p2"), Some("mmcblk0".into()));
+        assert_eq!(mmc_card_from_name("mmcblk1boot0"), Some("mmcblk1".into()));
+        assert_eq!(mmc_card_from_name("mmcblk0rpmb"), Some("mmcblk0".into()));
+        assert_eq!(mmc_card_from_name("mmcblk0gp"), None);
+        assert_eq!(mmc_card_from_name("sda"), None);
+    }
+
+    #[test]
+    fn test_pci_describe() {
+        let mut pci = PciInfo {
+            vendor: 0x1987,
+            device: 0x5012,
+            subsystem_vendor: 0x1987,
+            subsystem_device: 0x5012,
+            link_speed: Some("8.0 GT/s PCIe".into()),
+            link_width: Some("4".into()),
+            max_link_speed: Some("8.0 GT/s PCIe".into()),
+            max_link_width: Some("4".into()),
+        };
+        assert_eq!(pci.describe(), "1987:5012 (subsystem 1987:5012), PCIe 8.0 GT/s x4");
+        pci.link_width = Some("2".into());
+        assert_eq!(
+            pci.describe(),
+            "1987:5012 (subsystem 1987:5012), PCIe 8.0 GT/s x2 (max 8.0 GT/s x4)"
+        );
+    }
+
+    #[test]
+    fn test_normalize_wwn() {
+        assert_eq!(normalize_wwn("naa.5002538E40A1B2C3"), "5002538e40a1b2c3");
+        assert_eq!(normalize_wwn("0x5002538e40a1b2c3"), "5002538e40a1b2c3");
+        assert_eq!(normalize_wwn("eui.0025-3857-11b2-aa01"), "0025385711b2aa01");
+        // /dev/disk/by-id link names
+        assert_eq!(normalize_wwn("nvme-eui.0025385711b2aa01"), "0025385711b2aa01");
+        assert_eq!(
+            normalize_wwn("nvme-eui.e8238fa6bf530001001b448b4a2f1c3d"),
+            "e8238fa6bf530001001b448b4a2f1c3d"
+        );
+        assert_eq!(normalize_wwn("wwn-0x5002538e40a1b2c3"), "5002538e40a1b2c3");
+    }
+}
@@ -0,0 +1,178 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::hexdump::hexdump;
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+static AUDIT: Mutex<Option<AuditSink>> = Mutex::new(None);
+static DEVICE: Mutex<Option<(String, String)>> = Mutex::new(None);
+static CAPTURE: Mutex<Option<Vec<Outcome>>> = Mutex::new(None);
+
+enum AuditSink {
+    File(File),
+    Syslog,
+}
+
+/// One issued command as seen by the transport, after completion.
+pub struct CommandRecord<'a> {
+    /// Short command tag for file names, e.g. "nvme-c2" or "ata-b0"
+    pub tag: String,
+    pub desc: String,
+    /// Anything other than the standard Identify; only these go to the audit log
+    pub vendor: bool,
+    pub duration: Duration,
+    /// Completion details (NVMe result/status, SG_IO host/driver/SCSI status)
+    pub status: String,
+    pub error: Option<&'a str>,
+    pub data_out: Option<&'a [u8]>,
+    pub data_in: Option<&'a [u8]>,
+    pub sense: Option<&'a [u8]>,
+}
+
+/// What became of one command, kept while a capture is active.
+pub struct Outcome {
+    pub desc: String,
+    pub error: Option<String>,
+    /// (response length, bytes that are neither 0x00 nor 0xFF) for data-in commands
+    pub data_in: Option<(usize, usize)>,
+}
+
+/// -v logs every command with its duration and status, -vv adds payload, response
+/// and sense hexdumps.
+pub fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+/// Opens the append-only audit log: a file path, or "syslog" for the system logger.
+pub fn open_audit_log(target: &str) -> Result<(), String> {
+    let sink = if target == "syslog" {
+        unsafe { libc::openlog(c"ssd-flash-id".as_ptr(), libc::LOG_PID, libc::LOG_USER) };
+        AuditSink::Syslog
+    } else {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(target)
+            .map_err(|e| format!("failed to open audit log '{}': {}", target, e))?;
+        AuditSink::File(file)
+    };
+    *AUDIT.lock().unwrap() = Some(sink);
+    Ok(())
+}
+
+/// Device the following commands are sent to, recorded in each audit entry.
+pub fn set_device(path: &str, serial: &str) {
+    *DEVICE.lock().unwrap() = Some((path.to_string(), serial.to_string()));
+}
+
+pub fn record(rec: &CommandRecord) {
+    let verbosity = VERBOSITY.load(Ordering::Relaxed);
+    if verbosity >= 1 {
+        let outcome = match rec.error {
+            Some(e) => format!("FAILED: {}", e),
+            None => "ok".to_string(),
+        };
+        eprintln!(
+            "[trace] {} -> {} ({}) in {:.3} ms",
+            rec.desc,
+            outcome,
+            rec.status,
+            rec.duration.as_secs_f64() * 1000.0
+        );
+    }
+    if verbosity >= 2 {
+        if let Some(data) = rec.data_out {
+            eprintln!("[trace]   payload:");
+            eprint!("{}", hexdump(data, "    "));
+        }
+        if let Some(data) = rec.data_in {
+            eprintln!("[trace]   response:");
+            eprint!("{}", hexdump(data, "    "));
+        }
+        if let Some(sense) = rec.sense.filter(|s| !s.is_empty()) {
+            eprintln!("[trace]   sense:");
+            eprint!("{}", hexdump(sense, "    "));
+        }
+    }
+
+    if let Some(data) = rec.data_in {
+        crate::dump::save(rec, data);
+    }
+    if let Some(capture) = CAPTURE.lock().unwrap().as_mut() {
+        capture.push(Outcome {
+            desc: rec.desc.clone(),
+            error: rec.error.map(str::to_string),
+            data_in: rec
+                .data_in
+                .map(|d| (d.len(), d.iter().filter(|&&b| b != 0x00 && b != 0xFF).count())),
+        });
+    }
+    if rec.vendor {
+        audit(rec);
+    }
+}
+
+/// Starts collecting the outcome of every following command.
+pub fn start_capture() {
+    *CAPTURE.lock().unwrap() = Some(Vec::new());
+}
+
+/// Stops collecting and returns the outcomes since `start_capture`.
+pub fn take_capture() -> Vec<Outcome> {
+    CAPTURE.lock().unwrap().take().unwrap_or_default()
+}
+
+fn audit(rec: &CommandRecord) {
+    let mut guard = AUDIT.lock().unwrap();
+    let Some(sink) = guard.as_mut() else {
+        return;
+    };
+    let (path, serial) = DEVICE.lock().unwrap().clone().unwrap_or_default();
+    let outcome = match rec.error {
+        Some(e) => format!("error: {}", e),
+        None => "ok".to_string(),
+    };
+    let line = format!(
+        "device={} serial={} cmd=[{}] status=[{}] outcome={}",
+        path, serial, rec.desc, rec.status, outcome
+    );
+    match sink {
+        AuditSink::File(file) => {
+            let _ = writeln!(file, "{} pid={} {}", utc_timestamp(), std::process::id(), line);
+        }
+        AuditSink::Syslog => {
+            if let Ok(msg) = std::ffi::CString::new(line) {
+                unsafe { libc::syslog(libc::LOG_NOTICE, c"%s".as_ptr(), msg.as_ptr()) };
+            }
+        }
+    }
+}
+
+/// RFC 3339 UTC timestamp (days-to-civil conversion, to avoid a date/time dependency).
+fn utc_timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = now.as_secs() as i64;
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        now.subsec_millis()
+    )
+}
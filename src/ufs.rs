@@ -0,0 +1,401 @@
+//! Query requests to a UFS device through the host's ufs-bsg node (/dev/bsg/ufs-bsgN).
+//! Vendor SCSI commands go to a logical unit (/dev/sdX) through `scsi::ScsiDevice`.
+
+use std::ffi::CString;
+use std::time::Instant;
+
+use crate::errno::errno_to_str;
+use crate::scsi::{SgIoV4, BSG_SUB_PROTOCOL_SCSI_TRANSPORT, SG_IO};
+
+const UPIU_TRANSACTION_QUERY_REQ: u8 = 0x16;
+const UPIU_QUERY_FUNC_STANDARD_READ_REQUEST: u8 = 0x01;
+const QUERY_OPCODE_READ_DESC: u8 = 0x01;
+const QUERY_DESC_MAX_SIZE: usize = 255;
+const QUERY_TIMEOUT_MS: u32 = 5_000;
+
+pub const DESC_DEVICE: u8 = 0x00;
+pub const DESC_STRING: u8 = 0x05;
+pub const DESC_GEOMETRY: u8 = 0x07;
+pub const DESC_HEALTH: u8 = 0x09;
+
+/// struct ufs_bsg_request: msgcode, then the query request UPIU (header and query
+/// fields as raw big-endian bytes)
+#[repr(C)]
+struct UfsBsgRequest {
+    msgcode: u32,
+    header: [u8; 12],
+    query: [u8; 20],
+}
+
+/// struct ufs_bsg_reply
+#[repr(C)]
+struct UfsBsgReply {
+    result: i32,
+    reply_payload_rcv_len: u32,
+    header: [u8; 12],
+    query: [u8; 20],
+}
+
+pub struct UfsDevice {
+    fd: i32,
+}
+
+impl UfsDevice {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let c_path =
+            CString::new(path).map_err(|e| format!("invalid device path '{}': {}", path, e))?;
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_RDWR) };
+        if fd < 0 {
+            let errno = unsafe { *libc::__errno_location() };
+            return Err(format!(
+                "failed to open '{}': {} (errno {})",
+                path,
+                errno_to_str(errno),
+                errno
+            ));
+        }
+        Ok(UfsDevice { fd })
+    }
+
+    /// READ DESCRIPTOR query. Returns the descriptor as long as the device reports it.
+    pub fn read_descriptor(&self, idn: u8, index: u8) -> Result<Vec<u8>, String> {
+        let mut request = UfsBsgRequest {
+            msgcode: UPIU_TRANSACTION_QUERY_REQ as u32,
+            header: [0; 12],
+            query: [0; 20],
+        };
+        request.header[0] = UPIU_TRANSACTION_QUERY_REQ;
+        request.header[5] = UPIU_QUERY_FUNC_STANDARD_READ_REQUEST;
+        request.query[0] = QUERY_OPCODE_READ_DESC;
+        request.query[1] = idn;
+        request.query[2] = index;
+        request.query[6..8].copy_from_slice(&(QUERY_DESC_MAX_SIZE as u16).to_be_bytes());
+        let mut reply = UfsBsgReply {
+            result: 0,
+            reply_payload_rcv_len: 0,
+            header: [0; 12],
+            query: [0; 20],
+        };
+        let mut buf = [0u8; QUERY_DESC_MAX_SIZE];
+
+        let mut v4 = SgIoV4 {
+            guard: b'Q' as i32,
+            subprotocol: BSG_SUB_PROTOCOL_SCSI_TRANSPORT,
+            request_len: std::mem::size_of::<UfsBsgRequest>() as u32,
+            request: &request as *const UfsBsgRequest as u64,
+            max_response_len: std::mem::size_of::<UfsBsgReply>() as u32,
+            response: &mut reply as *mut UfsBsgReply as u64,
+            din_xfer_len: buf.len() as u32,
+            din_xferp: buf.as_mut_ptr() as u64,
+            timeout: QUERY_TIMEOUT_MS,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let ret = unsafe { libc::ioctl(self.fd, SG_IO, &mut v4 as *mut SgIoV4) };
+        let errno = if ret < 0 { unsafe { *libc::__errno_location() } } else { 0 };
+        let elapsed = start.elapsed();
+
+        let response = reply.header[6];
+        let len = (u16::from_be_bytes([reply.query[6], reply.query[7]]) as usize).min(buf.len());
+        let outcome = if errno == libc::ETIMEDOUT {
+            Err(format!("ufs query timed out (descriptor 0x{:02x})", idn))
+        } else if errno != 0 {
+            Err(format!(
+                "ufs-bsg ioctl failed: {} (errno {}, descriptor 0x{:02x})",
+                errno_to_str(errno),
+                errno,
+                idn
+            ))
+        } else if reply.result != 0 || response != 0 {
+            Err(format!(
+                "ufs query for descriptor 0x{:02x} index {} failed: {} (result {})",
+                idn,
+                index,
+                query_response_name(response),
+                reply.result
+            ))
+        } else {
+            Ok(buf[..len].to_vec())
+        };
+
+        crate::trace::record(&crate::trace::CommandRecord {
+            tag: format!("ufs-desc{:02x}", idn),
+            desc: format!("ufs query read descriptor idn=0x{:02x} index={}", idn, index),
+            vendor: false,
+            duration: elapsed,
+            status: if ret < 0 {
+                format!("errno {}", errno)
+            } else {
+                format!("result {}, query response 0x{:02x}", reply.result, response)
+            },
+            error: outcome.as_ref().err().map(|e| e.as_str()),
+            data_out: None,
+            data_in: outcome.as_ref().ok().map(|d| d.as_slice()),
+            sense: None,
+        });
+        outcome
+    }
+
+    /// String descriptor `index`: UTF-16BE after the two header bytes.
+    pub fn read_string(&self, index: u8) -> Result<String, String> {
+        let desc = self.read_descriptor(DESC_STRING, index)?;
+        Ok(parse_string_descriptor(&desc))
+    }
+}
+
+impl Drop for UfsDevice {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+pub fn parse_string_descriptor(desc: &[u8]) -> String {
+    let len = desc.first().map_or(0, |&l| l as usize).min(desc.len());
+    let units: Vec<u16> = desc
+        .get(2..len)
+        .unwrap_or_default()
+        .chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&units).trim_end_matches('\0').trim().to_string()
+}
+
+/// The fields of the device descriptor the report uses (JESD220 14.1.4.2).
+pub struct DeviceDescriptor {
+    pub spec_version: u16,
+    pub i_manufacturer: u8,
+    pub i_product: u8,
+    pub i_serial: u8,
+    pub manufacturer_id: u16,
+    pub i_product_revision: Option<u8>,
+}
+
+pub fn parse_device_descriptor(desc: &[u8]) -> Result<DeviceDescriptor, String> {
+    if desc.len() < 0x1A || desc[1] != DESC_DEVICE {
+        return Err(format!("device descriptor too short or invalid ({} bytes)", desc.len()));
+    }
+    let be16 = |off: usize| u16::from_be_bytes([desc[off], desc[off + 1]]);
+    Ok(DeviceDescriptor {
+        spec_version: be16(0x10),
+        i_manufacturer: desc[0x14],
+        i_product: desc[0x15],
+        i_serial: desc[0x16],
+        manufacturer_id: be16(0x18),
+        // UFS 2.0 and later
+        i_product_revision: desc.get(0x2A).copied(),
+    })
+}
+
+pub struct UfsInfo {
+    pub model: String,
+    pub firmware: String,
+    pub serial: String,
+    pub vendor: String,
+    pub manufacturer_id: u16,
+    pub spec_version: u16,
+    /// Raw capacity in bytes, from the geometry descriptor
+    pub capacity: Option<u64>,
+    /// bDeviceLifeTimeEstA/B, 10% steps like eMMC
+    pub life_time: Option<(u8, u8)>,
+}
+
+/// Device descriptor and its strings, plus geometry and health when the device has them.
+pub fn identify(dev: &UfsDevice) -> Result<UfsInfo, String> {
+    let desc = parse_device_descriptor(&dev.read_descriptor(DESC_DEVICE, 0)?)?;
+    let string = |index: u8| dev.read_string(index).unwrap_or_default();
+    let capacity = dev
+        .read_descriptor(DESC_GEOMETRY, 0)
+        .ok()
+        .and_then(|g| Some(u64::from_be_bytes(g.get(0x04..0x0C)?.try_into().ok()?) * 512));
+    let life_time = dev
+        .read_descriptor(DESC_HEALTH, 0)
+        .ok()
+        .and_then(|h| Some((*h.get(3)?, *h.get(4)?)));
+    Ok(UfsInfo {
+        model: string(desc.i_product),
+        firmware: desc.i_product_revision.map(string).unwrap_or_default(),
+        serial: string(desc.i_serial),
+        vendor: string(desc.i_manufacturer),
+        manufacturer_id: desc.manufacturer_id,
+        spec_version: desc.spec_version,
+        capacity,
+        life_time,
+    })
+}
+
+/// "3.1" for wSpecVersion 0x0310.
+pub fn spec_version(bcd: u16) -> String {
+    format!("{}.{}", bcd >> 8, (bcd >> 4) & 0x0F)
+}
+
+/// wManufacturerID: JEP106 bank (high byte) and code (low byte).
+pub fn manufacturer_name(id: u16) -> &'static str {
+    match id {
+        0x01CE => "Samsung",
+        0x01AD => "SK hynix",
+        0x0198 => "Kioxia/Toshiba",
+        0x012C => "Micron",
+        0x0145 => "SanDisk/WD",
+        _ => "unknown",
+    }
+}
+
+/// (offset, size, name) of the descriptor fields shown in the dump
+type Field = (usize, usize, &'static str);
+
+const DEVICE_FIELDS: &[Field] = &[
+    (0x02, 1, "bDevice"),
+    (0x03, 1, "bDeviceClass"),
+    (0x04, 1, "bDeviceSubClass"),
+    (0x05, 1, "bProtocol"),
+    (0x06, 1, "bNumberLU"),
+    (0x07, 1, "bNumberWLU"),
+    (0x08, 1, "bBootEnable"),
+    (0x09, 1, "bDescrAccessEn"),
+    (0x0A, 1, "bInitPowerMode"),
+    (0x0B, 1, "bHighPriorityLUN"),
+    (0x0C, 1, "bSecureRemovalType"),
+    (0x0D, 1, "bSecurityLU"),
+    (0x0E, 1, "bBackgroundOpsTermLat"),
+    (0x0F, 1, "bInitActiveICCLevel"),
+    (0x10, 2, "wSpecVersion"),
+    (0x12, 2, "wManufactureDate"),
+    (0x14, 1, "iManufacturerName"),
+    (0x15, 1, "iProductName"),
+    (0x16, 1, "iSerialNumber"),
+    (0x17, 1, "iOemID"),
+    (0x18, 2, "wManufacturerID"),
+    (0x1A, 1, "bUD0BaseOffset"),
+    (0x1B, 1, "bUDConfigPLength"),
+    (0x1C, 1, "bDeviceRTTCap"),
+    (0x1D, 2, "wPeriodicRTCUpdate"),
+    (0x1F, 1, "bUFSFeaturesSupport"),
+    (0x20, 1, "bFFUTimeout"),
+    (0x21, 1, "bQueueDepth"),
+    (0x22, 2, "wDeviceVersion"),
+    (0x24, 1, "bNumSecureWPArea"),
+    (0x25, 4, "dPSAMaxDataSize"),
+    (0x29, 1, "bPSAStateTimeout"),
+    (0x2A, 1, "iProductRevisionLevel"),
+];
+
+const GEOMETRY_FIELDS: &[Field] = &[
+    (0x02, 1, "bMediaTechnology"),
+    (0x04, 8, "qTotalRawDeviceCapacity"),
+    (0x0C, 1, "bMaxNumberLU"),
+    (0x0D, 4, "dSegmentSize"),
+    (0x11, 1, "bAllocationUnitSize"),
+    (0x12, 1, "bMinAddrBlockSize"),
+    (0x13, 1, "bOptimalReadBlockSize"),
+    (0x14, 1, "bOptimalWriteBlockSize"),
+    (0x15, 1, "bMaxInBufferSize"),
+    (0x16, 1, "bMaxOutBufferSize"),
+    (0x17, 1, "bRPMB_ReadWriteSize"),
+];
+
+const HEALTH_FIELDS: &[Field] = &[
+    (0x02, 1, "bPreEOLInfo"),
+    (0x03, 1, "bDeviceLifeTimeEstA"),
+    (0x04, 1, "bDeviceLifeTimeEstB"),
+];
+
+/// One "name = 0x.." line per field present in `desc` (descriptors shrink on older
+/// devices and grow on newer ones).
+pub fn format_descriptor(fields: &[Field], desc: &[u8]) -> String {
+    let mut out = String::new();
+    for &(off, size, name) in fields {
+        let Some(bytes) = desc.get(off..off + size) else { break };
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        out.push_str(&format!("  {:<24} = 0x{}\n", name, hex));
+    }
+    out
+}
+
+/// Structured dump of the device, geometry and health descriptors and the device
+/// strings, for devices no vendor reader supports.
+pub fn dump_descriptors(dev: &UfsDevice) -> String {
+    let mut out = String::new();
+    for (idn, title, fields) in [
+        (DESC_DEVICE, "Device descriptor", DEVICE_FIELDS),
+        (DESC_GEOMETRY, "Geometry descriptor", GEOMETRY_FIELDS),
+        (DESC_HEALTH, "Health descriptor", HEALTH_FIELDS),
+    ] {
+        match dev.read_descriptor(idn, 0) {
+            Ok(desc) => {
+                out.push_str(&format!("{} ({} bytes):\n", title, desc.len()));
+                out.push_str(&format_descriptor(fields, &desc));
+                if idn == DESC_DEVICE
+                    && let Ok(d) = parse_device_descriptor(&desc)
+                {
+                    let strings = [
+                        ("manufacturer", Some(d.i_manufacturer)),
+                        ("product", Some(d.i_product)),
+                        ("serial", Some(d.i_serial)),
+                        ("revision", d.i_product_revision),
+                    ];
+                    for (name, index) in strings {
+                        if let Some(index) = index
+                            && let Ok(s) = dev.read_string(index)
+                        {
+                            out.push_str(&format!("  {:<24} = \"{}\"\n", name, s));
+                        }
+                    }
+                }
+            }
+            Err(e) => out.push_str(&format!("{}: {}\n", title, e)),
+        }
+    }
+    out
+}
+
+fn query_response_name(code: u8) -> &'static str {
+    match code {
+        0x00 => "success",
+        0xF6 => "parameter not readable",
+        0xF7 => "parameter not writeable",
+        0xF8 => "parameter already written",
+        0xF9 => "invalid length",
+        0xFA => "invalid value",
+        0xFB => "invalid selector",
+        0xFC => "invalid index",
+        0xFD => "invalid IDN",
+        0xFE => "invalid opcode",
+        0xFF => "general failure",
+        _ => "reserved response code",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bsg_layout() {
+        // <scsi/scsi_bsg_ufs.h>
+        assert_eq!(std::mem::size_of::<UfsBsgRequest>(), 36);
+        assert_eq!(std::mem::size_of::<UfsBsgReply>(), 40);
+    }
+
+    #[test]
+    fn test_parse_descriptors() {
+        let mut desc = vec![0u8; 0x59];
+        desc[0] = 0x59;
+        desc[0x10..0x12].copy_from_slice(&[0x03, 0x10]);
+        desc[0x15] = 0x02;
+        desc[0x18..0x1A].copy_from_slice(&[0x01, 0xCE]);
+        desc[0x2A] = 0x04;
+        let d = parse_device_descriptor(&desc).unwrap();
+        assert_eq!(spec_version(d.spec_version), "3.1");
+        assert_eq!(manufacturer_name(d.manufacturer_id), "Samsung");
+        assert_eq!((d.i_product, d.i_product_revision), (0x02, Some(0x04)));
+
+        let dump = format_descriptor(DEVICE_FIELDS, &desc[..0x12]);
+        assert!(dump.ends_with("  wSpecVersion             = 0x0310\n"));
+        assert!(!dump.contains("wManufactureDate"));
+
+        let s = [0x0A, 0x05, 0, b'K', 0, b'L', 0, b'U', 0, b'D'];
+        assert_eq!(parse_string_descriptor(&s), "KLUD");
+    }
+}
@@ -0,0 +1,309 @@
+use crate::nvme::{DataDir, NvmeAdminCmd};
+use crate::scsi::{self, SENSE_BUF_LEN, SG_DXFER_FROM_DEV, SG_DXFER_NONE, SG_DXFER_TO_DEV};
+
+const RTL_CDB_READ: u8 = 0xE4;
+const RTL_CDB_WRITE: u8 = 0xE5;
+const ASM_CDB: u8 = 0xE6;
+const JMS_CDB: u8 = 0xA1;
+
+// JMS583 CDB byte 1: admin command flag plus the phase of the three-step protocol
+const JMS_ADMIN: u8 = 0x80;
+const JMS_PROTO_COMMAND: u8 = 0x0;
+const JMS_PROTO_NON_DATA: u8 = 0x1;
+const JMS_PROTO_DMA_IN: u8 = 0x2;
+const JMS_PROTO_DMA_OUT: u8 = 0x3;
+const JMS_PROTO_RESPONSE: u8 = 0xF;
+/// "NVME" in little-endian, first dword of the command and response blocks
+const JMS_SIGNATURE: u32 = 0x454D564E;
+const JMS_BLOCK_LEN: usize = 512;
+
+/// USB-to-NVMe bridge whose vendor SCSI command tunnels NVMe admin commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NvmeBridge {
+    /// Realtek RTL9210/RTL9220: 0xE4 (read) / 0xE5 (write), opcode and CDW10[7:0] only
+    Rtl9210,
+    /// JMicron JMS583: 0xA1 command / data / response phases carrying the full command
+    Jms583,
+    /// ASMedia ASM2362/ASM2364: 0xE6, opcode and CDW10 bytes 0 and 2 only, data-in only
+    Asm2362,
+}
+
+/// Known bridge USB IDs (idVendor, idProduct).
+const USB_IDS: &[(u16, u16, NvmeBridge)] = &[
+    (0x0bda, 0x9210, NvmeBridge::Rtl9210),
+    (0x0bda, 0x9220, NvmeBridge::Rtl9210),
+    (0x152d, 0x0583, NvmeBridge::Jms583),
+    (0x174c, 0x2362, NvmeBridge::Asm2362),
+];
+
+/// Fallback for enclosures with a rebranded USB ID: substrings of the SCSI INQUIRY
+/// product (or vendor) the bridge firmware reports.
+const INQUIRY_IDS: &[(&str, NvmeBridge)] = &[
+    ("RTL9210", NvmeBridge::Rtl9210),
+    ("RTL9220", NvmeBridge::Rtl9210),
+    ("JMS583", NvmeBridge::Jms583),
+    ("ASM236", NvmeBridge::Asm2362),
+];
+
+pub const BRIDGE_NAMES: &str = "rtl9210, jms583, asm2362, none";
+
+impl NvmeBridge {
+    pub fn name(self) -> &'static str {
+        match self {
+            NvmeBridge::Rtl9210 => "rtl9210",
+            NvmeBridge::Jms583 => "jms583",
+            NvmeBridge::Asm2362 => "asm2362",
+        }
+    }
+
+    pub fn display(self) -> &'static str {
+        match self {
+            NvmeBridge::Rtl9210 => "Realtek RTL9210/RTL9220",
+            NvmeBridge::Jms583 => "JMicron JMS583",
+            NvmeBridge::Asm2362 => "ASMedia ASM2362/ASM2364",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [NvmeBridge::Rtl9210, NvmeBridge::Jms583, NvmeBridge::Asm2362]
+            .into_iter()
+            .find(|b| b.name() == name)
+    }
+
+    /// Refuses commands the tunnel can't express instead of sending them with fields
+    /// silently dropped, which for a vendor command could mean a different command.
+    pub fn check(self, cmd: &NvmeAdminCmd, dir: DataDir) -> Result<(), String> {
+        let unsupported = |what: &str| {
+            Err(format!(
+                "the {} tunnel cannot carry {} (opcode 0x{:02x})",
+                self.display(),
+                what,
+                cmd.opcode
+            ))
+        };
+        let extra_cdws = [cmd.cdw11, cmd.cdw12, cmd.cdw13, cmd.cdw14, cmd.cdw15];
+        match self {
+            NvmeBridge::Jms583 => Ok(()),
+            NvmeBridge::Rtl9210 if cmd.cdw10 > 0xFF => unsupported("CDW10 above bit 7"),
+            NvmeBridge::Rtl9210 if extra_cdws.iter().any(|&c| c != 0) => unsupported("CDW11-15"),
+            NvmeBridge::Rtl9210 if cmd.data_len > 0xFFFF => unsupported("more than 64 KiB"),
+            NvmeBridge::Rtl9210 => Ok(()),
+            NvmeBridge::Asm2362 if cmd.cdw10 & 0xFF00_FF00 != 0 => {
+                unsupported("CDW10 bytes 1 and 3")
+            }
+            NvmeBridge::Asm2362 if extra_cdws.iter().any(|&c| c != 0) => unsupported("CDW11-15"),
+            NvmeBridge::Asm2362 if dir == DataDir::ToDevice => unsupported("data-out commands"),
+            NvmeBridge::Asm2362 => Ok(()),
+        }
+    }
+
+    /// Sends an admin command through the bridge. `cmd.addr`/`cmd.data_len` describe the
+    /// data buffer as for the native ioctl.
+    pub fn submit(self, fd: i32, cmd: &NvmeAdminCmd, dir: DataDir) -> Result<Completion, BridgeError> {
+        let (direction, data) = match dir {
+            DataDir::None => (SG_DXFER_NONE, std::ptr::null_mut()),
+            DataDir::ToDevice => (SG_DXFER_TO_DEV, cmd.addr as *mut u8),
+            DataDir::FromDevice => (SG_DXFER_FROM_DEV, cmd.addr as *mut u8),
+        };
+        let len = cmd.data_len as usize;
+        match self {
+            NvmeBridge::Rtl9210 | NvmeBridge::Asm2362 => {
+                send_phase(fd, &self.cdb(cmd, dir), direction, data, len, cmd.timeout_ms)?;
+                // Neither bridge returns the completion entry; a rejected command fails
+                // at the SCSI level instead
+                Ok(Completion { status: 0, result: 0 })
+            }
+            NvmeBridge::Jms583 => {
+                let mut block = jms_command_block(cmd);
+                let cdb = jms_cdb(JMS_PROTO_COMMAND, JMS_BLOCK_LEN);
+                send_phase(fd, &cdb, SG_DXFER_TO_DEV, block.as_mut_ptr(), block.len(), cmd.timeout_ms)?;
+
+                let proto = match dir {
+                    DataDir::None => JMS_PROTO_NON_DATA,
+                    DataDir::ToDevice => JMS_PROTO_DMA_OUT,
+                    DataDir::FromDevice => JMS_PROTO_DMA_IN,
+                };
+                send_phase(fd, &jms_cdb(proto, len), direction, data, len, cmd.timeout_ms)?;
+
+                let mut reply = [0u8; JMS_BLOCK_LEN];
+                let cdb = jms_cdb(JMS_PROTO_RESPONSE, JMS_BLOCK_LEN);
+                send_phase(fd, &cdb, SG_DXFER_FROM_DEV, reply.as_mut_ptr(), reply.len(), cmd.timeout_ms)?;
+                jms_completion(&reply)
+            }
+        }
+    }
+
+    /// The single CDB of the RTL9210 and ASM2362 tunnels.
+    fn cdb(self, cmd: &NvmeAdminCmd, dir: DataDir) -> [u8; 16] {
+        let mut cdb = [0u8; 16];
+        match self {
+            NvmeBridge::Rtl9210 => {
+                cdb[0] = if dir == DataDir::ToDevice { RTL_CDB_WRITE } else { RTL_CDB_READ };
+                cdb[1..3].copy_from_slice(&(cmd.data_len as u16).to_le_bytes());
+                cdb[3] = cmd.opcode;
+                cdb[4] = cmd.cdw10 as u8;
+            }
+            NvmeBridge::Asm2362 => {
+                cdb[0] = ASM_CDB;
+                cdb[1] = cmd.opcode;
+                cdb[3] = cmd.cdw10 as u8;
+                cdb[7] = (cmd.cdw10 >> 16) as u8;
+            }
+            NvmeBridge::Jms583 => unreachable!("JMS583 uses a multi-phase protocol"),
+        }
+        cdb
+    }
+}
+
+/// Completion of a tunneled command. Bridges that don't return the completion entry
+/// report status 0 and result 0 for any command the bridge accepted.
+pub struct Completion {
+    /// NVMe status field (SCT << 8 | SC)
+    pub status: u16,
+    pub result: u32,
+}
+
+pub enum BridgeError {
+    TimedOut,
+    Failed(String),
+}
+
+fn send_phase(
+    fd: i32,
+    cdb: &[u8],
+    direction: i32,
+    data: *mut u8,
+    len: usize,
+    timeout_ms: u32,
+) -> Result<(), BridgeError> {
+    let mut sense = [0u8; SENSE_BUF_LEN as usize];
+    let (hdr, errno) = scsi::send(fd, cdb, direction, data, len, timeout_ms, &mut sense);
+    if errno == libc::ETIMEDOUT || (errno == 0 && hdr.timed_out()) {
+        return Err(BridgeError::TimedOut);
+    }
+    if errno != 0 {
+        return Err(BridgeError::Failed(format!(
+            "sg_io ioctl failed (errno {}, cdb 0x{:02x})",
+            errno, cdb[0]
+        )));
+    }
+    if hdr.status != 0 || hdr.host_status != 0 || hdr.driver_status & !0x08 != 0 {
+        let sense = scsi::decode_sense(&sense[..(hdr.sb_len_wr as usize).min(sense.len())])
+            .map(|sense| format!(", {}", sense.describe()))
+            .unwrap_or_default();
+        return Err(BridgeError::Failed(format!(
+            "bridge rejected cdb 0x{:02x}: {}{}",
+            cdb[0],
+            hdr.describe_status(),
+            sense
+        )));
+    }
+    Ok(())
+}
+
+fn jms_cdb(proto: u8, len: usize) -> [u8; 12] {
+    let mut cdb = [0u8; 12];
+    cdb[0] = JMS_CDB;
+    cdb[1] = JMS_ADMIN | proto;
+    cdb[3..6].copy_from_slice(&(len as u32).to_be_bytes()[1..]);
+    cdb
+}
+
+/// Command block of the JMS583 first phase. Dword layout: 0 signature, 6 opcode,
+/// 7 NSID, 10-15 CDW10-15.
+fn jms_command_block(cmd: &NvmeAdminCmd) -> [u8; JMS_BLOCK_LEN] {
+    let mut block = [0u8; JMS_BLOCK_LEN];
+    let mut put = |dword: usize, value: u32| {
+        block[dword * 4..dword * 4 + 4].copy_from_slice(&value.to_le_bytes());
+    };
+    put(0, JMS_SIGNATURE);
+    put(6, cmd.opcode as u32);
+    put(7, cmd.nsid);
+    for (i, cdw) in [cmd.cdw10, cmd.cdw11, cmd.cdw12, cmd.cdw13, cmd.cdw14, cmd.cdw15]
+        .into_iter()
+        .enumerate()
+    {
+        put(10 + i, cdw);
+    }
+    block
+}
+
+/// Response block of the JMS583 last phase: signature, then the completion queue entry
+/// at dwords 2-5 (DW0 result, DW3 status in bits 31:17).
+fn jms_completion(reply: &[u8; JMS_BLOCK_LEN]) -> Result<Completion, BridgeError> {
+    let dword = |i: usize| u32::from_le_bytes(reply[i * 4..i * 4 + 4].try_into().unwrap());
+    if dword(0) != JMS_SIGNATURE {
+        return Err(BridgeError::Failed(format!(
+            "JMS583 response has no NVME signature (0x{:08x})",
+            dword(0)
+        )));
+    }
+    Ok(Completion {
+        status: ((dword(5) >> 17) & 0x7FF) as u16,
+        result: dword(2),
+    })
+}
+
+/// Picks the bridge from the USB ID in sysfs, then from the INQUIRY strings.
+pub fn detect(dev_path: &str, inquiry: Option<&[u8; 96]>) -> Option<NvmeBridge> {
+    if let Some((vid, pid)) = crate::sysfs::usb_id(dev_path)
+        && let Some(&(_, _, bridge)) = USB_IDS.iter().find(|&&(v, p, _)| v == vid && p == pid)
+    {
+        return Some(bridge);
+    }
+    let inq = inquiry?;
+    let ident = String::from_utf8_lossy(&inq[8..32]).to_ascii_uppercase();
+    INQUIRY_IDS
+        .iter()
+        .find(|(needle, _)| ident.contains(needle))
+        .map(|&(_, bridge)| bridge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn admin(opcode: u8, cdw10: u32, cdw12: u32, len: u32) -> NvmeAdminCmd {
+        let mut cmd = NvmeAdminCmd::zeroed();
+        cmd.opcode = opcode;
+        cmd.cdw10 = cdw10;
+        cmd.cdw12 = cdw12;
+        cmd.data_len = len;
+        cmd
+    }
+
+    #[test]
+    fn test_single_cdb_tunnels() {
+        let identify = admin(0x06, 1, 0, 4096);
+        let rtl = NvmeBridge::Rtl9210.cdb(&identify, DataDir::FromDevice);
+        assert_eq!(rtl[..5], [0xE4, 0x00, 0x10, 0x06, 0x01]);
+        let log = admin(0x02, 0x007F_0002, 0, 512);
+        let asm = NvmeBridge::Asm2362.cdb(&log, DataDir::FromDevice);
+        assert_eq!((asm[0], asm[1], asm[3], asm[7]), (0xE6, 0x02, 0x02, 0x7F));
+
+        let vendor = admin(0xC2, 0x40, 0x1000, 512);
+        assert!(NvmeBridge::Rtl9210.check(&vendor, DataDir::FromDevice).is_err());
+        assert!(NvmeBridge::Asm2362.check(&vendor, DataDir::FromDevice).is_err());
+        assert!(NvmeBridge::Jms583.check(&vendor, DataDir::FromDevice).is_ok());
+        assert!(NvmeBridge::Asm2362.check(&identify, DataDir::ToDevice).is_err());
+    }
+
+    #[test]
+    fn test_jms583_blocks() {
+        let mut cmd = admin(0xC2, 0x40, 0x1000, 512);
+        cmd.nsid = 1;
+        let block = jms_command_block(&cmd);
+        assert_eq!(block[..4], *b"NVME");
+        assert_eq!((block[24], block[28], block[40], block[48]), (0xC2, 1, 0x40, 0x00));
+        assert_eq!(block[49], 0x10);
+        assert_eq!(jms_cdb(JMS_PROTO_DMA_IN, 4096), [0xA1, 0x82, 0, 0, 0x10, 0, 0, 0, 0, 0, 0, 0]);
+
+        let mut reply = [0u8; JMS_BLOCK_LEN];
+        reply[..4].copy_from_slice(b"NVME");
+        reply[8] = 0x5A;
+        reply[20..24].copy_from_slice(&(0x0002u32 << 17).to_le_bytes());
+        let c = jms_completion(&reply).ok().unwrap();
+        assert_eq!((c.status, c.result), (0x0002, 0x5A));
+        assert!(jms_completion(&[0u8; JMS_BLOCK_LEN]).is_err());
+    }
+}
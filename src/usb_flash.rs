@@ -0,0 +1,82 @@
+/// Controller vendors that ship sticks under their own USB vendor ID.
+const USB_VENDORS: &[(u16, &str)] = &[
+    (0x13fe, "phison-usb"),
+    (0x090c, "smi-usb"),
+    (0x058f, "alcor-usb"),
+    (0x1e3d, "chipsbank-usb"),
+];
+
+/// Rebranded sticks whose controller is known from the exact USB ID (idVendor, idProduct).
+const USB_IDS: &[(u16, u16, &str)] = &[
+    (0x0951, 0x1643, "phison-usb"), // Kingston DataTraveler G3
+    (0x0951, 0x1666, "phison-usb"), // Kingston DataTraveler 100 G3
+    (0x0781, 0x5571, "smi-usb"),    // SanDisk Cruzer Fit (older revisions)
+];
+
+/// Fallback: the SCSI INQUIRY vendor field, which many controllers fill with their own name
+/// when the stick vendor didn't customize it.
+const INQUIRY_VENDORS: &[(&str, &str)] = &[
+    ("PHISON", "phison-usb"),
+    ("SMI", "smi-usb"),
+    ("ALCOR", "alcor-usb"),
+    ("CHIPSBNK", "chipsbank-usb"),
+];
+
+/// Picks the USB flash drive family from the USB ID, then from the INQUIRY vendor.
+pub fn detect(usb_id: Option<(u16, u16)>, inquiry: Option<&[u8; 96]>) -> Option<&'static str> {
+    if let Some((vid, pid)) = usb_id {
+        if let Some(&(_, _, family)) = USB_IDS.iter().find(|&&(v, p, _)| v == vid && p == pid) {
+            return Some(family);
+        }
+        if let Some(&(_, family)) = USB_VENDORS.iter().find(|&&(v, _)| v == vid) {
+            return Some(family);
+        }
+    }
+    let vendor = inquiry_strings(inquiry?).0.to_ascii_uppercase();
+    INQUIRY_VENDORS
+        .iter()
+        .find(|(name, _)| vendor == *name)
+        .map(|&(_, family)| family)
+}
+
+/// Vendor, product and revision of a standard INQUIRY response.
+pub fn inquiry_strings(inq: &[u8; 96]) -> (String, String, String) {
+    let field = |range: std::ops::Range<usize>| String::from_utf8_lossy(&inq[range]).trim().to_string();
+    (field(8..16), field(16..32), field(32..36))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inquiry(vendor: &str, product: &str) -> [u8; 96] {
+        let mut inq = [b' '; 96];
+        inq[8..8 + vendor.len()].copy_from_slice(vendor.as_bytes());
+        inq[16..16 + product.len()].copy_from_slice(product.as_bytes());
+        inq[32..36].copy_from_slice(b"1100");
+        inq
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(Some((0x13fe, 0x4200)), None), Some("phison-usb"));
+        assert_eq!(detect(Some((0x0951, 0x1666)), None), Some("phison-usb"));
+        // Unknown Kingston PID: no guess from the vendor alone
+        assert_eq!(detect(Some((0x0951, 0x16a5)), None), None);
+        // Innostor is only read with --controller innostor-usb
+        let inq = inquiry("INNOSTOR", "USB DISK");
+        assert_eq!(detect(Some((0x1f75, 0x0917)), Some(&inq)), None);
+
+        let inq = inquiry("ChipsBnk", "Flash Disk");
+        assert_eq!(detect(Some((0x1234, 0x5678)), Some(&inq)), Some("chipsbank-usb"));
+        let inq = inquiry("SMI", "USB DISK");
+        assert_eq!(detect(None, Some(&inq)), Some("smi-usb"));
+        // Substrings don't count: "SMILE" is not SMI
+        let inq = inquiry("SMILE", "USB DISK");
+        assert_eq!(detect(None, Some(&inq)), None);
+        assert_eq!(
+            inquiry_strings(&inq),
+            ("SMILE".to_string(), "USB DISK".to_string(), "1100".to_string())
+        );
+    }
+}